@@ -1,9 +1,14 @@
-use crate::connector::GeoConnections;
+use crate::connector::{GeoConnections, TileCache};
 use crate::data::Database;
+use crate::job::IngestQueue;
+use crate::TileAuthCache;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub app_data: Arc<dyn Database>,
     pub geo_connections: GeoConnections,
+    pub tile_auth_cache: TileAuthCache,
+    pub tile_cache: TileCache,
+    pub ingest_queue: IngestQueue,
 }
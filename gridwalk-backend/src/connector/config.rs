@@ -0,0 +1,116 @@
+use crate::connector::{
+    DuckDbConnection, DuckDbConnector, GeoConnector, GeoPackageConnection, GeoPackageConnector,
+    PostgisConnector, PostgresConnection, TileArchiveConnection, TileArchiveConnector,
+};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+// Settings for each kind of connector, tagged with the connector type
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConnectorConfig {
+    Postgis(PostgresConnection),
+    #[serde(rename = "geopackage")]
+    GeoPackage(GeoPackageConnection),
+    // A directory of MBTiles and PMTiles files
+    TileArchive(TileArchiveConnection),
+    // A directory of GeoParquet and Parquet files queried with DuckDB
+    #[serde(rename = "duckdb")]
+    DuckDb(DuckDbConnection),
+}
+
+impl ConnectorConfig {
+    pub fn connector_type(&self) -> &'static str {
+        match self {
+            ConnectorConfig::Postgis(_) => "postgis",
+            ConnectorConfig::GeoPackage(_) => "geopackage",
+            ConnectorConfig::TileArchive(_) => "tile_archive",
+            ConnectorConfig::DuckDb(_) => "duckdb",
+        }
+    }
+
+    // Build the connector the config describes
+    pub fn create_connector(&self) -> Result<Arc<dyn GeoConnector>> {
+        match self {
+            ConnectorConfig::Postgis(config) => {
+                Ok(Arc::new(PostgisConnector::new(config.clone())?))
+            }
+            ConnectorConfig::GeoPackage(config) => {
+                Ok(Arc::new(GeoPackageConnector::new(config.clone())?))
+            }
+            ConnectorConfig::TileArchive(config) => {
+                Ok(Arc::new(TileArchiveConnector::new(config.clone())?))
+            }
+            ConnectorConfig::DuckDb(config) => Ok(Arc::new(DuckDbConnector::new(config.clone())?)),
+        }
+    }
+
+    // Postgres connection details, for connectors backed by a Postgres database
+    pub fn postgres(&self) -> Option<&PostgresConnection> {
+        match self {
+            ConnectorConfig::Postgis(config) => Some(config),
+            _ => None,
+        }
+    }
+
+    // Config as stored by the app databases. Records saved before connector
+    // configs were tagged hold the Postgres connection details only.
+    pub fn from_stored(connector_type: &str, value: serde_json::Value) -> Result<Self> {
+        if value.get("type").is_some() {
+            return serde_json::from_value(value)
+                .map_err(|e| anyhow!("Invalid {} connection config: {}", connector_type, e));
+        }
+        match connector_type.to_lowercase().as_str() {
+            "postgis" | "postgres" => Ok(ConnectorConfig::Postgis(serde_json::from_value(value)?)),
+            _ => Err(anyhow!("Unknown connector type: {}", connector_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn legacy_postgres() -> serde_json::Value {
+        json!({
+            "host": "db",
+            "port": 5432,
+            "database": "gis",
+            "username": "gw",
+            "password": "secret",
+            "schema": null
+        })
+    }
+
+    #[test]
+    fn from_stored_reads_untagged_postgres_records() {
+        for connector_type in ["postgis", "Postgres"] {
+            let config = ConnectorConfig::from_stored(connector_type, legacy_postgres()).unwrap();
+            assert_eq!(config.connector_type(), "postgis");
+            assert_eq!(config.postgres().unwrap().database, "gis");
+        }
+    }
+
+    #[test]
+    fn from_stored_reads_tagged_records() {
+        let config = ConnectorConfig::from_stored(
+            "tile_archive",
+            json!({ "type": "tile_archive", "directory": "/data/tiles" }),
+        )
+        .unwrap();
+        assert_eq!(config.connector_type(), "tile_archive");
+
+        let round_trip =
+            ConnectorConfig::from_stored("geopackage", serde_json::to_value(&config).unwrap())
+                .unwrap();
+        assert_eq!(round_trip.connector_type(), "tile_archive");
+    }
+
+    #[test]
+    fn from_stored_rejects_unknown_untagged_records() {
+        assert!(ConnectorConfig::from_stored("geopackage", json!({ "path": "a.gpkg" })).is_err());
+        assert!(ConnectorConfig::from_stored("postgis", json!({ "host": "db" })).is_err());
+    }
+}
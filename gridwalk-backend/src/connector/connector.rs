@@ -4,20 +4,22 @@ use deadpool_postgres::{Config, Pool, Runtime};
 use native_tls;
 use postgres_native_tls::MakeTlsConnector;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio_postgres::NoTls;
+use url::Url;
 
-use crate::{data::Database, Workspace};
+use crate::connector::{export_file_stem, export_postgis_query, ConnectorConfig, ExportOptions};
+use crate::{data::Database, LayerMode, Workspace};
 
-// TODO: Switch connector_type/postgis_uri to enum to support other connectors
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Connection {
     pub id: String,
     pub name: String,
-    pub connector_type: String,
-    pub config: PostgresConnection,
+    pub config: ConnectorConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -32,6 +34,10 @@ pub enum GeometryType {
 }
 
 impl Connection {
+    pub fn connector_type(&self) -> &'static str {
+        self.config.connector_type()
+    }
+
     pub async fn create_record(self, database: &Arc<dyn Database>) -> Result<()> {
         database.create_connection(&self).await?;
         Ok(())
@@ -42,6 +48,10 @@ impl Connection {
         Ok(con)
     }
 
+    pub async fn get_all(database: &Arc<dyn Database>, wsp: &Workspace) -> Result<Vec<Self>> {
+        database.get_workspace_connections(wsp).await
+    }
+
     //pub async fn delete(&self, database: &Arc<dyn Database>) -> Result<()> {
     //    database.delete_workspace_connection(self).await
     //}
@@ -86,6 +96,18 @@ impl ConnectionAccessConfig {
             | ConnectionAccessConfig::ReadOnly(v) => v,
         }
     }
+
+    // Access is granted per namespace, so the path must match the one requested
+    pub fn allows_namespace(&self, namespace: &str) -> bool {
+        self.path() == namespace
+    }
+
+    pub fn allows_write(&self) -> bool {
+        matches!(
+            self,
+            ConnectionAccessConfig::Admin(_) | ConnectionAccessConfig::ReadWrite(_)
+        )
+    }
 }
 
 impl ConnectionAccess {
@@ -112,9 +134,14 @@ impl ConnectionAccess {
 pub trait GeoConnector: Send + Sync {
     async fn connect(&mut self) -> Result<()>;
     async fn get_geometry_type(&self, namespace: &str, source_name: &str) -> Result<GeometryType>;
+    // Attribute columns that can be carried in a vector tile
+    async fn get_fields(&self, namespace: &str, source_name: &str) -> Result<Vec<SourceField>>;
+    // Extent of the source in EPSG:4326 as [west, south, east, north], None if empty
+    async fn get_bounds(&self, namespace: &str, source_name: &str) -> Result<Option<[f64; 4]>>;
     async fn disconnect(&mut self) -> Result<()>;
     async fn create_namespace(&self, name: &str) -> Result<()>;
     async fn list_sources(&self, namespace: &str) -> Result<Vec<String>>;
+    async fn delete_source(&self, namespace: &str, source_name: &str) -> Result<()>;
     async fn get_tile(
         &self,
         namespace: &str,
@@ -122,7 +149,507 @@ pub trait GeoConnector: Send + Sync {
         z: u32,
         x: u32,
         y: u32,
+        fields: Option<&[String]>,
     ) -> Result<Vec<u8>>;
+    // Zoom range and layers of a source whose tiles are pre-rendered, None when
+    // tiles are rendered from the source on request
+    async fn get_tile_set(&self, _namespace: &str, _source_name: &str) -> Result<Option<TileSet>> {
+        Ok(None)
+    }
+    async fn query_features(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        query: &FeatureQuery,
+    ) -> Result<FeaturePage>;
+    // Number of features in the source
+    async fn count_source(&self, namespace: &str, source_name: &str) -> Result<u64>;
+    // Write the source to a file in output_dir and return its path
+    async fn export_source(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        options: &ExportOptions,
+        output_dir: &Path,
+    ) -> Result<PathBuf>;
+    // The methods below load uploaded layers. Connections that cannot store
+    // uploads keep the default, which fails.
+
+    // Set the CRS of a newly loaded source and reproject it if asked
+    async fn set_source_crs(
+        &self,
+        _namespace: &str,
+        _source_name: &str,
+        _options: &CrsOptions,
+    ) -> Result<AppliedCrs> {
+        Err(anyhow!("This connection cannot load layers"))
+    }
+    // Report on the geometries of a newly loaded source, repairing invalid ones
+    // or splitting it into one source per geometry type if asked
+    async fn validate_source(
+        &self,
+        _namespace: &str,
+        _source_name: &str,
+        _options: &ValidationOptions,
+    ) -> Result<ValidationReport> {
+        Err(anyhow!("This connection cannot load layers"))
+    }
+    // Make a staged source the named source: renamed into place on create, swapped
+    // for the existing source on replace, or added to it on append
+    async fn commit_staged_source(
+        &self,
+        _namespace: &str,
+        _staging_name: &str,
+        _source_name: &str,
+        _mode: LayerMode,
+    ) -> Result<()> {
+        Err(anyhow!("This connection cannot load layers"))
+    }
+}
+
+// How to treat the CRS of a newly loaded source. `declared` was given by the
+// user and overrides the SRID the loader stored, while `detected` was read from
+// the file and only fills in for data loaded without one. `storage` reprojects
+// the data into another SRID.
+#[derive(Debug, Clone, Default)]
+pub struct CrsOptions {
+    pub declared: Option<i32>,
+    pub detected: Option<i32>,
+    pub storage: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct AppliedCrs {
+    // CRS of the uploaded data
+    pub source_srid: i32,
+    // CRS the data is stored in
+    pub storage_srid: i32,
+}
+
+// What to do about problems found when a source is validated
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct ValidationOptions {
+    // Replace invalid geometries with ST_MakeValid
+    #[serde(default)]
+    pub repair: bool,
+    // Copy each geometry type of a mixed source into a source of its own
+    #[serde(default)]
+    pub split_by_type: bool,
+    // Names split sources must not take, e.g. those of existing layers
+    #[serde(skip)]
+    pub reserved_names: Vec<String>,
+    // Name split sources are named after when it is not the validated source's,
+    // e.g. when validating a staging table
+    #[serde(skip)]
+    pub split_base: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ValidationReport {
+    pub feature_count: u64,
+    // Features per geometry type, e.g. MultiPolygon
+    pub geometry_types: BTreeMap<String, u64>,
+    pub null_count: u64,
+    pub empty_count: u64,
+    // Invalid geometries found, before any repair
+    pub invalid_count: u64,
+    pub repaired_count: u64,
+    // Extent in EPSG:4326 as [west, south, east, north]
+    pub extent: Option<[f64; 4]>,
+    // Sources created by splitting a mixed source
+    #[serde(default)]
+    pub split_sources: Vec<String>,
+}
+
+impl ValidationReport {
+    // Geometry types grouped as points, lines, polygons and collections
+    pub fn geometry_families(&self) -> Vec<&'static str> {
+        let mut families: Vec<&'static str> = self
+            .geometry_types
+            .keys()
+            .map(|t| geometry_family(t))
+            .collect();
+        families.sort();
+        families.dedup();
+        families
+    }
+
+    // Add the report of rows appended to a source to the report of the source
+    pub fn merge(&mut self, appended: &ValidationReport) {
+        self.feature_count += appended.feature_count;
+        for (geometry_type, count) in &appended.geometry_types {
+            *self
+                .geometry_types
+                .entry(geometry_type.clone())
+                .or_default() += count;
+        }
+        self.null_count += appended.null_count;
+        self.empty_count += appended.empty_count;
+        self.invalid_count += appended.invalid_count;
+        self.repaired_count += appended.repaired_count;
+        self.extent = match (self.extent, appended.extent) {
+            (Some(a), Some(b)) => Some([
+                a[0].min(b[0]),
+                a[1].min(b[1]),
+                a[2].max(b[2]),
+                a[3].max(b[3]),
+            ]),
+            (a, b) => a.or(b),
+        };
+        self.split_sources
+            .extend(appended.split_sources.iter().cloned());
+    }
+}
+
+fn geometry_family(geometry_type: &str) -> &'static str {
+    match geometry_type {
+        "Point" | "MultiPoint" => "point",
+        "LineString" | "MultiLineString" => "line",
+        "Polygon" | "MultiPolygon" => "polygon",
+        _ => "collection",
+    }
+}
+
+pub const DEFAULT_FEATURE_LIMIT: i64 = 100;
+pub const MAX_FEATURE_LIMIT: i64 = 10_000;
+
+// Filters and paging for a feature query. The bbox is always given in EPSG:4326,
+// features are returned in the crs SRID.
+#[derive(Debug, Clone)]
+pub struct FeatureQuery {
+    pub bbox: Option<[f64; 4]>,
+    // Attribute equality filters, compared as text
+    pub filters: Vec<(String, String)>,
+    // Attribute columns to return, None for all
+    pub properties: Option<Vec<String>>,
+    pub limit: i64,
+    pub offset: i64,
+    pub crs: i32,
+}
+
+impl Default for FeatureQuery {
+    fn default() -> Self {
+        FeatureQuery {
+            bbox: None,
+            filters: Vec::new(),
+            properties: None,
+            limit: DEFAULT_FEATURE_LIMIT,
+            offset: 0,
+            crs: 4326,
+        }
+    }
+}
+
+// One page of GeoJSON features and the total number matching the query
+#[derive(Debug, Clone)]
+pub struct FeaturePage {
+    pub features: Vec<serde_json::Value>,
+    pub number_matched: i64,
+}
+
+impl FeaturePage {
+    pub fn into_feature_collection(self) -> serde_json::Value {
+        let number_returned = self.features.len();
+        serde_json::json!({
+            "type": "FeatureCollection",
+            "numberMatched": self.number_matched,
+            "numberReturned": number_returned,
+            "features": self.features,
+        })
+    }
+}
+
+// Returned when an output CRS is not known to the connector
+#[derive(Debug)]
+pub struct UnsupportedCrsError {
+    pub srid: i32,
+}
+
+impl fmt::Display for UnsupportedCrsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unsupported crs: EPSG:{}", self.srid)
+    }
+}
+
+impl std::error::Error for UnsupportedCrsError {}
+
+// Returned when a request names attribute columns that the source does not have
+#[derive(Debug)]
+pub struct UnknownFieldsError {
+    pub fields: Vec<String>,
+}
+
+impl fmt::Display for UnknownFieldsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown fields: {}", self.fields.join(", "))
+    }
+}
+
+impl std::error::Error for UnknownFieldsError {}
+
+// Returned when a request names a source the connection does not have
+#[derive(Debug)]
+pub struct SourceNotFoundError {
+    pub source_name: String,
+}
+
+impl fmt::Display for SourceNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "source not found: {}", self.source_name)
+    }
+}
+
+impl std::error::Error for SourceNotFoundError {}
+
+// Value types of vector tile attributes, named as in TileJSON vector_layers
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FieldType {
+    String,
+    Number,
+    Boolean,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SourceField {
+    pub name: String,
+    pub field_type: FieldType,
+}
+
+// A layer of vector tiles as described in TileJSON
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VectorLayer {
+    pub id: String,
+    pub fields: BTreeMap<String, FieldType>,
+    pub minzoom: u32,
+    pub maxzoom: u32,
+}
+
+// The zoom range and layers of pre-rendered tiles, as far as the source records
+// them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TileSet {
+    pub minzoom: Option<u32>,
+    pub maxzoom: Option<u32>,
+    pub vector_layers: Vec<VectorLayer>,
+}
+
+// A column of a PostGIS table as described by information_schema.columns
+#[derive(Debug, Clone)]
+pub struct SourceColumn {
+    pub name: String,
+    pub udt_name: String,
+}
+
+impl SourceColumn {
+    pub fn is_geometry(&self) -> bool {
+        self.udt_name == "geometry" || self.udt_name == "geography"
+    }
+
+    // Type of the column once encoded in a tile, None if it is left out
+    pub fn field_type(&self) -> Option<FieldType> {
+        match self.udt_name.as_str() {
+            "int2" | "int4" | "int8" | "float4" | "float8" | "numeric" => Some(FieldType::Number),
+            "bool" => Some(FieldType::Boolean),
+            "text" | "varchar" | "bpchar" | "date" | "timestamp" | "timestamptz" | "time"
+            | "timetz" | "uuid" | "json" | "jsonb" | "name" => Some(FieldType::String),
+            _ => None,
+        }
+    }
+
+    // Select expression for the column as an MVT attribute. MVT values can only be
+    // strings, numbers or booleans, so other types are cast to one of those or
+    // skipped if there is no sensible representation.
+    pub fn mvt_attribute_expr(&self, table_alias: &str) -> Option<String> {
+        let column = format!("{}.{}", table_alias, quote_ident(&self.name));
+        match self.udt_name.as_str() {
+            "int2" | "int4" | "int8" | "float4" | "float8" | "bool" | "text" | "varchar"
+            | "bpchar" => Some(format!("{} AS {}", column, quote_ident(&self.name))),
+            "numeric" => Some(format!(
+                "{}::double precision AS {}",
+                column,
+                quote_ident(&self.name)
+            )),
+            "date" | "timestamp" | "timestamptz" | "time" | "timetz" | "uuid" | "json"
+            | "jsonb" | "name" => Some(format!("{}::text AS {}", column, quote_ident(&self.name))),
+            _ => None,
+        }
+    }
+}
+
+// Find which geometry column exists
+fn geometry_column(columns: &[SourceColumn], source_name: &str) -> Result<String> {
+    columns
+        .iter()
+        .find(|c| c.name == "geom" || c.name == "geometry")
+        .map(|c| c.name.clone())
+        .ok_or_else(|| anyhow!("No geometry column found in {}", source_name))
+}
+
+// Fail with UnsupportedCrsError if PostGIS cannot transform to the SRID
+async fn check_crs(client: &deadpool_postgres::Client, srid: i32) -> Result<()> {
+    let known_crs = client
+        .query_opt("SELECT 1 FROM spatial_ref_sys WHERE srid = $1", &[&srid])
+        .await?
+        .is_some();
+    if !known_crs {
+        return Err(UnsupportedCrsError { srid }.into());
+    }
+    Ok(())
+}
+
+// SRID of the source geometries, 0 if they were loaded without one and None
+// if the table has no geometries
+async fn read_source_srid(
+    client: &deadpool_postgres::Client,
+    table: &str,
+    geom_column: &str,
+) -> Result<Option<i32>> {
+    let geom = quote_ident(geom_column);
+    Ok(client
+        .query_opt(
+            &format!(
+                "SELECT ST_SRID({geom}) FROM {table} WHERE {geom} IS NOT NULL LIMIT 1",
+                geom = geom,
+                table = table
+            ),
+            &[],
+        )
+        .await?
+        .map(|row| row.get(0)))
+}
+
+// Geometry expression for the source table aliased as t. Data loaded without an
+// SRID is treated as 4326.
+fn source_geometry(geom_column: &str, srid: i32) -> String {
+    let geom = format!("t.{}", quote_ident(geom_column));
+    if srid == 0 {
+        format!("ST_SetSRID({}, 4326)", geom)
+    } else {
+        geom
+    }
+}
+
+// True if every geometry of the source lies within lon/lat bounds
+async fn looks_geographic(
+    client: &deadpool_postgres::Client,
+    table: &str,
+    geom: &str,
+) -> Result<bool> {
+    let row = client
+        .query_one(
+            &format!(
+                "SELECT ST_XMin(e) >= -180 AND ST_XMax(e) <= 180
+                    AND ST_YMin(e) >= -90 AND ST_YMax(e) <= 90
+                FROM (SELECT ST_Extent({geom}) AS e FROM {table}) extent",
+                geom = geom,
+                table = table
+            ),
+            &[],
+        )
+        .await?;
+    Ok(row.get::<_, Option<bool>>(0).unwrap_or(false))
+}
+
+async fn is_geographic(client: &deadpool_postgres::Client, srid: i32) -> Result<bool> {
+    Ok(client
+        .query_opt(
+            "SELECT proj4text LIKE '%+proj=longlat%' FROM spatial_ref_sys WHERE srid = $1",
+            &[&srid],
+        )
+        .await?
+        .map(|row| row.get(0))
+        .unwrap_or(false))
+}
+
+// Whether values of a column type can be inserted into a column of another type
+fn can_append_column(from: &str, to: &str) -> bool {
+    from == to
+        || matches!(
+            (from, to),
+            ("int2", "int4" | "int8" | "float8" | "numeric")
+                | ("int4", "int8" | "float8" | "numeric")
+                | ("int8", "numeric")
+                | ("float4", "float8")
+                | (_, "text")
+        )
+}
+
+// Names tried for a new source, the base name and then numbered ones
+const MAX_NAME_CANDIDATES: usize = 100;
+
+fn name_candidates(base: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(base.to_string())
+        .chain((2..=MAX_NAME_CANDIDATES).map(move |n| format!("{}_{}", base, n)))
+}
+
+// First candidate for a new source that is not a table in the namespace and not
+// reserved, so creating it never replaces a table
+async fn free_source_name(
+    client: &deadpool_postgres::Client,
+    namespace: &str,
+    base: &str,
+    reserved: &[String],
+) -> Result<String> {
+    for name in name_candidates(base) {
+        if reserved.contains(&name) {
+            continue;
+        }
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(&name));
+        let exists: bool = client
+            .query_one("SELECT to_regclass($1::text) IS NOT NULL", &[&table])
+            .await?
+            .get(0);
+        if !exists {
+            return Ok(name);
+        }
+    }
+    Err(anyhow!("No free name for a source based on {}", base))
+}
+
+// Geometry type and SRID a geometry column is declared with
+async fn geometry_column_type(
+    client: &deadpool_postgres::Client,
+    namespace: &str,
+    source_name: &str,
+    geom_column: &str,
+) -> Result<(String, i32)> {
+    Ok(client
+        .query_opt(
+            "SELECT type, srid FROM geometry_columns
+            WHERE f_table_schema = $1 AND f_table_name = $2 AND f_geometry_column = $3",
+            &[&namespace, &source_name, &geom_column],
+        )
+        .await?
+        .map(|row| (row.get(0), row.get(1)))
+        .unwrap_or_else(|| ("GEOMETRY".to_string(), 0)))
+}
+
+// Condition matching geometries of the source table aliased as t that intersect
+// a bbox given in 4326. The bbox is moved into the source CRS as a constant so
+// the spatial index on the geometry column can be used.
+fn bbox_condition(geom_column: &str, srid: i32, [west, south, east, north]: [f64; 4]) -> String {
+    let envelope = match srid {
+        // Geometries loaded without an SRID are in 4326 but compared as SRID 0
+        0 => format!("ST_MakeEnvelope({}, {}, {}, {})", west, south, east, north),
+        4326 => format!(
+            "ST_MakeEnvelope({}, {}, {}, {}, 4326)",
+            west, south, east, north
+        ),
+        srid => format!(
+            "ST_Transform(ST_MakeEnvelope({}, {}, {}, {}, 4326), {})",
+            west, south, east, north, srid
+        ),
+    };
+    format!(
+        "ST_Intersects(t.{}, {})",
+        quote_ident(geom_column),
+        envelope
+    )
+}
+
+// Quote an identifier for use in generated SQL
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -135,42 +662,148 @@ pub struct PostgresConnection {
     pub schema: Option<String>,
 }
 
+impl PostgresConnection {
+    // libpq URI for the connection, as used by the duckdb_postgis loader. TLS is
+    // required unless running locally, as for the connection pools, and the
+    // schema, if any, is put on the search path.
+    pub fn postgis_uri(&self) -> Result<String> {
+        let mut uri = Url::parse("postgresql://localhost")?;
+        uri.set_host(Some(&self.host))?;
+        uri.set_port(Some(self.port))
+            .map_err(|_| anyhow!("Invalid port: {}", self.port))?;
+        uri.set_username(&self.username)
+            .map_err(|_| anyhow!("Invalid username: {}", self.username))?;
+        uri.set_password(Some(&self.password))
+            .map_err(|_| anyhow!("Invalid password"))?;
+        uri.set_path(&self.database);
+
+        let is_local = std::env::var("GW_LOCAL")
+            .map(|val| val == "true")
+            .unwrap_or(false);
+        {
+            let mut query = uri.query_pairs_mut();
+            query.append_pair("sslmode", if is_local { "disable" } else { "require" });
+            if let Some(schema) = &self.schema {
+                query.append_pair("options", &format!("-c search_path={},public", schema));
+            }
+        }
+        Ok(uri.to_string())
+    }
+}
+
+// Name of the geometry column in the generated tile query, chosen so it cannot
+// clash with an attribute column
+const MVT_GEOM_COLUMN: &str = "__gw_mvt_geom";
+
 #[derive(Clone, Debug)]
 pub struct PostgisConnector {
     pool: Arc<Pool>,
+    connection: PostgresConnection,
+    // SRID of each source table, kept as every tile would otherwise look it up
+    srids: Arc<std::sync::Mutex<HashMap<String, i32>>>,
 }
 
 impl PostgisConnector {
     pub fn new(connection: PostgresConnection) -> Result<Self> {
-        let mut config = Config::new();
-        config.host = Some(connection.host.to_string());
-        config.port = Some(connection.port);
-        config.dbname = Some(connection.database.to_string());
-        config.user = Some(connection.username.to_string());
-        config.password = Some(connection.password.to_string());
-
-        let is_local = std::env::var("GW_LOCAL")
-            .map(|val| val == "true")
-            .unwrap_or(false);
-
-        let pool = if is_local {
-            config
-                .create_pool(Some(Runtime::Tokio1), NoTls)
-                .map_err(|e| anyhow!("Failed to create connection pool: {}", e))?
-        } else {
-            let mut builder = native_tls::TlsConnector::builder();
-            // TODO: For testing - remove this line once connection works and replace with proper cert verification
-            builder.danger_accept_invalid_certs(true);
-            let connector = MakeTlsConnector::new(builder.build().unwrap());
-            config
-                .create_pool(Some(Runtime::Tokio1), connector)
-                .map_err(|e| anyhow!("Failed to create connection pool: {}", e))?
-        };
-
+        let pool = create_pool(&connection, None)?;
         Ok(PostgisConnector {
             pool: Arc::new(pool),
+            connection,
+            srids: Arc::new(std::sync::Mutex::new(HashMap::new())),
         })
     }
+
+    // SRID of the source geometries, 0 if they were loaded without one. A table
+    // without geometries is looked up again next time.
+    async fn source_srid(
+        &self,
+        client: &deadpool_postgres::Client,
+        table: &str,
+        geom_column: &str,
+    ) -> Result<i32> {
+        if let Some(srid) = self.srids.lock().unwrap().get(table) {
+            return Ok(*srid);
+        }
+        let srid = read_source_srid(client, table, geom_column).await?;
+        if let Some(srid) = srid {
+            self.srids.lock().unwrap().insert(table.to_string(), srid);
+        }
+        Ok(srid.unwrap_or(0))
+    }
+
+    // Drop the cached SRID of a table that was changed or removed
+    fn forget_source_srid(&self, table: &str) {
+        self.srids.lock().unwrap().remove(table);
+    }
+
+    pub async fn get_columns(
+        &self,
+        namespace: &str,
+        source_name: &str,
+    ) -> Result<Vec<SourceColumn>> {
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        let rows = client
+            .query(
+                "SELECT column_name, udt_name
+                FROM information_schema.columns
+                WHERE table_schema = $1
+                AND table_name = $2
+                ORDER BY ordinal_position",
+                &[&namespace, &source_name],
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to list columns: {}", e))?;
+        // A table always has columns, so none means there is no such table
+        if rows.is_empty() {
+            return Err(SourceNotFoundError {
+                source_name: source_name.to_string(),
+            }
+            .into());
+        }
+        Ok(rows
+            .iter()
+            .map(|row| SourceColumn {
+                name: row.get(0),
+                udt_name: row.get(1),
+            })
+            .collect())
+    }
+}
+
+// Build a deadpool for a Postgres connection, using TLS unless running locally.
+// Optional server options (e.g. "-c search_path=...") are passed on connect.
+pub fn create_pool(connection: &PostgresConnection, options: Option<String>) -> Result<Pool> {
+    let mut config = Config::new();
+    config.host = Some(connection.host.to_string());
+    config.port = Some(connection.port);
+    config.dbname = Some(connection.database.to_string());
+    config.user = Some(connection.username.to_string());
+    config.password = Some(connection.password.to_string());
+    config.options = options;
+
+    let is_local = std::env::var("GW_LOCAL")
+        .map(|val| val == "true")
+        .unwrap_or(false);
+
+    let pool = if is_local {
+        config
+            .create_pool(Some(Runtime::Tokio1), NoTls)
+            .map_err(|e| anyhow!("Failed to create connection pool: {}", e))?
+    } else {
+        let mut builder = native_tls::TlsConnector::builder();
+        // TODO: For testing - remove this line once connection works and replace with proper cert verification
+        builder.danger_accept_invalid_certs(true);
+        let connector = MakeTlsConnector::new(builder.build().unwrap());
+        config
+            .create_pool(Some(Runtime::Tokio1), connector)
+            .map_err(|e| anyhow!("Failed to create connection pool: {}", e))?
+    };
+
+    Ok(pool)
 }
 
 #[async_trait]
@@ -231,6 +864,29 @@ impl GeoConnector for PostgisConnector {
         Ok(sources)
     }
 
+    async fn delete_source(&self, namespace: &str, source_name: &str) -> Result<()> {
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        let query = format!(
+            "DROP TABLE IF EXISTS \"{}\".\"{}\"",
+            namespace.replace('"', "\"\""),
+            source_name.replace('"', "\"\"")
+        );
+        client
+            .execute(&query, &[])
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to delete source: {}", e))?;
+        self.forget_source_srid(&format!(
+            "{}.{}",
+            quote_ident(namespace),
+            quote_ident(source_name)
+        ));
+        Ok(())
+    }
+
     async fn get_tile(
         &self,
         namespace: &str,
@@ -238,46 +894,96 @@ impl GeoConnector for PostgisConnector {
         z: u32,
         x: u32,
         y: u32,
+        fields: Option<&[String]>,
     ) -> Result<Vec<u8>> {
-        let pool = self.pool.as_ref();
-        let client = pool.get().await?;
+        let columns = self.get_columns(namespace, source_name).await?;
 
-        // First, check which geometry column exists
-        let check_column_query = format!(
-            "SELECT column_name 
-            FROM information_schema.columns 
-            WHERE table_schema = $1 
-            AND table_name = $2 
-            AND column_name IN ('geom', 'geometry')",
-        );
+        let geom_column = geometry_column(&columns, source_name)?;
 
-        let geom_column: String = client
-            .query_one(&check_column_query, &[&namespace, &source_name])
-            .await?
-            .get(0);
+        // Requested fields must be columns of the source, all attributes are
+        // included if no fields are given
+        if let Some(fields) = fields {
+            let unknown: Vec<String> = fields
+                .iter()
+                .filter(|f| !columns.iter().any(|c| &c.name == *f))
+                .cloned()
+                .collect();
+            if !unknown.is_empty() {
+                return Err(UnknownFieldsError { fields: unknown }.into());
+            }
+        }
+
+        let attributes: Vec<String> = columns
+            .iter()
+            .filter(|c| !c.is_geometry() && c.name != MVT_GEOM_COLUMN)
+            .filter(|c| fields.is_none_or(|fields| fields.contains(&c.name)))
+            .filter_map(|c| c.mvt_attribute_expr("t"))
+            .collect();
+        let attribute_select = attributes
+            .iter()
+            .map(|expr| format!(",\n                        {}", expr))
+            .collect::<String>();
+
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+
+        // Tiles are cut in web mercator. The tile envelope is moved into the
+        // source CRS for the filter so the spatial index is used, and geometries
+        // are reprojected from the source CRS for encoding.
+        let source_geom = format!("t.{}", quote_ident(&geom_column));
+        let srid = self.source_srid(&client, &table, &geom_column).await?;
+        let (filter_geom, filter_bounds, tile_geom) = match srid {
+            3857 => (source_geom.clone(), "bounds.geom".to_string(), source_geom),
+            0 => {
+                let geom = format!("ST_SetSRID({}, 4326)", source_geom);
+                (
+                    geom.clone(),
+                    "ST_Transform(bounds.geom, 4326)".to_string(),
+                    format!("ST_Transform({}, 3857)", geom),
+                )
+            }
+            _ => (
+                source_geom.clone(),
+                format!(
+                    "ST_Transform(ST_Segmentize(bounds.geom, (ST_XMax(bounds.geom) - ST_XMin(bounds.geom)) / 16), {})",
+                    srid
+                ),
+                format!("ST_Transform({}, 3857)", source_geom),
+            ),
+        };
 
         let query = format!(
             "
                 WITH bounds AS (
-                    SELECT ST_Transform(ST_TileEnvelope({z}, {x}, {y}), 4326) AS geom
+                    SELECT ST_TileEnvelope({z}, {x}, {y}) AS geom
                 ),
                 mvt_data AS (
                     SELECT ST_AsMVTGeom(
-                        t.{source_geom_column},
+                        {tile_geom},
                         bounds.geom,
                         4096,
                         256,
                         true
-                    ) AS geom
+                    ) AS {mvt_geom}{attribute_select}
                     FROM {table} t,
                     bounds
-                    WHERE ST_Intersects(t.{source_geom_column}, bounds.geom)
+                    WHERE ST_Intersects({filter_geom}, {filter_bounds})
                 )
-                SELECT ST_AsMVT(mvt_data.*, '{source_name}') AS mvt
+                SELECT ST_AsMVT(mvt_data.*, '{source_name}', 4096, '{mvt_geom}') AS mvt
                 FROM mvt_data;
                 ",
-            table = format!("\"{}\".\"{}\"", namespace, source_name),
-            source_geom_column = geom_column,
+            table = table,
+            tile_geom = tile_geom,
+            filter_geom = filter_geom,
+            filter_bounds = filter_bounds,
+            mvt_geom = MVT_GEOM_COLUMN,
+            attribute_select = attribute_select,
+            source_name = source_name.replace('\'', "''"),
             z = z,
             x = x,
             y = y,
@@ -288,6 +994,599 @@ impl GeoConnector for PostgisConnector {
         Ok(mvt_data)
     }
 
+    async fn get_fields(&self, namespace: &str, source_name: &str) -> Result<Vec<SourceField>> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        Ok(columns
+            .iter()
+            .filter(|c| !c.is_geometry() && c.name != MVT_GEOM_COLUMN)
+            .filter_map(|c| {
+                c.field_type().map(|field_type| SourceField {
+                    name: c.name.clone(),
+                    field_type,
+                })
+            })
+            .collect())
+    }
+
+    async fn get_bounds(&self, namespace: &str, source_name: &str) -> Result<Option<[f64; 4]>> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        let geom_column = geometry_column(&columns, source_name)?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+
+        // The estimate comes from planner statistics and is cheap on large tables,
+        // but is missing until the table has been analysed
+        let estimated = client
+            .query_one(
+                "SELECT ST_EstimatedExtent($1, $2, $3) IS NOT NULL",
+                &[&namespace, &source_name, &geom_column],
+            )
+            .await
+            .map(|row| row.get::<_, bool>(0))
+            .unwrap_or(false);
+        let extent = if estimated {
+            format!(
+                "ST_EstimatedExtent('{}', '{}', '{}')::geometry",
+                namespace.replace('\'', "''"),
+                source_name.replace('\'', "''"),
+                geom_column.replace('\'', "''")
+            )
+        } else {
+            format!(
+                "(SELECT ST_Extent({}) FROM {})::geometry",
+                quote_ident(&geom_column),
+                table
+            )
+        };
+
+        // Take the SRID the column is declared with, an unconstrained column
+        // falls back to the stored geometries. Data without an SRID is assumed
+        // to already be in 4326.
+        let (_, declared_srid) =
+            geometry_column_type(&client, namespace, source_name, &geom_column).await?;
+        let srid = match declared_srid {
+            0 => self.source_srid(&client, &table, &geom_column).await?,
+            srid => srid,
+        };
+        let srid = if srid == 0 { 4326 } else { srid };
+        let query = format!(
+            "WITH extent AS (
+                SELECT ST_Transform(ST_SetSRID({extent}, {srid}), 4326) AS geom
+            )
+            SELECT ST_XMin(geom), ST_YMin(geom), ST_XMax(geom), ST_YMax(geom)
+            FROM extent
+            WHERE geom IS NOT NULL",
+            extent = extent,
+            srid = srid,
+        );
+        let row = client
+            .query_opt(&query, &[])
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to get bounds: {}", e))?;
+        Ok(row.map(|row| [row.get(0), row.get(1), row.get(2), row.get(3)]))
+    }
+
+    async fn query_features(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        query: &FeatureQuery,
+    ) -> Result<FeaturePage> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        let geom_column = geometry_column(&columns, source_name)?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+
+        // Every named column must exist before it is used in the query
+        let requested = query
+            .filters
+            .iter()
+            .map(|(name, _)| name)
+            .chain(query.properties.iter().flatten());
+        let mut unknown: Vec<String> = requested
+            .filter(|name| !columns.iter().any(|c| &c.name == *name && !c.is_geometry()))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(UnknownFieldsError { fields: unknown }.into());
+        }
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+
+        check_crs(&client, query.crs).await?;
+
+        let srid = self.source_srid(&client, &table, &geom_column).await?;
+        let geom = source_geometry(&geom_column, srid);
+
+        let mut conditions = Vec::new();
+        if let Some(bbox) = query.bbox {
+            conditions.push(bbox_condition(&geom_column, srid, bbox));
+        }
+        let mut params: Vec<&(dyn tokio_postgres::types::ToSql + Sync)> = Vec::new();
+        for (name, value) in &query.filters {
+            params.push(value);
+            conditions.push(format!("t.{}::text = ${}", quote_ident(name), params.len()));
+        }
+        let where_clause = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let properties = columns
+            .iter()
+            .filter(|c| !c.is_geometry())
+            .filter(|c| {
+                query
+                    .properties
+                    .as_ref()
+                    .is_none_or(|properties| properties.contains(&c.name))
+            })
+            .map(|c| format!("t.{}", quote_ident(&c.name)))
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        let count_query = format!("SELECT count(*) FROM {} t {}", table, where_clause);
+        let number_matched: i64 = client
+            .query_one(&count_query, &params)
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to count features: {}", e))?
+            .get(0);
+
+        // ctid gives a stable order for paging without relying on a key column
+        let features_query = format!(
+            "SELECT jsonb_build_object(
+                'type', 'Feature',
+                'geometry', ST_AsGeoJSON(ST_Transform({geom}, {crs}))::jsonb,
+                'properties', to_jsonb(p)
+            )
+            FROM {table} t
+            CROSS JOIN LATERAL (SELECT {properties}) p
+            {where_clause}
+            ORDER BY t.ctid
+            LIMIT {limit} OFFSET {offset}",
+            geom = geom,
+            crs = query.crs,
+            table = table,
+            properties = properties,
+            where_clause = where_clause,
+            limit = query.limit.clamp(0, MAX_FEATURE_LIMIT),
+            offset = query.offset.max(0),
+        );
+        let rows = client
+            .query(&features_query, &params)
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to get features: {}", e))?;
+
+        Ok(FeaturePage {
+            features: rows.iter().map(|row| row.get(0)).collect(),
+            number_matched,
+        })
+    }
+
+    async fn count_source(&self, namespace: &str, source_name: &str) -> Result<u64> {
+        // Checks the source exists, failing with SourceNotFoundError if not
+        self.get_columns(namespace, source_name).await?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        let count: i64 = client
+            .query_one(&format!("SELECT count(*) FROM {}", table), &[])
+            .await
+            .map_err(|e| anyhow!("Failed to execute query to count features: {}", e))?
+            .get(0);
+        Ok(count as u64)
+    }
+
+    async fn export_source(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        options: &ExportOptions,
+        output_dir: &Path,
+    ) -> Result<PathBuf> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        let geom_column = geometry_column(&columns, source_name)?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        check_crs(&client, options.crs).await?;
+        let srid = self.source_srid(&client, &table, &geom_column).await?;
+        let geom = source_geometry(&geom_column, srid);
+
+        // Filtering and reprojection run in PostGIS, DuckDB only writes the file
+        let mut select: Vec<String> = columns
+            .iter()
+            .filter(|c| !c.is_geometry() && c.name != "geom")
+            .map(|c| format!("t.{}", quote_ident(&c.name)))
+            .collect();
+        select.push(format!(
+            "ST_AsBinary(ST_Transform({}, {})) AS geom",
+            geom, options.crs
+        ));
+        let where_clause = options
+            .bbox
+            .map(|bbox| format!("WHERE {}", bbox_condition(&geom_column, srid, bbox)))
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT {} FROM {} t {}",
+            select.join(", "),
+            table,
+            where_clause
+        );
+
+        let connection = self.connection.clone();
+        let file_stem = export_file_stem(source_name);
+        let options = options.clone();
+        let output_dir = output_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            export_postgis_query(&connection, &query, &file_stem, &options, &output_dir)
+        })
+        .await?
+    }
+
+    async fn set_source_crs(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        options: &CrsOptions,
+    ) -> Result<AppliedCrs> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        let geom_column = geometry_column(&columns, source_name)?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+        let geom = quote_ident(&geom_column);
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        let loaded_srid = read_source_srid(&client, &table, &geom_column)
+            .await?
+            .unwrap_or(0);
+
+        let source_srid = match (options.declared, options.detected, loaded_srid) {
+            (Some(declared), _, _) => declared,
+            (None, Some(detected), 0) => {
+                if looks_geographic(&client, &table, &geom).await?
+                    && !is_geographic(&client, detected).await?
+                {
+                    // The loader has already reprojected to lon/lat but left the
+                    // SRID unset, so the file's projected CRS no longer applies
+                    tracing::warn!(
+                        "{} is in lon/lat coordinates, not EPSG:{} as detected",
+                        table,
+                        detected
+                    );
+                    4326
+                } else {
+                    detected
+                }
+            }
+            // Data without any CRS information is assumed to be 4326
+            (None, None, 0) => 4326,
+            (None, _, loaded) => loaded,
+        };
+        check_crs(&client, source_srid).await?;
+        let srid = options.storage.unwrap_or(source_srid);
+        check_crs(&client, srid).await?;
+
+        if loaded_srid != source_srid || srid != source_srid {
+            // Keep the geometry type constraint of the column, if it has one
+            let geometry_type: String = client
+                .query_opt(
+                    "SELECT type FROM geometry_columns
+                    WHERE f_table_schema = $1 AND f_table_name = $2 AND f_geometry_column = $3",
+                    &[&namespace, &source_name, &geom_column],
+                )
+                .await?
+                .map(|row| row.get(0))
+                .unwrap_or_else(|| "GEOMETRY".to_string());
+            let relabelled = format!("ST_SetSRID({}, {})", geom, source_srid);
+            let using = if srid == source_srid {
+                relabelled
+            } else {
+                format!("ST_Transform({}, {})", relabelled, srid)
+            };
+            client
+                .batch_execute(&format!(
+                    "ALTER TABLE {table} ALTER COLUMN {geom} TYPE geometry({geometry_type}, {srid}) USING {using};
+                    CREATE INDEX IF NOT EXISTS {index} ON {table} USING GIST ({geom});",
+                    table = table,
+                    geom = geom,
+                    geometry_type = geometry_type,
+                    srid = srid,
+                    using = using,
+                    index = quote_ident(&format!("{}_{}_idx", source_name, geom_column)),
+                ))
+                .await
+                .map_err(|e| anyhow!("Failed to set the CRS of {}: {}", table, e))?;
+            self.forget_source_srid(&table);
+        }
+
+        Ok(AppliedCrs {
+            source_srid,
+            storage_srid: srid,
+        })
+    }
+
+    async fn validate_source(
+        &self,
+        namespace: &str,
+        source_name: &str,
+        options: &ValidationOptions,
+    ) -> Result<ValidationReport> {
+        let columns = self.get_columns(namespace, source_name).await?;
+        let geom_column = geometry_column(&columns, source_name)?;
+        let table = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+        let geom = quote_ident(&geom_column);
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+
+        let invalid = format!(
+            "{geom} IS NOT NULL AND NOT ST_IsEmpty({geom}) AND NOT ST_IsValid({geom})",
+            geom = geom
+        );
+        let counts = client
+            .query_one(
+                &format!(
+                    "SELECT count(*),
+                        count(*) FILTER (WHERE {geom} IS NULL),
+                        count(*) FILTER (WHERE ST_IsEmpty({geom})),
+                        count(*) FILTER (WHERE {invalid})
+                    FROM {table}",
+                    geom = geom,
+                    invalid = invalid,
+                    table = table
+                ),
+                &[],
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to validate {}: {}", table, e))?;
+        let count = |i: usize| counts.get::<_, i64>(i) as u64;
+        let mut report = ValidationReport {
+            feature_count: count(0),
+            null_count: count(1),
+            empty_count: count(2),
+            invalid_count: count(3),
+            ..Default::default()
+        };
+
+        let geometry_type = format!("replace(ST_GeometryType({}), 'ST_', '')", geom);
+        report.geometry_types = client
+            .query(
+                &format!(
+                    "SELECT {geometry_type}, count(*) FROM {table}
+                    WHERE {geom} IS NOT NULL GROUP BY 1",
+                    geometry_type = geometry_type,
+                    table = table,
+                    geom = geom
+                ),
+                &[],
+            )
+            .await?
+            .iter()
+            .map(|row| (row.get::<_, String>(0), row.get::<_, i64>(1) as u64))
+            .collect();
+
+        if options.repair && report.invalid_count > 0 {
+            // ST_MakeValid can return a collection, so keep only the parts of the
+            // original dimension to fit the column's geometry type
+            let made_valid = format!("ST_MakeValid({})", geom);
+            let extracted = format!(
+                "ST_CollectionExtract({}, ST_Dimension({}) + 1)",
+                made_valid, geom
+            );
+            report.repaired_count = client
+                .execute(
+                    &format!(
+                        "UPDATE {table} SET {geom} = CASE
+                            WHEN ST_GeometryType({made_valid}) = ST_GeometryType({geom}) THEN {made_valid}
+                            WHEN ST_GeometryType({geom}) LIKE 'ST_Multi%' THEN ST_Multi({extracted})
+                            ELSE {extracted}
+                        END
+                        WHERE {invalid}",
+                        table = table,
+                        geom = geom,
+                        made_valid = made_valid,
+                        extracted = extracted,
+                        invalid = invalid
+                    ),
+                    &[],
+                )
+                .await
+                .map_err(|e| anyhow!("Failed to repair geometries in {}: {}", table, e))?;
+        }
+
+        let families = report.geometry_families();
+        if options.split_by_type && families.len() > 1 {
+            let split_base = options.split_base.as_deref().unwrap_or(source_name);
+            for family in families {
+                let types: Vec<String> = report
+                    .geometry_types
+                    .keys()
+                    .filter(|t| geometry_family(t) == family)
+                    .map(|t| format!("'{}'", t.replace('\'', "''")))
+                    .collect();
+                let split_name = free_source_name(
+                    &client,
+                    namespace,
+                    &format!("{}_{}", split_base, family),
+                    &options.reserved_names,
+                )
+                .await?;
+                let split_table =
+                    format!("{}.{}", quote_ident(namespace), quote_ident(&split_name));
+                // The statements of one batch run in a single transaction
+                let split = client
+                    .batch_execute(&format!(
+                        "CREATE TABLE {split_table} AS
+                            SELECT * FROM {table} WHERE {geometry_type} IN ({types});
+                        CREATE INDEX {index} ON {split_table} USING GIST ({geom});",
+                        split_table = split_table,
+                        table = table,
+                        geometry_type = geometry_type,
+                        types = types.join(", "),
+                        index = quote_ident(&format!("{}_{}_idx", split_name, geom_column)),
+                        geom = geom
+                    ))
+                    .await;
+                if let Err(e) = split {
+                    // Leave no part of a split behind
+                    for created in &report.split_sources {
+                        if let Err(drop_err) = self.delete_source(namespace, created).await {
+                            tracing::error!(
+                                "Failed to drop split source {}: {}",
+                                created,
+                                drop_err
+                            );
+                        }
+                    }
+                    return Err(anyhow!("Failed to split {} by geometry type: {}", table, e));
+                }
+                // A table of the same name may have been dropped since its CRS was read
+                self.forget_source_srid(&split_table);
+                report.split_sources.push(split_name);
+            }
+        }
+
+        report.extent = self.get_bounds(namespace, source_name).await?;
+        Ok(report)
+    }
+
+    async fn commit_staged_source(
+        &self,
+        namespace: &str,
+        staging_name: &str,
+        source_name: &str,
+        mode: LayerMode,
+    ) -> Result<()> {
+        let staging_columns = self.get_columns(namespace, staging_name).await?;
+        let geom_column = geometry_column(&staging_columns, staging_name)?;
+        let staging = format!("{}.{}", quote_ident(namespace), quote_ident(staging_name));
+        let target = format!("{}.{}", quote_ident(namespace), quote_ident(source_name));
+
+        let client = self
+            .pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+
+        // The spatial index made by set_source_crs is named after the table
+        let rename = format!(
+            "ALTER TABLE {staging} RENAME TO {name};
+            ALTER INDEX IF EXISTS {namespace}.{staging_index} RENAME TO {index};",
+            staging = staging,
+            name = quote_ident(source_name),
+            namespace = quote_ident(namespace),
+            staging_index = quote_ident(&format!("{}_{}_idx", staging_name, geom_column)),
+            index = quote_ident(&format!("{}_{}_idx", source_name, geom_column)),
+        );
+        // The statements of one batch run in a single transaction, so readers
+        // see either the old table or the new one
+        let query = match mode {
+            LayerMode::Create => rename,
+            LayerMode::Replace => format!("DROP TABLE IF EXISTS {};\n{}", target, rename),
+            LayerMode::Append => {
+                let target_columns = self.get_columns(namespace, source_name).await?;
+                let target_geom = geometry_column(&target_columns, source_name)?;
+
+                let mut problems = Vec::new();
+                for column in staging_columns.iter().filter(|c| c.name != geom_column) {
+                    match target_columns.iter().find(|c| c.name == column.name) {
+                        None => {
+                            problems.push(format!("column {} is not in the layer", column.name))
+                        }
+                        Some(existing)
+                            if !can_append_column(&column.udt_name, &existing.udt_name) =>
+                        {
+                            problems.push(format!(
+                                "column {} is {} but the layer has {}",
+                                column.name, column.udt_name, existing.udt_name
+                            ))
+                        }
+                        Some(_) => {}
+                    }
+                }
+                let (staging_type, staging_srid) =
+                    geometry_column_type(&client, namespace, staging_name, &geom_column).await?;
+                let (target_type, target_srid) =
+                    geometry_column_type(&client, namespace, source_name, &target_geom).await?;
+                if staging_srid != target_srid {
+                    problems.push(format!(
+                        "geometries are in EPSG:{} but the layer is in EPSG:{}",
+                        staging_srid, target_srid
+                    ));
+                }
+                if target_type != "GEOMETRY"
+                    && staging_type.trim_start_matches("MULTI")
+                        != target_type.trim_start_matches("MULTI")
+                {
+                    problems.push(format!(
+                        "geometries are {} but the layer holds {}",
+                        staging_type, target_type
+                    ));
+                }
+                if !problems.is_empty() {
+                    return Err(anyhow!(
+                        "Cannot append to {}: {}",
+                        source_name,
+                        problems.join("; ")
+                    ));
+                }
+
+                let geometry = if target_type.starts_with("MULTI") {
+                    format!("ST_Multi({})", quote_ident(&geom_column))
+                } else {
+                    quote_ident(&geom_column)
+                };
+                let attributes: Vec<String> = staging_columns
+                    .iter()
+                    .filter(|c| c.name != geom_column)
+                    .map(|c| quote_ident(&c.name))
+                    .collect();
+                let (mut columns, mut values) = (attributes.clone(), attributes);
+                columns.push(quote_ident(&target_geom));
+                values.push(geometry);
+                format!(
+                    "INSERT INTO {target} ({columns}) SELECT {values} FROM {staging};
+                    DROP TABLE {staging};",
+                    target = target,
+                    columns = columns.join(", "),
+                    values = values.join(", "),
+                    staging = staging,
+                )
+            }
+        };
+        client
+            .batch_execute(&query)
+            .await
+            .map_err(|e| anyhow!("Failed to {} {}: {}", mode, target, e))?;
+        self.forget_source_srid(&staging);
+        self.forget_source_srid(&target);
+        Ok(())
+    }
+
     async fn get_geometry_type(&self, namespace: &str, source_name: &str) -> Result<GeometryType> {
         // Let the client and handle the connection
         let client = self
@@ -311,12 +1610,17 @@ impl GeoConnector for PostgisConnector {
             .await?
             .get(0);
 
-        // Query to get the geometry type
+        // Query to get the geometry type, the most common one if the source is mixed
         let query = format!(
-            "SELECT DISTINCT ST_GeometryType({}) 
-            FROM \"{}\".\"{}\" 
+            "SELECT ST_GeometryType({0})
+            FROM \"{1}\".\"{2}\"
+            WHERE {0} IS NOT NULL
+            GROUP BY 1
+            ORDER BY count(*) DESC
             LIMIT 1",
-            geom_column, namespace, source_name
+            geom_column,
+            namespace.replace('"', "\"\""),
+            source_name.replace('"', "\"\"")
         );
 
         let row = client.query_one(&query, &[]).await?;
@@ -349,17 +1653,33 @@ impl GeoConnections {
         }
     }
 
-    pub async fn add_connection<T: GeoConnector + 'static>(&self, name: String, source: T) {
+    pub async fn add_connection(&self, name: String, source: Arc<dyn GeoConnector>) {
         let mut sources = self.sources.write().await;
-        sources.insert(name, Arc::new(source));
+        sources.insert(name, source);
     }
 
-    pub async fn get_connection(&self, connection_id: &str) -> Result<Arc<dyn GeoConnector>> {
-        let sources = self.sources.read().await;
-        sources
-            .get(connection_id)
-            .cloned()
-            .ok_or_else(|| anyhow!("Source not found"))
+    // Connectors are built from the stored record the first time a connection is
+    // used, so connections created before a restart are available again
+    pub async fn get_connection(
+        &self,
+        database: &Arc<dyn Database>,
+        connection_id: &str,
+    ) -> Result<Arc<dyn GeoConnector>> {
+        if let Some(source) = self.sources.read().await.get(connection_id) {
+            return Ok(source.clone());
+        }
+        let record = Connection::from_name(database, connection_id)
+            .await
+            .map_err(|e| anyhow!("Connection {} not found: {}", connection_id, e))?;
+        // Building a connector can read files, so keep it off the async workers
+        let connector =
+            tokio::task::spawn_blocking(move || record.config.create_connector()).await??;
+        // Another request may have built the connector in the meantime
+        let mut sources = self.sources.write().await;
+        Ok(sources
+            .entry(connection_id.to_string())
+            .or_insert(connector)
+            .clone())
     }
 
     pub async fn remove_connection(&self, name: &str) -> Option<Arc<dyn GeoConnector>> {
@@ -367,3 +1687,69 @@ impl GeoConnections {
         sources.remove(name)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bbox_conditions_compare_with_a_constant_envelope() {
+        let bbox = [-1.5, 50.0, 0.5, 51.25];
+        assert_eq!(
+            bbox_condition("geom", 27700, bbox),
+            "ST_Intersects(t.\"geom\", ST_Transform(ST_MakeEnvelope(-1.5, 50, 0.5, 51.25, 4326), 27700))"
+        );
+        assert_eq!(
+            bbox_condition("geom", 4326, bbox),
+            "ST_Intersects(t.\"geom\", ST_MakeEnvelope(-1.5, 50, 0.5, 51.25, 4326))"
+        );
+        assert_eq!(
+            bbox_condition("geom", 0, bbox),
+            "ST_Intersects(t.\"geom\", ST_MakeEnvelope(-1.5, 50, 0.5, 51.25))"
+        );
+    }
+
+    #[test]
+    fn can_append_column_allows_widening_only() {
+        assert!(can_append_column("int4", "int4"));
+        assert!(can_append_column("int4", "int8"));
+        assert!(can_append_column("float4", "float8"));
+        assert!(can_append_column("timestamp", "text"));
+        assert!(!can_append_column("int8", "int4"));
+        assert!(!can_append_column("text", "int4"));
+        assert!(!can_append_column("float8", "numeric"));
+    }
+
+    #[test]
+    fn name_candidates_number_after_the_base_name() {
+        let names: Vec<String> = name_candidates("roads_line").take(3).collect();
+        assert_eq!(names, vec!["roads_line", "roads_line_2", "roads_line_3"]);
+        assert_eq!(name_candidates("roads").count(), MAX_NAME_CANDIDATES);
+    }
+
+    #[test]
+    fn appended_reports_add_to_the_layer_report() {
+        let mut report = ValidationReport {
+            feature_count: 3,
+            geometry_types: BTreeMap::from([(String::from("Point"), 3)]),
+            invalid_count: 1,
+            extent: Some([0.0, 0.0, 1.0, 1.0]),
+            ..Default::default()
+        };
+        report.merge(&ValidationReport {
+            feature_count: 2,
+            geometry_types: BTreeMap::from([
+                (String::from("Point"), 1),
+                (String::from("LineString"), 1),
+            ]),
+            null_count: 1,
+            extent: Some([-1.0, 0.5, 0.5, 2.0]),
+            ..Default::default()
+        });
+        assert_eq!(report.feature_count, 5);
+        assert_eq!(report.geometry_types["Point"], 4);
+        assert_eq!(report.geometry_types["LineString"], 1);
+        assert_eq!((report.null_count, report.invalid_count), (1, 1));
+        assert_eq!(report.extent, Some([-1.0, 0.0, 1.0, 2.0]));
+    }
+}
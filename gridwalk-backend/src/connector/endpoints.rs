@@ -1,22 +1,41 @@
 use crate::app_state::AppState;
 use crate::auth::AuthUser;
-use crate::connector::{Connection, ConnectionAccess, PostgisConnector, PostgresConnection};
+use crate::connector::{
+    export_file_stem, max_export_features, Connection, ConnectionAccess, ConnectorConfig,
+    ExportFormat, ExportOptions, ExportTooLargeError, FeatureQuery, GeoConnector, SourceField,
+    SourceNotFoundError, UnknownFieldsError, UnsupportedCrsError, DEFAULT_FEATURE_LIMIT,
+};
 use crate::{GlobalRole, Workspace, WorkspaceMember};
 use axum::{
-    extract::{Extension, Path, State},
-    http::StatusCode,
+    body::Body,
+    extract::{Extension, Path, Query, State},
+    http::{header, StatusCode},
     response::IntoResponse,
     Json,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
+use tokio::fs;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
 
-// TODO: Allow other connector types
 #[derive(Debug, Deserialize)]
 pub struct CreateGlobalConnectionRequest {
     name: String,
     display_name: String,
-    config: PostgresConnection,
+    #[serde(deserialize_with = "request_connector_config")]
+    config: ConnectorConfig,
+}
+
+// Requests from before connector types were added send Postgres details without
+// a type, so an untagged config is read as a PostGIS connection
+fn request_connector_config<'de, D>(deserializer: D) -> Result<ConnectorConfig, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    ConnectorConfig::from_stored("postgis", value).map_err(serde::de::Error::custom)
 }
 
 impl Connection {
@@ -24,7 +43,6 @@ impl Connection {
         Connection {
             id: req.name,
             name: req.display_name,
-            connector_type: "postgis".into(),
             config: req.config,
         }
     }
@@ -64,8 +82,21 @@ pub async fn create_connection(
         return (StatusCode::CONFLICT, "Connection already exists").into_response();
     }
 
-    // Create postgis connector
-    let postgis_connector = PostgisConnector::new(connection_info.clone().config).unwrap();
+    // Building a connector can read files, so keep it off the async workers
+    let config = connection_info.config.clone();
+    let built = tokio::task::spawn_blocking(move || config.create_connector())
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    let connector = match built {
+        Ok(connector) => connector,
+        Err(e) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid connection config: {}", e),
+            )
+                .into_response()
+        }
+    };
 
     // Attempt to create record
     match connection_info.clone().create_record(&state.app_data).await {
@@ -73,7 +104,7 @@ pub async fn create_connection(
             // Add connection to geo_connections
             state
                 .geo_connections
-                .add_connection(connection_info.id, postgis_connector)
+                .add_connection(connection_info.id, connector)
                 .await;
             (StatusCode::OK, "Connection creation submitted").into_response()
         }
@@ -92,13 +123,12 @@ pub struct ConnectionResponse {
     pub connector_type: String,
 }
 
-// TODO: Switch to using Connection after retrieving the connection from the database
-impl From<ConnectionAccess> for ConnectionResponse {
-    fn from(con: ConnectionAccess) -> Self {
+impl From<Connection> for ConnectionResponse {
+    fn from(con: Connection) -> Self {
         ConnectionResponse {
-            id: con.connection_id.clone(),
-            name: con.connection_id,
-            connector_type: "postgis".into(),
+            connector_type: con.connector_type().to_string(),
+            id: con.id,
+            name: con.name,
         }
     }
 }
@@ -119,14 +149,18 @@ pub async fn list_connections(
                 .await
                 .map_err(|_| (StatusCode::FORBIDDEN, "unauthorized".to_string()))?;
 
-            let connection_access_list = ConnectionAccess::get_all(&state.app_data, &workspace)
+            let connections = Connection::get_all(&state.app_data, &workspace)
                 .await
-                .ok()
-                .unwrap();
+                .map_err(|e| {
+                    tracing::error!("Error listing connections: {:?}", e);
+                    (
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        "Failed to list connections".to_string(),
+                    )
+                })?;
 
-            // Convert Vec<Connection> to Vec<ConnectionResponse>
             // Removes the config from the response
-            let connection_responses: Vec<ConnectionResponse> = connection_access_list
+            let connection_responses: Vec<ConnectionResponse> = connections
                 .into_iter()
                 .map(ConnectionResponse::from)
                 .collect();
@@ -161,14 +195,14 @@ pub async fn list_sources(
 
             let connection = state
                 .geo_connections
-                .get_connection(&connection_id)
+                .get_connection(&state.app_data, &connection_id)
                 .await
-                .unwrap();
+                .map_err(|_| (StatusCode::NOT_FOUND, "connection not found".to_string()))?;
 
             match connection.list_sources(&workspace.id).await {
                 Ok(sources) => Ok(Json(sources)),
                 Err(e) => {
-                    eprintln!("Error listing sources: {:?}", e);
+                    tracing::error!("Error listing sources: {:?}", e);
                     Err((
                         StatusCode::INTERNAL_SERVER_ERROR,
                         "Failed to list sources".to_string(),
@@ -179,3 +213,417 @@ pub async fn list_sources(
         None => Err((StatusCode::FORBIDDEN, "unauthorized".to_string())),
     }
 }
+
+// Check the user is a member of the workspace and the workspace has access to
+// the connection for its own namespace, returning the connector to query
+pub async fn authorize_source_access(
+    state: &Arc<AppState>,
+    auth_user: &AuthUser,
+    workspace_id: &str,
+    connection_id: &str,
+) -> Result<Arc<dyn GeoConnector>, (StatusCode, String)> {
+    let user = auth_user
+        .user
+        .as_ref()
+        .ok_or((StatusCode::FORBIDDEN, "unauthorized".to_string()))?;
+
+    let workspace = Workspace::from_id(&state.app_data, workspace_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "workspace not found".to_string()))?;
+
+    WorkspaceMember::get(&state.app_data, &workspace, user)
+        .await
+        .map_err(|_| (StatusCode::FORBIDDEN, "unauthorized".to_string()))?;
+
+    let connection_access = ConnectionAccess::get(&state.app_data, &workspace, connection_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "connection not found".to_string()))?;
+    if !connection_access
+        .access_config
+        .allows_namespace(&workspace.id)
+    {
+        return Err((StatusCode::FORBIDDEN, "unauthorized".to_string()));
+    }
+
+    state
+        .geo_connections
+        .get_connection(&state.app_data, connection_id)
+        .await
+        .map_err(|_| (StatusCode::NOT_FOUND, "connection not found".to_string()))
+}
+
+// Split a comma separated query parameter, ignoring empty entries
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+// Parse a west,south,east,north bbox in 4326
+pub fn parse_bbox(value: &str) -> Result<[f64; 4], String> {
+    let coords = split_list(value)
+        .iter()
+        .map(|v| v.parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| "bbox must be four numbers".to_string())?;
+    let [west, south, east, north] = coords[..] else {
+        return Err("bbox must be four numbers".to_string());
+    };
+    if !coords.iter().all(|c| c.is_finite()) {
+        return Err("bbox must be finite numbers".to_string());
+    }
+    if west > east || south > north {
+        return Err("bbox must be ordered west,south,east,north".to_string());
+    }
+    Ok([west, south, east, north])
+}
+
+// Build a feature query from request parameters. Parameters that are not
+// reserved are attribute equality filters and must name a field of the source.
+impl FeatureQuery {
+    pub fn from_params(
+        params: &HashMap<String, String>,
+        fields: &[SourceField],
+    ) -> Result<Self, String> {
+        let mut query = FeatureQuery::default();
+        let mut unknown = Vec::new();
+        for (key, value) in params {
+            match key.as_str() {
+                "bbox" => query.bbox = Some(parse_bbox(value)?),
+                "limit" => {
+                    query.limit = value
+                        .parse()
+                        .map_err(|_| "limit must be a number".to_string())?;
+                }
+                "offset" => {
+                    query.offset = value
+                        .parse()
+                        .map_err(|_| "offset must be a number".to_string())?;
+                }
+                "properties" => query.properties = Some(split_list(value)),
+                "crs" => query.crs = parse_crs(value)?,
+                _ if fields.iter().any(|f| &f.name == key) => {
+                    query.filters.push((key.clone(), value.clone()))
+                }
+                _ => unknown.push(key.clone()),
+            }
+        }
+        if !unknown.is_empty() {
+            unknown.sort();
+            return Err(format!("unknown query parameters: {}", unknown.join(", ")));
+        }
+        // Keep filter order stable so identical requests build identical SQL
+        query.filters.sort();
+        if query.limit <= 0 {
+            query.limit = DEFAULT_FEATURE_LIMIT;
+        }
+        Ok(query)
+    }
+}
+
+// Fields of a source, for checking request parameters before querying it
+pub async fn source_fields(
+    connection: &Arc<dyn GeoConnector>,
+    workspace_id: &str,
+    source_name: &str,
+) -> Result<Vec<SourceField>, (StatusCode, String)> {
+    connection
+        .get_fields(workspace_id, source_name)
+        .await
+        .map_err(|e| {
+            if e.is::<SourceNotFoundError>() {
+                (StatusCode::NOT_FOUND, "source not found".to_string())
+            } else {
+                tracing::error!("Error getting fields: {:?}", e);
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to get fields".to_string(),
+                )
+            }
+        })
+}
+
+// Accepts a bare SRID, EPSG:<srid> or an OGC CRS URI
+pub fn parse_crs(value: &str) -> Result<i32, String> {
+    let code = value.rsplit(['/', ':']).next().unwrap_or_default();
+    if code == "CRS84" {
+        return Ok(4326);
+    }
+    code.parse::<i32>()
+        .ok()
+        .filter(|srid| *srid > 0)
+        .ok_or_else(|| format!("invalid crs: {}", value))
+}
+
+pub async fn get_features(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((workspace_id, connection_id, source_name)): Path<(String, String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let connection =
+        authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+
+    let fields = source_fields(&connection, &workspace_id, &source_name).await?;
+    let query =
+        FeatureQuery::from_params(&params, &fields).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    match connection
+        .query_features(&workspace_id, &source_name, &query)
+        .await
+    {
+        Ok(page) => Ok((
+            [(header::CONTENT_TYPE, "application/geo+json")],
+            Json(page.into_feature_collection()),
+        )),
+        Err(e) if e.is::<UnknownFieldsError>() || e.is::<UnsupportedCrsError>() => {
+            Err((StatusCode::BAD_REQUEST, e.to_string()))
+        }
+        Err(e) => {
+            tracing::error!("Error querying features: {:?}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to query features".to_string(),
+            ))
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    format: String,
+    bbox: Option<String>,
+    crs: Option<String>,
+}
+
+impl ExportQuery {
+    fn options(&self) -> Result<ExportOptions, String> {
+        let format = ExportFormat::from_str(&self.format)?;
+        let bbox = self.bbox.as_deref().map(parse_bbox).transpose()?;
+        let crs = match &self.crs {
+            Some(crs) => parse_crs(crs)?,
+            None => 4326,
+        };
+        Ok(ExportOptions {
+            format,
+            bbox,
+            crs,
+            max_features: max_export_features(),
+        })
+    }
+}
+
+pub async fn export_source(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((workspace_id, connection_id, source_name)): Path<(String, String, String)>,
+    Query(query): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let connection =
+        authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+    let options = query.options().map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    // Each export gets its own directory so concurrent exports cannot collide
+    let export_dir = std::path::Path::new("exports").join(Uuid::new_v4().to_string());
+    let export = connection
+        .export_source(&workspace_id, &source_name, &options, &export_dir)
+        .await;
+    let file = match export {
+        Ok(path) => fs::File::open(&path).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+
+    // The open handle keeps the file readable while it streams, so the export
+    // directory can be removed straight away
+    if let Err(e) = fs::remove_dir_all(&export_dir).await {
+        tracing::error!(
+            "Failed to clean up export {}: {:?}",
+            export_dir.display(),
+            e
+        );
+    }
+
+    let file = match file {
+        Ok(file) => file,
+        Err(e) if e.is::<UnsupportedCrsError>() => {
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+        Err(e) if e.is::<ExportTooLargeError>() => {
+            return Err((StatusCode::PAYLOAD_TOO_LARGE, e.to_string()));
+        }
+        Err(e) if e.is::<SourceNotFoundError>() => {
+            return Err((StatusCode::NOT_FOUND, "source not found".to_string()));
+        }
+        Err(e) => {
+            tracing::error!("Error exporting source: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to export source".to_string(),
+            ));
+        }
+    };
+
+    let filename = format!(
+        "{}.{}",
+        export_file_stem(&source_name),
+        options.format.file_extension()
+    );
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                options.format.content_type().to_string(),
+            ),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        Body::from_stream(ReaderStream::new(file)),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::{ConnectionAccessConfig, FieldType};
+    use crate::data::InMemoryDatabase;
+
+    #[test]
+    fn connection_requests_accept_tagged_and_legacy_configs() {
+        let tagged: CreateGlobalConnectionRequest = serde_json::from_value(serde_json::json!({
+            "name": "parcels",
+            "display_name": "Parcels",
+            "config": { "type": "geopackage", "path": "/data/parcels.gpkg" }
+        }))
+        .unwrap();
+        assert_eq!(tagged.config.connector_type(), "geopackage");
+
+        let legacy: CreateGlobalConnectionRequest = serde_json::from_value(serde_json::json!({
+            "name": "warehouse",
+            "display_name": "Warehouse",
+            "config": {
+                "host": "db",
+                "port": 5432,
+                "database": "gis",
+                "username": "gw",
+                "password": "secret",
+                "schema": null
+            }
+        }))
+        .unwrap();
+        assert_eq!(legacy.config.postgres().unwrap().host, "db");
+
+        let unknown = serde_json::from_value::<CreateGlobalConnectionRequest>(serde_json::json!({
+            "name": "tiles",
+            "display_name": "Tiles",
+            "config": { "type": "wms", "url": "https://example.com" }
+        }));
+        assert!(unknown.is_err());
+    }
+
+    #[test]
+    fn parse_bbox_accepts_ordered_finite_bounds() {
+        assert_eq!(
+            parse_bbox("-1.5, 50, 0.5,52").unwrap(),
+            [-1.5, 50.0, 0.5, 52.0]
+        );
+    }
+
+    #[test]
+    fn parse_bbox_rejects_invalid_bounds() {
+        for bbox in [
+            "1,2,3",
+            "1,2,3,4,5",
+            "a,2,3,4",
+            "NaN,50,0,52",
+            "-1,50,inf,52",
+            "1,50,-1,52",
+            "-1,52,1,50",
+        ] {
+            assert!(parse_bbox(bbox).is_err(), "{} should be rejected", bbox);
+        }
+    }
+
+    #[test]
+    fn from_params_only_filters_on_source_fields() {
+        let fields = vec![SourceField {
+            name: "name".to_string(),
+            field_type: FieldType::String,
+        }];
+        let params = HashMap::from([
+            ("name".to_string(), "Main Street".to_string()),
+            ("limit".to_string(), "5".to_string()),
+        ]);
+        let query = FeatureQuery::from_params(&params, &fields).unwrap();
+        assert_eq!(
+            query.filters,
+            vec![("name".to_string(), "Main Street".to_string())]
+        );
+        assert_eq!(query.limit, 5);
+
+        let params = HashMap::from([("limt".to_string(), "5".to_string())]);
+        assert_eq!(
+            FeatureQuery::from_params(&params, &fields).unwrap_err(),
+            "unknown query parameters: limt"
+        );
+    }
+
+    #[tokio::test]
+    async fn connections_are_listed_once_with_their_connector_type() {
+        let database = InMemoryDatabase::new().await.unwrap();
+        let workspace = Workspace {
+            id: "workspace".to_string(),
+            name: "Workspace".to_string(),
+            owner: "owner".to_string(),
+            created_at: 0,
+            active: true,
+        };
+        for (id, config) in [
+            (
+                "parcels",
+                serde_json::json!({ "type": "geopackage", "path": "/data/parcels.gpkg" }),
+            ),
+            (
+                "basemaps",
+                serde_json::json!({ "type": "tile_archive", "directory": "/data/tiles" }),
+            ),
+        ] {
+            Connection {
+                id: id.to_string(),
+                name: format!("{} data", id),
+                config: serde_json::from_value(config).unwrap(),
+            }
+            .create_record(&database)
+            .await
+            .unwrap();
+        }
+        for access_config in [
+            ConnectionAccessConfig::ReadOnly("a".to_string()),
+            ConnectionAccessConfig::ReadWrite("b".to_string()),
+        ] {
+            ConnectionAccess {
+                connection_id: "parcels".to_string(),
+                workspace_id: workspace.id.clone(),
+                access_config,
+            }
+            .create_record(&database)
+            .await
+            .unwrap();
+        }
+
+        let responses: Vec<ConnectionResponse> = Connection::get_all(&database, &workspace)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(ConnectionResponse::from)
+            .collect();
+        assert_eq!(responses.len(), 1);
+        assert_eq!(responses[0].id, "parcels");
+        assert_eq!(responses[0].name, "parcels data");
+        assert_eq!(responses[0].connector_type, "geopackage");
+    }
+}
@@ -0,0 +1,271 @@
+use crate::connector::PostgresConnection;
+use anyhow::{anyhow, Result};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Gpkg,
+    GeoJson,
+    Shapefile,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn from_str(format: &str) -> Result<Self, String> {
+        match format.to_lowercase().as_str() {
+            "gpkg" => Ok(ExportFormat::Gpkg),
+            "geojson" => Ok(ExportFormat::GeoJson),
+            "shp" => Ok(ExportFormat::Shapefile),
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(format!("Invalid export format: {}", format)),
+        }
+    }
+
+    // Extension of the file sent to the client. Shapefiles are several files so
+    // they are sent as a zip.
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpkg => "gpkg",
+            ExportFormat::GeoJson => "geojson",
+            ExportFormat::Shapefile => "zip",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Gpkg => "application/geopackage+sqlite3",
+            ExportFormat::GeoJson => "application/geo+json",
+            ExportFormat::Shapefile => "application/zip",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+}
+
+// What to export from a source. The bbox is given in EPSG:4326 and the output is
+// written in the crs SRID. Exports of more than max_features are refused.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub bbox: Option<[f64; 4]>,
+    pub crs: i32,
+    pub max_features: usize,
+}
+
+// Feature limit of an export, from GW_EXPORT_MAX_FEATURES
+pub fn max_export_features() -> usize {
+    std::env::var("GW_EXPORT_MAX_FEATURES")
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(1_000_000)
+}
+
+#[derive(Debug)]
+pub struct ExportTooLargeError {
+    pub max_features: usize,
+}
+
+impl std::fmt::Display for ExportTooLargeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "export is larger than {} features, narrow it with a bbox",
+            self.max_features
+        )
+    }
+}
+
+impl std::error::Error for ExportTooLargeError {}
+
+// Name used for files written from a source, safe to use in a path
+pub fn export_file_stem(source_name: &str) -> String {
+    source_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// Write the result of a PostGIS query to a file with DuckDB, which reads the
+// rows through its postgres extension and writes them with the spatial extension,
+// as done for uploads by duckdb_postgis. The query must return the geometry as
+// WKB in a column named geom. Blocking, so run it off the async workers.
+pub fn export_postgis_query(
+    connection: &PostgresConnection,
+    query: &str,
+    file_stem: &str,
+    options: &ExportOptions,
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    let conn = open_duckdb(&["postgres"])?;
+    conn.execute_batch(&format!(
+        "ATTACH {} AS pg (TYPE POSTGRES, READ_ONLY);",
+        sql_literal(&postgres_conninfo(connection))
+    ))
+    .map_err(|e| anyhow!("Failed to attach PostGIS database: {}", e))?;
+
+    let select = format!(
+        "SELECT * REPLACE (ST_GeomFromWKB(geom) AS geom) FROM postgres_query('pg', {})",
+        sql_literal(query)
+    );
+    export_duckdb_query(&conn, &select, file_stem, options, output_dir)
+}
+
+// DuckDB extensions used to read and write files
+const DUCKDB_EXTENSIONS: &[&str] = &["spatial", "postgres"];
+
+// Install the DuckDB extensions into the local extension directory. Installing
+// downloads them, so it is done once at startup and requests only load them.
+pub fn install_duckdb_extensions() -> Result<()> {
+    let conn = duckdb::Connection::open_in_memory()
+        .map_err(|e| anyhow!("Failed to open DuckDB: {}", e))?;
+    let install = DUCKDB_EXTENSIONS
+        .iter()
+        .map(|name| format!("INSTALL {name};"))
+        .collect::<String>();
+    conn.execute_batch(&install)
+        .map_err(|e| anyhow!("Failed to install DuckDB extensions: {}", e))
+}
+
+// An in-memory DuckDB with the spatial extension and any others given loaded
+pub fn open_duckdb(extensions: &[&str]) -> Result<duckdb::Connection> {
+    let conn = duckdb::Connection::open_in_memory()
+        .map_err(|e| anyhow!("Failed to open DuckDB: {}", e))?;
+    let load = std::iter::once(&"spatial")
+        .chain(extensions)
+        .map(|name| format!("LOAD {name};"))
+        .collect::<String>();
+    conn.execute_batch(&load)
+        .map_err(|e| anyhow!("Failed to load DuckDB extensions: {}", e))?;
+    Ok(conn)
+}
+
+// Write the result of a DuckDB query to a file. The query must return the
+// geometry, already in the export CRS, in a GEOMETRY column named geom.
+// Blocking, so run it off the async workers.
+pub fn export_duckdb_query(
+    conn: &duckdb::Connection,
+    query: &str,
+    file_stem: &str,
+    options: &ExportOptions,
+    output_dir: &Path,
+) -> Result<PathBuf> {
+    // One feature over the limit is enough to tell the export is too large
+    let select = match options.format {
+        ExportFormat::Csv => format!(
+            "SELECT * REPLACE (ST_AsText(geom) AS geom) FROM ({}) LIMIT {}",
+            query,
+            options.max_features + 1
+        ),
+        _ => format!(
+            "SELECT * FROM ({}) LIMIT {}",
+            query,
+            options.max_features + 1
+        ),
+    };
+
+    // Shapefile parts are written to their own directory and zipped afterwards
+    let target_dir = match options.format {
+        ExportFormat::Shapefile => output_dir.join("shp"),
+        _ => output_dir.to_path_buf(),
+    };
+    std::fs::create_dir_all(&target_dir)?;
+
+    let extension = match options.format {
+        ExportFormat::Shapefile => "shp",
+        format => format.file_extension(),
+    };
+    let target = target_dir.join(format!("{}.{}", file_stem, extension));
+    let srs = format!("EPSG:{}", options.crs);
+    let copy_options = match options.format {
+        ExportFormat::Gpkg => format!("FORMAT GDAL, DRIVER 'GPKG', SRS {}", sql_literal(&srs)),
+        ExportFormat::GeoJson => {
+            format!("FORMAT GDAL, DRIVER 'GeoJSON', SRS {}", sql_literal(&srs))
+        }
+        ExportFormat::Shapefile => format!(
+            "FORMAT GDAL, DRIVER 'ESRI Shapefile', SRS {}",
+            sql_literal(&srs)
+        ),
+        ExportFormat::Csv => "FORMAT CSV, HEADER".to_string(),
+        ExportFormat::Parquet => "FORMAT PARQUET".to_string(),
+    };
+
+    let written = conn
+        .execute(
+            &format!(
+                "COPY ({}) TO {} ({});",
+                select,
+                sql_literal(&target.to_string_lossy()),
+                copy_options
+            ),
+            duckdb::params![],
+        )
+        .map_err(|e| anyhow!("Failed to write export: {}", e))?;
+    if written > options.max_features {
+        return Err(ExportTooLargeError {
+            max_features: options.max_features,
+        }
+        .into());
+    }
+
+    match options.format {
+        ExportFormat::Shapefile => {
+            let archive = output_dir.join(format!("{}.zip", file_stem));
+            zip_directory(&target_dir, &archive)?;
+            Ok(archive)
+        }
+        _ => Ok(target),
+    }
+}
+
+// Zip the files of a directory, e.g. the .shp, .shx, .dbf and .prj of a shapefile
+fn zip_directory(dir: &Path, archive: &Path) -> Result<()> {
+    let mut zip = ZipWriter::new(File::create(archive)?);
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .ok_or_else(|| anyhow!("Invalid export file name"))?;
+        zip.start_file(name, SimpleFileOptions::default())?;
+        std::io::copy(&mut File::open(&path)?, &mut zip)?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+// libpq connection string for the DuckDB postgres extension
+fn postgres_conninfo(connection: &PostgresConnection) -> String {
+    let is_local = std::env::var("GW_LOCAL")
+        .map(|val| val == "true")
+        .unwrap_or(false);
+    let value = |v: &str| format!("'{}'", v.replace('\\', "\\\\").replace('\'', "\\'"));
+    format!(
+        "host={} port={} dbname={} user={} password={} sslmode={}",
+        value(&connection.host),
+        connection.port,
+        value(&connection.database),
+        value(&connection.username),
+        value(&connection.password),
+        if is_local { "disable" } else { "require" }
+    )
+}
+
+pub(crate) fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
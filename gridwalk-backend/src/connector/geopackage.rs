@@ -0,0 +1,716 @@
+use crate::connector::{
+    buffered_tile_bounds, check_transform, encode_tile, export_duckdb_query, export_file_stem,
+    open_duckdb, quote_ident, sql_literal, transform_bbox, transform_geometry, ExportOptions,
+    FeaturePage, FeatureQuery, FieldType, GeoConnector, GeometryType, SourceField,
+    SourceNotFoundError, TileFeature, UnknownFieldsError, MAX_FEATURE_LIMIT, MAX_TILE_FEATURES,
+};
+use crate::srid_from_wkt;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use geo_types::Geometry;
+use geozero::mvt::TileValue;
+use geozero::wkb::GpkgWkb;
+use geozero::{ToGeo, ToJson};
+use rusqlite::types::{Value, ValueRef};
+use rusqlite::{params_from_iter, Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+// Connections kept open between requests
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct GeoPackageConnection {
+    // Path of the .gpkg file on the server
+    pub path: String,
+}
+
+// Read-only connector over a GeoPackage file. Each feature table is a source,
+// shared by every workspace with access to the connection, so namespaces are
+// not used. Tables must be stored in EPSG:4326 or EPSG:3857, and tiles and bbox
+// filters need an R-tree spatial index. The file is read when the connector is
+// built and is expected not to change while the server runs.
+pub struct GeoPackageConnector {
+    path: PathBuf,
+    tables: BTreeMap<String, Arc<FeatureTable>>,
+    idle: Arc<Mutex<Vec<Connection>>>,
+}
+
+impl GeoPackageConnector {
+    pub fn new(connection: GeoPackageConnection) -> Result<Self> {
+        let path = PathBuf::from(connection.path);
+        if !path.is_file() {
+            return Err(anyhow!("GeoPackage not found: {}", path.display()));
+        }
+        let conn = open_geopackage(&path)?;
+        let tables = load_tables(&conn)?;
+        Ok(GeoPackageConnector {
+            path,
+            tables,
+            idle: Arc::new(Mutex::new(vec![conn])),
+        })
+    }
+
+    fn table(&self, source_name: &str) -> Result<Arc<FeatureTable>> {
+        self.tables.get(source_name).cloned().ok_or_else(|| {
+            SourceNotFoundError {
+                source_name: source_name.to_string(),
+            }
+            .into()
+        })
+    }
+
+    // SQLite is blocking, so calls run on a blocking thread with a connection
+    // taken from the idle ones or opened for the call
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T> + Send + 'static,
+    {
+        let path = self.path.clone();
+        let idle = self.idle.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = match idle.lock().unwrap().pop() {
+                Some(conn) => conn,
+                None => open_geopackage(&path)?,
+            };
+            let result = f(&conn);
+            let mut idle = idle.lock().unwrap();
+            if idle.len() < MAX_IDLE_CONNECTIONS {
+                idle.push(conn);
+            }
+            result
+        })
+        .await?
+    }
+}
+
+fn open_geopackage(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| anyhow!("Failed to open GeoPackage {}: {}", path.display(), e))
+}
+
+// Read every feature table, failing if one is in a CRS tiles cannot be made from
+fn load_tables(conn: &Connection) -> Result<BTreeMap<String, Arc<FeatureTable>>> {
+    let mut stmt = conn
+        .prepare("SELECT table_name FROM gpkg_contents WHERE data_type = 'features'")
+        .map_err(|e| anyhow!("Not a GeoPackage: {}", e))?;
+    let names = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let mut tables = BTreeMap::new();
+    for name in names {
+        let table = FeatureTable::load(conn, &name)?;
+        check_table_srid(&table.name, table.srid)?;
+        tables.insert(name, Arc::new(table));
+    }
+    Ok(tables)
+}
+
+fn check_table_srid(table_name: &str, srid: i32) -> Result<()> {
+    match srid {
+        4326 | 3857 => Ok(()),
+        0 => Err(anyhow!(
+            "The CRS of GeoPackage table {} is not known, only EPSG:4326 and EPSG:3857 are supported",
+            table_name
+        )),
+        _ => Err(anyhow!(
+            "GeoPackage table {} is in EPSG:{}, only EPSG:4326 and EPSG:3857 are supported",
+            table_name,
+            srid
+        )),
+    }
+}
+
+// A column of a feature table as described by PRAGMA table_info
+struct TableColumn {
+    name: String,
+    declared_type: String,
+}
+
+impl TableColumn {
+    // Type of the column once encoded in a tile, None if it is left out
+    fn field_type(&self) -> Option<FieldType> {
+        let declared = self.declared_type.to_uppercase();
+        match declared.split('(').next().unwrap_or_default() {
+            "BOOLEAN" => Some(FieldType::Boolean),
+            "TINYINT" | "SMALLINT" | "MEDIUMINT" | "INT" | "INTEGER" | "FLOAT" | "DOUBLE"
+            | "REAL" => Some(FieldType::Number),
+            "TEXT" | "DATE" | "DATETIME" => Some(FieldType::String),
+            _ => None,
+        }
+    }
+
+    fn tile_value(&self, value: ValueRef) -> Option<TileValue> {
+        match (value, self.field_type()?) {
+            (ValueRef::Integer(i), FieldType::Boolean) => Some(TileValue::Bool(i != 0)),
+            (ValueRef::Integer(i), _) => Some(TileValue::Int(i)),
+            (ValueRef::Real(f), _) => Some(TileValue::Double(f)),
+            (ValueRef::Text(text), _) => {
+                Some(TileValue::Str(String::from_utf8_lossy(text).into_owned()))
+            }
+            _ => None,
+        }
+    }
+
+    fn json_value(&self, value: ValueRef) -> serde_json::Value {
+        match (value, self.field_type()) {
+            (ValueRef::Integer(i), Some(FieldType::Boolean)) => (i != 0).into(),
+            (ValueRef::Integer(i), _) => i.into(),
+            (ValueRef::Real(f), _) => f.into(),
+            (ValueRef::Text(text), _) => String::from_utf8_lossy(text).into(),
+            _ => serde_json::Value::Null,
+        }
+    }
+}
+
+// A feature table and what gpkg_geometry_columns says about it
+struct FeatureTable {
+    name: String,
+    geometry_column: String,
+    geometry_type: String,
+    // EPSG code of the table's CRS, 0 if it is not known
+    srid: i32,
+    columns: Vec<TableColumn>,
+    rtree: Option<String>,
+}
+
+impl FeatureTable {
+    fn load(conn: &Connection, source_name: &str) -> Result<Self> {
+        let (geometry_column, geometry_type, srs_id, organization, coordsys_id, definition) = conn
+            .query_row(
+                "SELECT g.column_name, g.geometry_type_name, g.srs_id,
+                    s.organization, s.organization_coordsys_id, s.definition
+                FROM gpkg_geometry_columns g
+                LEFT JOIN gpkg_spatial_ref_sys s ON s.srs_id = g.srs_id
+                WHERE g.table_name = ?1",
+                [source_name],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, i32>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                        row.get::<_, Option<i32>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                    ))
+                },
+            )
+            .optional()?
+            .ok_or_else(|| SourceNotFoundError {
+                source_name: source_name.to_string(),
+            })?;
+
+        // srs_id 0 is the GeoPackage's undefined geographic system, taken as 4326
+        let srid = match (srs_id, organization, coordsys_id) {
+            (0, _, _) => 4326,
+            (_, Some(organization), Some(id)) if organization.eq_ignore_ascii_case("EPSG") => id,
+            _ => definition
+                .as_deref()
+                .and_then(srid_from_wkt)
+                .unwrap_or_default(),
+        };
+
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote_ident(source_name)))?;
+        let columns = stmt
+            .query_map([], |row| {
+                Ok(TableColumn {
+                    name: row.get(1)?,
+                    declared_type: row.get(2)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let rtree_name = format!("rtree_{}_{}", source_name, geometry_column);
+        let rtree = conn
+            .query_row(
+                "SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [&rtree_name],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+
+        Ok(FeatureTable {
+            name: source_name.to_string(),
+            geometry_column,
+            geometry_type,
+            srid,
+            columns,
+            rtree,
+        })
+    }
+
+    fn attribute_columns(&self) -> impl Iterator<Item = &TableColumn> {
+        self.columns
+            .iter()
+            .filter(move |c| c.name != self.geometry_column)
+    }
+
+    // Requested columns must be attributes of the table
+    fn check_fields<'a>(&self, names: impl Iterator<Item = &'a String>) -> Result<()> {
+        let mut unknown: Vec<String> = names
+            .filter(|name| !self.attribute_columns().any(|c| &c.name == *name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(UnknownFieldsError { fields: unknown }.into());
+        }
+        Ok(())
+    }
+
+    // Condition selecting the rows whose envelope meets a bbox in the table's CRS,
+    // looked up in the table's R-tree
+    fn bbox_condition(&self, [west, south, east, north]: [f64; 4]) -> Result<(String, Vec<Value>)> {
+        let rtree = self.rtree.as_ref().ok_or_else(|| {
+            anyhow!(
+                "GeoPackage table {} has no spatial index to filter by bbox",
+                self.name
+            )
+        })?;
+        Ok((
+            format!(
+                "t.rowid IN (SELECT id FROM {} WHERE minx <= ? AND maxx >= ? AND miny <= ? AND maxy >= ?)",
+                quote_ident(rtree)
+            ),
+            vec![
+                Value::Real(east),
+                Value::Real(west),
+                Value::Real(north),
+                Value::Real(south),
+            ],
+        ))
+    }
+}
+
+fn decode_geometry(value: ValueRef) -> Result<Option<Geometry<f64>>> {
+    match value.as_blob_or_null()? {
+        Some(blob) => Ok(Some(
+            GpkgWkb(blob)
+                .to_geo()
+                .map_err(|e| anyhow!("Failed to read geometry: {}", e))?,
+        )),
+        None => Ok(None),
+    }
+}
+
+fn geometry_type_of(geometry: &Geometry<f64>) -> GeometryType {
+    match geometry {
+        Geometry::Point(_) => GeometryType::Point,
+        Geometry::LineString(_) | Geometry::Line(_) => GeometryType::LineString,
+        Geometry::Polygon(_) | Geometry::Rect(_) | Geometry::Triangle(_) => GeometryType::Polygon,
+        Geometry::MultiPoint(_) => GeometryType::MultiPoint,
+        Geometry::MultiLineString(_) => GeometryType::MultiLineString,
+        Geometry::MultiPolygon(_) => GeometryType::MultiPolygon,
+        Geometry::GeometryCollection(_) => GeometryType::GeometryCollection,
+    }
+}
+
+fn read_only_error() -> anyhow::Error {
+    anyhow!("GeoPackage connections are read only")
+}
+
+#[async_trait]
+impl GeoConnector for GeoPackageConnector {
+    async fn connect(&mut self) -> Result<()> {
+        self.run(|conn| {
+            conn.query_row("SELECT count(*) FROM gpkg_contents", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| anyhow!("Not a GeoPackage: {}", e))?;
+            Ok(())
+        })
+        .await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Every workspace sees the same tables, so there is nothing to create
+    async fn create_namespace(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_sources(&self, _namespace: &str) -> Result<Vec<String>> {
+        Ok(self.tables.keys().cloned().collect())
+    }
+
+    async fn delete_source(&self, _namespace: &str, _source_name: &str) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn get_tile(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        fields: Option<&[String]>,
+    ) -> Result<Vec<u8>> {
+        let table = self.table(source_name)?;
+        let fields = fields.map(|fields| fields.to_vec());
+        self.run(move |conn| {
+            if let Some(fields) = &fields {
+                table.check_fields(fields.iter())?;
+            }
+            let attributes: Vec<&TableColumn> = table
+                .attribute_columns()
+                .filter(|c| c.field_type().is_some())
+                .filter(|c| {
+                    fields
+                        .as_ref()
+                        .is_none_or(|fields| fields.contains(&c.name))
+                })
+                .collect();
+
+            // The buffered tile is moved into the table's CRS to search the R-tree
+            let bbox = transform_bbox(buffered_tile_bounds(z, x, y), 3857, table.srid)?;
+            let (condition, params) = table.bbox_condition(bbox)?;
+            let query = format!(
+                "SELECT t.{}{} FROM {} t WHERE {} LIMIT {}",
+                quote_ident(&table.geometry_column),
+                attributes
+                    .iter()
+                    .map(|c| format!(", t.{}", quote_ident(&c.name)))
+                    .collect::<String>(),
+                quote_ident(&table.name),
+                condition,
+                MAX_TILE_FEATURES
+            );
+
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(params_from_iter(params))?;
+            let mut features = Vec::new();
+            while let Some(row) = rows.next()? {
+                // Geometries that cannot be read are left out of the tile
+                let Ok(Some(mut geometry)) = decode_geometry(row.get_ref(0)?) else {
+                    continue;
+                };
+                transform_geometry(&mut geometry, table.srid, 3857)?;
+                let mut properties = Vec::new();
+                for (i, column) in attributes.iter().enumerate() {
+                    if let Some(value) = column.tile_value(row.get_ref(i + 1)?) {
+                        properties.push((column.name.clone(), value));
+                    }
+                }
+                features.push(TileFeature {
+                    geometry,
+                    properties,
+                });
+            }
+            encode_tile(&table.name, z, x, y, features)
+        })
+        .await
+    }
+
+    async fn get_fields(&self, _namespace: &str, source_name: &str) -> Result<Vec<SourceField>> {
+        let table = self.table(source_name)?;
+        Ok(table
+            .attribute_columns()
+            .filter_map(|c| {
+                c.field_type().map(|field_type| SourceField {
+                    name: c.name.clone(),
+                    field_type,
+                })
+            })
+            .collect())
+    }
+
+    // The extent of the R-tree, or failing that the one recorded in gpkg_contents
+    async fn get_bounds(&self, _namespace: &str, source_name: &str) -> Result<Option<[f64; 4]>> {
+        let table = self.table(source_name)?;
+        self.run(move |conn| {
+            let read_extent = |row: &rusqlite::Row| {
+                Ok([
+                    row.get::<_, Option<f64>>(0)?,
+                    row.get::<_, Option<f64>>(1)?,
+                    row.get::<_, Option<f64>>(2)?,
+                    row.get::<_, Option<f64>>(3)?,
+                ])
+            };
+            let extent = match &table.rtree {
+                Some(rtree) => conn.query_row(
+                    &format!(
+                        "SELECT min(minx), min(miny), max(maxx), max(maxy) FROM {}",
+                        quote_ident(rtree)
+                    ),
+                    [],
+                    read_extent,
+                )?,
+                None => conn.query_row(
+                    "SELECT min_x, min_y, max_x, max_y FROM gpkg_contents WHERE table_name = ?1",
+                    [&table.name],
+                    read_extent,
+                )?,
+            };
+            match extent {
+                [Some(west), Some(south), Some(east), Some(north)] => Ok(Some(transform_bbox(
+                    [west, south, east, north],
+                    table.srid,
+                    4326,
+                )?)),
+                _ => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn query_features(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        query: &FeatureQuery,
+    ) -> Result<FeaturePage> {
+        let table = self.table(source_name)?;
+        let query = query.clone();
+        self.run(move |conn| {
+            table.check_fields(
+                query
+                    .filters
+                    .iter()
+                    .map(|(name, _)| name)
+                    .chain(query.properties.iter().flatten()),
+            )?;
+            check_transform(table.srid, query.crs)?;
+
+            let mut conditions = Vec::new();
+            let mut params = Vec::new();
+            if let Some(bbox) = query.bbox {
+                let (condition, bbox_params) =
+                    table.bbox_condition(transform_bbox(bbox, 4326, table.srid)?)?;
+                conditions.push(condition);
+                params.extend(bbox_params);
+            }
+            for (name, value) in &query.filters {
+                conditions.push(format!("CAST(t.{} AS TEXT) = ?", quote_ident(name)));
+                params.push(Value::Text(value.clone()));
+            }
+            let where_clause = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", conditions.join(" AND "))
+            };
+            let from = format!("{} t {}", quote_ident(&table.name), where_clause);
+
+            let number_matched: i64 = conn.query_row(
+                &format!("SELECT count(*) FROM {}", from),
+                params_from_iter(params.iter()),
+                |row| row.get(0),
+            )?;
+
+            let properties: Vec<&TableColumn> = table
+                .attribute_columns()
+                .filter(|c| {
+                    query
+                        .properties
+                        .as_ref()
+                        .is_none_or(|properties| properties.contains(&c.name))
+                })
+                .collect();
+            let features_query = format!(
+                "SELECT t.{}{} FROM {} ORDER BY t.rowid LIMIT {} OFFSET {}",
+                quote_ident(&table.geometry_column),
+                properties
+                    .iter()
+                    .map(|c| format!(", t.{}", quote_ident(&c.name)))
+                    .collect::<String>(),
+                from,
+                query.limit.clamp(0, MAX_FEATURE_LIMIT),
+                query.offset.max(0),
+            );
+            let mut stmt = conn.prepare(&features_query)?;
+            let mut rows = stmt.query(params_from_iter(params.iter()))?;
+            let mut features = Vec::new();
+            while let Some(row) = rows.next()? {
+                let geometry = match decode_geometry(row.get_ref(0)?)? {
+                    Some(mut geometry) => {
+                        transform_geometry(&mut geometry, table.srid, query.crs)?;
+                        serde_json::from_str(&geometry.to_json()?)?
+                    }
+                    None => serde_json::Value::Null,
+                };
+                let mut feature_properties = serde_json::Map::new();
+                for (i, column) in properties.iter().enumerate() {
+                    feature_properties
+                        .insert(column.name.clone(), column.json_value(row.get_ref(i + 1)?));
+                }
+                features.push(serde_json::json!({
+                    "type": "Feature",
+                    "geometry": geometry,
+                    "properties": feature_properties,
+                }));
+            }
+
+            Ok(FeaturePage {
+                features,
+                number_matched,
+            })
+        })
+        .await
+    }
+
+    // Exports are read and written by DuckDB, which can reproject into any CRS
+    async fn count_source(&self, _namespace: &str, source_name: &str) -> Result<u64> {
+        let table = self.table(source_name)?;
+        self.run(move |conn| {
+            let count: i64 = conn.query_row(
+                &format!("SELECT count(*) FROM {}", quote_ident(&table.name)),
+                [],
+                |row| row.get(0),
+            )?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn export_source(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        options: &ExportOptions,
+        output_dir: &Path,
+    ) -> Result<PathBuf> {
+        let table = self.table(source_name)?;
+
+        let source_crs = sql_literal(&format!("EPSG:{}", table.srid));
+        let geom = if table.srid == options.crs {
+            "geom".to_string()
+        } else {
+            format!(
+                "ST_Transform(geom, {}, {}, always_xy := true)",
+                source_crs,
+                sql_literal(&format!("EPSG:{}", options.crs))
+            )
+        };
+        let where_clause = options
+            .bbox
+            .map(|[west, south, east, north]| {
+                format!(
+                    "WHERE ST_Intersects(geom, ST_Transform(ST_MakeEnvelope({}, {}, {}, {}), 'EPSG:4326', {}, always_xy := true))",
+                    west, south, east, north, source_crs
+                )
+            })
+            .unwrap_or_default();
+        let query = format!(
+            "SELECT * REPLACE ({} AS geom) FROM ST_Read({}, layer := {}) {}",
+            geom,
+            sql_literal(&self.path.to_string_lossy()),
+            sql_literal(&table.name),
+            where_clause
+        );
+
+        let file_stem = export_file_stem(&table.name);
+        let options = options.clone();
+        let output_dir = output_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let conn = open_duckdb(&[])?;
+            export_duckdb_query(&conn, &query, &file_stem, &options, &output_dir)
+        })
+        .await?
+    }
+
+    // Taken from gpkg_geometry_columns, or from the first geometry for tables
+    // declared with the generic GEOMETRY type
+    async fn get_geometry_type(&self, _namespace: &str, source_name: &str) -> Result<GeometryType> {
+        let table = self.table(source_name)?;
+        self.run(
+            move |conn| match table.geometry_type.to_uppercase().as_str() {
+                "POINT" => Ok(GeometryType::Point),
+                "LINESTRING" => Ok(GeometryType::LineString),
+                "POLYGON" => Ok(GeometryType::Polygon),
+                "MULTIPOINT" => Ok(GeometryType::MultiPoint),
+                "MULTILINESTRING" => Ok(GeometryType::MultiLineString),
+                "MULTIPOLYGON" => Ok(GeometryType::MultiPolygon),
+                "GEOMETRYCOLLECTION" => Ok(GeometryType::GeometryCollection),
+                _ => {
+                    let geom = quote_ident(&table.geometry_column);
+                    let geometry = conn
+                        .query_row(
+                            &format!(
+                                "SELECT {geom} FROM {} WHERE {geom} IS NOT NULL LIMIT 1",
+                                quote_ident(&table.name)
+                            ),
+                            [],
+                            |row| row.get::<_, Vec<u8>>(0),
+                        )
+                        .optional()?
+                        .ok_or_else(|| anyhow!("Source {} has no geometries", table.name))?;
+                    decode_geometry(ValueRef::Blob(&geometry))?
+                        .as_ref()
+                        .map(geometry_type_of)
+                        .ok_or_else(|| anyhow!("Source {} has no geometries", table.name))
+                }
+            },
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_geopackage(path: &Path, srs_id: i32) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(&format!(
+            "CREATE TABLE gpkg_spatial_ref_sys (srs_name TEXT, srs_id INTEGER PRIMARY KEY,
+                organization TEXT, organization_coordsys_id INTEGER, definition TEXT);
+            INSERT INTO gpkg_spatial_ref_sys VALUES ('test', {srs_id}, 'EPSG', {srs_id}, '');
+            CREATE TABLE gpkg_contents (table_name TEXT PRIMARY KEY, data_type TEXT,
+                min_x REAL, min_y REAL, max_x REAL, max_y REAL);
+            INSERT INTO gpkg_contents (table_name, data_type) VALUES ('sites', 'features');
+            CREATE TABLE gpkg_geometry_columns (table_name TEXT, column_name TEXT,
+                geometry_type_name TEXT, srs_id INTEGER);
+            INSERT INTO gpkg_geometry_columns VALUES ('sites', 'geom', 'POINT', {srs_id});
+            CREATE TABLE sites (fid INTEGER PRIMARY KEY, geom BLOB, name TEXT, area REAL);"
+        ))
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_tables_when_the_connector_is_built() {
+        let path = std::env::temp_dir().join(format!("gw-{}.gpkg", uuid::Uuid::new_v4()));
+        write_geopackage(&path, 4326);
+        let connector = GeoPackageConnector::new(GeoPackageConnection {
+            path: path.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(connector.list_sources("").await.unwrap(), vec!["sites"]);
+        let fields: Vec<String> = connector
+            .get_fields("", "sites")
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|field| field.name)
+            .collect();
+        assert_eq!(fields, vec!["fid", "name", "area"]);
+        let missing = connector.get_fields("", "roads").await.unwrap_err();
+        assert!(missing.is::<SourceNotFoundError>());
+    }
+
+    #[test]
+    fn rejects_tables_in_other_crss() {
+        let path = std::env::temp_dir().join(format!("gw-{}.gpkg", uuid::Uuid::new_v4()));
+        write_geopackage(&path, 27700);
+        let result = GeoPackageConnector::new(GeoPackageConnection {
+            path: path.to_string_lossy().into_owned(),
+        });
+        std::fs::remove_file(&path).unwrap();
+
+        let err = result.err().unwrap().to_string();
+        assert!(err.contains("EPSG:27700"), "{}", err);
+        assert!(check_table_srid("sites", 3857).is_ok());
+        assert!(check_table_srid("sites", 0).is_err());
+    }
+}
@@ -0,0 +1,827 @@
+use crate::connector::{
+    buffered_tile_bounds, encode_tile, export_duckdb_query, export_file_stem, open_duckdb,
+    quote_ident, sql_literal, ExportOptions, FeaturePage, FeatureQuery, FieldType, GeoConnector,
+    GeometryType, SourceField, SourceNotFoundError, TileFeature, UnknownFieldsError,
+    MAX_FEATURE_LIMIT, MAX_TILE_FEATURES,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use duckdb::types::Value;
+use duckdb::OptionalExt;
+use geozero::mvt::TileValue;
+use geozero::wkb::Wkb;
+use geozero::ToGeo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DuckDbConnection {
+    // Directory on the server holding the GeoParquet and Parquet files
+    pub directory: String,
+}
+
+// Read-only connector querying a directory of GeoParquet and Parquet files with
+// DuckDB. Each file is a source named after its file stem, shared by every
+// workspace with access to the connection, so namespaces are not used. Features
+// are selected and reprojected by DuckDB and tiles are encoded from the results.
+// A file is described when first queried, and again once it has changed.
+pub struct DuckDbConnector {
+    directory: PathBuf,
+    // Each request runs on its own clone of this connection, which shares its
+    // database and loaded extensions
+    conn: Mutex<duckdb::Connection>,
+    sources: SourceCache,
+}
+
+impl DuckDbConnector {
+    pub fn new(connection: DuckDbConnection) -> Result<Self> {
+        let directory = PathBuf::from(connection.directory);
+        if !directory.is_dir() {
+            return Err(anyhow!(
+                "Parquet directory not found: {}",
+                directory.display()
+            ));
+        }
+        Ok(DuckDbConnector {
+            directory,
+            conn: Mutex::new(open_duckdb(&[])?),
+            sources: SourceCache::default(),
+        })
+    }
+
+    fn parquet_file(&self, source_name: &str) -> Result<PathBuf> {
+        let not_found = || -> anyhow::Error {
+            SourceNotFoundError {
+                source_name: source_name.to_string(),
+            }
+            .into()
+        };
+        if source_name.is_empty() || source_name.contains(['/', '\\']) || source_name == ".." {
+            return Err(not_found());
+        }
+        let path = self.directory.join(format!("{}.parquet", source_name));
+        if !path.is_file() {
+            return Err(not_found());
+        }
+        Ok(path)
+    }
+
+    // DuckDB is blocking, so queries run on a blocking thread, each with a
+    // connection of its own so they do not wait on one another
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&duckdb::Connection) -> Result<T> + Send + 'static,
+    {
+        let conn = self
+            .conn
+            .lock()
+            .map_err(|_| anyhow!("DuckDB connection lock poisoned"))?
+            .try_clone()?;
+        tokio::task::spawn_blocking(move || f(&conn)).await?
+    }
+
+    // Run a query against a source, described from its file unless the file is
+    // unchanged since it was last described
+    async fn with_source<T, F>(&self, source_name: &str, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&duckdb::Connection, &ParquetSource) -> Result<T> + Send + 'static,
+    {
+        let path = self.parquet_file(source_name)?;
+        let modified = tokio::fs::metadata(&path).await?.modified()?;
+        let source = match self.sources.get(source_name, modified) {
+            Some(source) => source,
+            None => {
+                let name = source_name.to_string();
+                let source = self
+                    .run(move |conn| ParquetSource::load(conn, &name, &path))
+                    .await?;
+                self.sources.insert(source_name, modified, source)
+            }
+        };
+        self.run(move |conn| f(conn, &source)).await
+    }
+}
+
+// Sources described from their files, each with the modification time of the
+// file when it was described
+#[derive(Default)]
+struct SourceCache {
+    sources: Mutex<HashMap<String, (SystemTime, Arc<ParquetSource>)>>,
+}
+
+impl SourceCache {
+    // The source, if it was described from the file as last modified
+    fn get(&self, source_name: &str, modified: SystemTime) -> Option<Arc<ParquetSource>> {
+        self.sources
+            .lock()
+            .unwrap()
+            .get(source_name)
+            .filter(|(described, _)| *described == modified)
+            .map(|(_, source)| source.clone())
+    }
+
+    fn insert(
+        &self,
+        source_name: &str,
+        modified: SystemTime,
+        source: ParquetSource,
+    ) -> Arc<ParquetSource> {
+        let source = Arc::new(source);
+        self.sources
+            .lock()
+            .unwrap()
+            .insert(source_name.to_string(), (modified, source.clone()));
+        source
+    }
+}
+
+// A column of a Parquet file as described by DuckDB
+struct ParquetColumn {
+    name: String,
+    column_type: String,
+}
+
+impl ParquetColumn {
+    // Type the column is cast to for tiles, None if it is left out
+    fn tile_cast(&self) -> Option<&'static str> {
+        let column_type = self.column_type.to_uppercase();
+        match column_type.split('(').next().unwrap_or_default() {
+            "BOOLEAN" => Some("BOOLEAN"),
+            "TINYINT" | "SMALLINT" | "INTEGER" | "BIGINT" | "UTINYINT" | "USMALLINT"
+            | "UINTEGER" => Some("BIGINT"),
+            "UBIGINT" | "HUGEINT" | "UHUGEINT" | "FLOAT" | "DOUBLE" | "DECIMAL" => Some("DOUBLE"),
+            "VARCHAR" | "DATE" | "TIME" | "TIMESTAMP" | "TIMESTAMP WITH TIME ZONE" | "UUID" => {
+                Some("VARCHAR")
+            }
+            _ => None,
+        }
+    }
+
+    fn field_type(&self) -> Option<FieldType> {
+        match self.tile_cast()? {
+            "BOOLEAN" => Some(FieldType::Boolean),
+            "VARCHAR" => Some(FieldType::String),
+            _ => Some(FieldType::Number),
+        }
+    }
+
+    fn tile_value(&self, row: &duckdb::Row, i: usize) -> Result<Option<TileValue>> {
+        Ok(match self.tile_cast() {
+            Some("BOOLEAN") => row.get::<_, Option<bool>>(i)?.map(TileValue::Bool),
+            Some("BIGINT") => row.get::<_, Option<i64>>(i)?.map(TileValue::Int),
+            Some("DOUBLE") => row.get::<_, Option<f64>>(i)?.map(TileValue::Double),
+            Some(_) => row.get::<_, Option<String>>(i)?.map(TileValue::Str),
+            None => None,
+        })
+    }
+}
+
+// A Parquet file and what its GeoParquet metadata says about it
+struct ParquetSource {
+    name: String,
+    // Path of the file quoted as a SQL literal
+    path: String,
+    geometry_column: String,
+    // Whether the geometry column holds WKB rather than DuckDB geometries
+    wkb: bool,
+    // EPSG code of the file's CRS, 0 if it is not known
+    srid: i32,
+    geometry_types: Vec<String>,
+    // [west, south, east, north] in the file's CRS
+    bbox: Option<[f64; 4]>,
+    columns: Vec<ParquetColumn>,
+}
+
+impl ParquetSource {
+    fn load(conn: &duckdb::Connection, source_name: &str, path: &Path) -> Result<Self> {
+        let path = sql_literal(&path.to_string_lossy());
+
+        let mut stmt = conn.prepare(&format!("DESCRIBE SELECT * FROM read_parquet({})", path))?;
+        let mut rows = stmt.query(duckdb::params![])?;
+        let mut columns = Vec::new();
+        while let Some(row) = rows.next()? {
+            columns.push(ParquetColumn {
+                name: row.get(0)?,
+                column_type: row.get(1)?,
+            });
+        }
+
+        let metadata: Option<serde_json::Value> = conn
+            .query_row(
+                &format!(
+                    "SELECT decode(value) FROM parquet_kv_metadata({}) WHERE decode(key) = 'geo'",
+                    path
+                ),
+                duckdb::params![],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .and_then(|geo| serde_json::from_str(&geo).ok());
+        let primary = metadata
+            .as_ref()
+            .and_then(|geo| geo["primary_column"].as_str())
+            .filter(|name| columns.iter().any(|c| c.name == *name));
+
+        // Plain Parquet files need a GEOMETRY column or a WKB column with a usual name
+        let geometry_column = primary
+            .and_then(|name| columns.iter().find(|c| c.name == name))
+            .or_else(|| columns.iter().find(|c| c.column_type == "GEOMETRY"))
+            .or_else(|| {
+                columns.iter().find(|c| {
+                    c.column_type == "BLOB"
+                        && ["geometry", "geom", "wkb_geometry"].contains(&c.name.as_str())
+                })
+            })
+            .ok_or_else(|| anyhow!("Source {} has no geometry column", source_name))?;
+
+        let column_metadata = primary.and_then(|name| {
+            metadata
+                .as_ref()
+                .and_then(|geo| geo["columns"].get(name))
+                .cloned()
+        });
+        // GeoParquet takes a missing crs to be OGC:CRS84 and a null one as unknown.
+        // Files without the metadata are taken to be in EPSG:4326 too.
+        let srid = match column_metadata.as_ref().map(|c| c.get("crs")) {
+            Some(Some(crs)) => projjson_srid(crs).unwrap_or_default(),
+            _ => 4326,
+        };
+        let geometry_types = column_metadata
+            .as_ref()
+            .and_then(|c| c["geometry_types"].as_array().cloned())
+            .into_iter()
+            .flatten()
+            .filter_map(|t| t.as_str().map(String::from))
+            .collect();
+        let bbox = column_metadata
+            .as_ref()
+            .and_then(|c| serde_json::from_value::<[f64; 4]>(c["bbox"].clone()).ok());
+
+        Ok(ParquetSource {
+            name: source_name.to_string(),
+            path,
+            geometry_column: geometry_column.name.clone(),
+            wkb: geometry_column.column_type != "GEOMETRY",
+            srid,
+            geometry_types,
+            bbox,
+            columns,
+        })
+    }
+
+    fn table(&self) -> String {
+        format!("read_parquet({})", self.path)
+    }
+
+    fn attribute_columns(&self) -> impl Iterator<Item = &ParquetColumn> {
+        self.columns
+            .iter()
+            .filter(move |c| c.name != self.geometry_column)
+    }
+
+    // Requested columns must be attributes of the file
+    fn check_fields<'a>(&self, names: impl Iterator<Item = &'a String>) -> Result<()> {
+        let mut unknown: Vec<String> = names
+            .filter(|name| !self.attribute_columns().any(|c| &c.name == *name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            unknown.sort();
+            unknown.dedup();
+            return Err(UnknownFieldsError { fields: unknown }.into());
+        }
+        Ok(())
+    }
+
+    fn geometry(&self) -> String {
+        let column = format!("t.{}", quote_ident(&self.geometry_column));
+        if self.wkb {
+            format!("ST_GeomFromWKB({})", column)
+        } else {
+            column
+        }
+    }
+
+    // Move a geometry expression between CRSs, which needs the file's CRS known
+    fn transform(&self, expr: &str, from: i32, to: i32) -> Result<String> {
+        if from == to {
+            return Ok(expr.to_string());
+        }
+        if self.srid <= 0 {
+            return Err(anyhow!(
+                "The CRS of source {} is not known, so it cannot be reprojected",
+                self.name
+            ));
+        }
+        Ok(format!(
+            "ST_Transform({}, {}, {}, always_xy := true)",
+            expr,
+            sql_literal(&format!("EPSG:{}", from)),
+            sql_literal(&format!("EPSG:{}", to))
+        ))
+    }
+
+    // The source geometry in another CRS
+    fn geometry_in(&self, srid: i32) -> Result<String> {
+        self.transform(&self.geometry(), self.srid, srid)
+    }
+
+    // Condition selecting the rows meeting a bbox given in another CRS, with
+    // the bbox left to be bound as the parameters returned alongside it
+    fn bbox_condition(&self, bbox: [f64; 4], srid: i32) -> Result<(String, Vec<Value>)> {
+        Ok((
+            self.envelope_condition("ST_MakeEnvelope(?, ?, ?, ?)", srid)?,
+            bbox_params(bbox),
+        ))
+    }
+
+    // Condition selecting the rows meeting an envelope expression in another CRS
+    fn envelope_condition(&self, envelope: &str, srid: i32) -> Result<String> {
+        Ok(format!(
+            "ST_Intersects({}, {})",
+            self.geometry(),
+            self.transform(envelope, srid, self.srid)?
+        ))
+    }
+}
+
+fn bbox_params(bbox: [f64; 4]) -> Vec<Value> {
+    bbox.into_iter().map(Value::Double).collect()
+}
+
+// EPSG code of a PROJJSON CRS, with OGC:CRS84 taken as 4326
+fn projjson_srid(crs: &serde_json::Value) -> Option<i32> {
+    let id = &crs["id"];
+    match (id["authority"].as_str()?, &id["code"]) {
+        ("EPSG", code) => code
+            .as_i64()
+            .or_else(|| code.as_str().and_then(|c| c.parse().ok()))
+            .map(|code| code as i32),
+        ("OGC", code) if code == "CRS84" => Some(4326),
+        _ => None,
+    }
+}
+
+// GeoParquet geometry_types names, or those of ST_GeometryType, ignoring any Z or M
+fn parse_geometry_type(name: &str) -> Option<GeometryType> {
+    let name = name.split_whitespace().next()?.to_uppercase();
+    match name.as_str() {
+        "POINT" => Some(GeometryType::Point),
+        "LINESTRING" => Some(GeometryType::LineString),
+        "POLYGON" => Some(GeometryType::Polygon),
+        "MULTIPOINT" => Some(GeometryType::MultiPoint),
+        "MULTILINESTRING" => Some(GeometryType::MultiLineString),
+        "MULTIPOLYGON" => Some(GeometryType::MultiPolygon),
+        "GEOMETRYCOLLECTION" => Some(GeometryType::GeometryCollection),
+        _ => None,
+    }
+}
+
+fn read_only_error() -> anyhow::Error {
+    anyhow!("DuckDB connections are read only")
+}
+
+#[async_trait]
+impl GeoConnector for DuckDbConnector {
+    async fn connect(&mut self) -> Result<()> {
+        self.run(|conn| {
+            conn.execute_batch("SELECT 1")
+                .map_err(|e| anyhow!("DuckDB is not available: {}", e))
+        })
+        .await
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Every workspace sees the same files, so there is nothing to create
+    async fn create_namespace(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_sources(&self, _namespace: &str) -> Result<Vec<String>> {
+        let mut sources = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let is_parquet = path.extension().and_then(|e| e.to_str()) == Some("parquet");
+            if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
+                if is_parquet && entry.file_type().await?.is_file() {
+                    sources.push(stem.to_string());
+                }
+            }
+        }
+        sources.sort();
+        Ok(sources)
+    }
+
+    async fn delete_source(&self, _namespace: &str, _source_name: &str) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn get_tile(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        fields: Option<&[String]>,
+    ) -> Result<Vec<u8>> {
+        let fields = fields.map(|fields| fields.to_vec());
+        self.with_source(source_name, move |conn, source| {
+            if let Some(fields) = &fields {
+                source.check_fields(fields.iter())?;
+            }
+            let attributes: Vec<&ParquetColumn> = source
+                .attribute_columns()
+                .filter(|c| c.tile_cast().is_some())
+                .filter(|c| {
+                    fields
+                        .as_ref()
+                        .is_none_or(|fields| fields.contains(&c.name))
+                })
+                .collect();
+
+            let (condition, params) = source.bbox_condition(buffered_tile_bounds(z, x, y), 3857)?;
+            let query = format!(
+                "SELECT ST_AsWKB({}){} FROM {} t WHERE {} LIMIT {}",
+                source.geometry_in(3857)?,
+                attributes
+                    .iter()
+                    .map(|c| format!(
+                        ", CAST(t.{} AS {})",
+                        quote_ident(&c.name),
+                        c.tile_cast().unwrap_or("VARCHAR")
+                    ))
+                    .collect::<String>(),
+                source.table(),
+                condition,
+                MAX_TILE_FEATURES
+            );
+
+            let mut stmt = conn.prepare(&query)?;
+            let mut rows = stmt.query(duckdb::params_from_iter(params))?;
+            let mut features = Vec::new();
+            while let Some(row) = rows.next()? {
+                // Geometries that cannot be read are left out of the tile
+                let Some(wkb) = row.get::<_, Option<Vec<u8>>>(0)? else {
+                    continue;
+                };
+                let Ok(geometry) = Wkb(wkb).to_geo() else {
+                    continue;
+                };
+                let mut properties = Vec::new();
+                for (i, column) in attributes.iter().enumerate() {
+                    if let Some(value) = column.tile_value(row, i + 1)? {
+                        properties.push((column.name.clone(), value));
+                    }
+                }
+                features.push(TileFeature {
+                    geometry,
+                    properties,
+                });
+            }
+            encode_tile(&source.name, z, x, y, features)
+        })
+        .await
+    }
+
+    async fn get_fields(&self, _namespace: &str, source_name: &str) -> Result<Vec<SourceField>> {
+        self.with_source(source_name, |_, source| {
+            Ok(source
+                .attribute_columns()
+                .filter_map(|c| {
+                    c.field_type().map(|field_type| SourceField {
+                        name: c.name.clone(),
+                        field_type,
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    // The bbox in the GeoParquet metadata, or failing that the extent of the rows
+    async fn get_bounds(&self, _namespace: &str, source_name: &str) -> Result<Option<[f64; 4]>> {
+        self.with_source(source_name, |conn, source| {
+            let (extent, params) = match source.bbox {
+                Some(bbox) => ("ST_MakeEnvelope(?, ?, ?, ?)".to_string(), bbox_params(bbox)),
+                None => (format!(
+                    "(SELECT ST_MakeEnvelope(min(ST_XMin(g)), min(ST_YMin(g)), max(ST_XMax(g)), max(ST_YMax(g)))
+                    FROM (SELECT {} AS g FROM {} t))",
+                    source.geometry(),
+                    source.table()
+                ), Vec::new()),
+            };
+            let extent = conn.query_row(
+                &format!(
+                    "SELECT ST_XMin(e), ST_YMin(e), ST_XMax(e), ST_YMax(e) FROM (SELECT {} AS e)",
+                    source.transform(&extent, source.srid, 4326)?
+                ),
+                duckdb::params_from_iter(params),
+                |row| {
+                    Ok([
+                        row.get::<_, Option<f64>>(0)?,
+                        row.get::<_, Option<f64>>(1)?,
+                        row.get::<_, Option<f64>>(2)?,
+                        row.get::<_, Option<f64>>(3)?,
+                    ])
+                },
+            )?;
+            match extent {
+                [Some(west), Some(south), Some(east), Some(north)] => {
+                    Ok(Some([west, south, east, north]))
+                }
+                _ => Ok(None),
+            }
+        })
+        .await
+    }
+
+    async fn query_features(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        query: &FeatureQuery,
+    ) -> Result<FeaturePage> {
+        let query = query.clone();
+        self.with_source(source_name, move |conn, source| {
+            source.check_fields(
+                query
+                    .filters
+                    .iter()
+                    .map(|(name, _)| name)
+                    .chain(query.properties.iter().flatten()),
+            )?;
+
+            let mut conditions = Vec::new();
+            let mut params = Vec::new();
+            if let Some(bbox) = query.bbox {
+                let (condition, bbox_params) = source.bbox_condition(bbox, 4326)?;
+                conditions.push(condition);
+                params.extend(bbox_params);
+            }
+            for (name, value) in &query.filters {
+                conditions.push(format!("CAST(t.{} AS VARCHAR) = ?", quote_ident(name)));
+                params.push(Value::Text(value.clone()));
+            }
+            let where_clause = if conditions.is_empty() {
+                String::new()
+            } else {
+                format!("WHERE {}", conditions.join(" AND "))
+            };
+
+            let number_matched: i64 = conn.query_row(
+                &format!("SELECT count(*) FROM {} t {}", source.table(), where_clause),
+                duckdb::params_from_iter(&params),
+                |row| row.get(0),
+            )?;
+
+            // Properties are written to JSON by DuckDB, keeping their types
+            let properties = source
+                .attribute_columns()
+                .filter(|c| {
+                    query
+                        .properties
+                        .as_ref()
+                        .is_none_or(|properties| properties.contains(&c.name))
+                })
+                .map(|c| format!("{}, t.{}", sql_literal(&c.name), quote_ident(&c.name)))
+                .collect::<Vec<_>>();
+            let features_query = format!(
+                "SELECT CAST(ST_AsGeoJSON({}) AS VARCHAR), CAST(json_object({}) AS VARCHAR)
+                FROM read_parquet({}, file_row_number = true) t {}
+                ORDER BY t.file_row_number LIMIT {} OFFSET {}",
+                source.geometry_in(query.crs)?,
+                properties.join(", "),
+                source.path,
+                where_clause,
+                query.limit.clamp(0, MAX_FEATURE_LIMIT),
+                query.offset.max(0),
+            );
+            let mut stmt = conn.prepare(&features_query)?;
+            let mut rows = stmt.query(duckdb::params_from_iter(&params))?;
+            let mut features = Vec::new();
+            while let Some(row) = rows.next()? {
+                let geometry = match row.get::<_, Option<String>>(0)? {
+                    Some(geometry) => serde_json::from_str(&geometry)?,
+                    None => serde_json::Value::Null,
+                };
+                let feature_properties: serde_json::Value =
+                    serde_json::from_str(&row.get::<_, String>(1)?)?;
+                features.push(serde_json::json!({
+                    "type": "Feature",
+                    "geometry": geometry,
+                    "properties": feature_properties,
+                }));
+            }
+
+            Ok(FeaturePage {
+                features,
+                number_matched,
+            })
+        })
+        .await
+    }
+
+    async fn count_source(&self, _namespace: &str, source_name: &str) -> Result<u64> {
+        self.with_source(source_name, |conn, source| {
+            let count: i64 = conn.query_row(
+                &format!("SELECT count(*) FROM {}", source.table()),
+                duckdb::params![],
+                |row| row.get(0),
+            )?;
+            Ok(count as u64)
+        })
+        .await
+    }
+
+    async fn export_source(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        options: &ExportOptions,
+        output_dir: &Path,
+    ) -> Result<PathBuf> {
+        let bbox = options.bbox;
+        let crs = options.crs;
+        let (query, file_stem) = self
+            .with_source(source_name, move |_, source| {
+                // COPY cannot take parameters, so the export bbox, which is
+                // checked to be finite, is written into the query
+                let where_clause = match bbox {
+                    Some([west, south, east, north]) => format!(
+                        "WHERE {}",
+                        source.envelope_condition(
+                            &format!("ST_MakeEnvelope({}, {}, {}, {})", west, south, east, north),
+                            4326
+                        )?
+                    ),
+                    None => String::new(),
+                };
+                let query = format!(
+                    "SELECT * EXCLUDE ({}), {} AS geom FROM {} t {}",
+                    quote_ident(&source.geometry_column),
+                    source.geometry_in(crs)?,
+                    source.table(),
+                    where_clause
+                );
+                Ok((query, export_file_stem(&source.name)))
+            })
+            .await?;
+
+        // Exports can take a while, so they get a DuckDB of their own
+        let options = options.clone();
+        let output_dir = output_dir.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            let conn = open_duckdb(&[])?;
+            export_duckdb_query(&conn, &query, &file_stem, &options, &output_dir)
+        })
+        .await?
+    }
+
+    // Taken from the GeoParquet metadata when it names a single type, otherwise
+    // the most common type among the rows
+    async fn get_geometry_type(&self, _namespace: &str, source_name: &str) -> Result<GeometryType> {
+        self.with_source(source_name, |conn, source| {
+            if let [geometry_type] = source.geometry_types.as_slice() {
+                if let Some(geometry_type) = parse_geometry_type(geometry_type) {
+                    return Ok(geometry_type);
+                }
+            }
+            let geometry = source.geometry();
+            conn.query_row(
+                &format!(
+                    "SELECT CAST(ST_GeometryType({geometry}) AS VARCHAR) FROM {} t
+                    WHERE {geometry} IS NOT NULL GROUP BY 1 ORDER BY count(*) DESC LIMIT 1",
+                    source.table()
+                ),
+                duckdb::params![],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .and_then(|name| parse_geometry_type(&name))
+            .ok_or_else(|| anyhow!("Source {} has no geometries", source.name))
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(srid: i32) -> ParquetSource {
+        ParquetSource {
+            name: "roads".to_string(),
+            path: sql_literal("/data/roads.parquet"),
+            geometry_column: "geometry".to_string(),
+            wkb: true,
+            srid,
+            geometry_types: Vec::new(),
+            bbox: None,
+            columns: vec![
+                ParquetColumn {
+                    name: "geometry".to_string(),
+                    column_type: "BLOB".to_string(),
+                },
+                ParquetColumn {
+                    name: "lanes".to_string(),
+                    column_type: "INTEGER".to_string(),
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn projjson_srid_reads_epsg_and_crs84() {
+        let epsg = serde_json::json!({"id": {"authority": "EPSG", "code": 27700}});
+        assert_eq!(projjson_srid(&epsg), Some(27700));
+        let text_code = serde_json::json!({"id": {"authority": "EPSG", "code": "3857"}});
+        assert_eq!(projjson_srid(&text_code), Some(3857));
+        let crs84 = serde_json::json!({"id": {"authority": "OGC", "code": "CRS84"}});
+        assert_eq!(projjson_srid(&crs84), Some(4326));
+        let other = serde_json::json!({"id": {"authority": "ESRI", "code": 102100}});
+        assert_eq!(projjson_srid(&other), None);
+        assert_eq!(projjson_srid(&serde_json::json!({})), None);
+    }
+
+    #[test]
+    fn parse_geometry_type_ignores_dimensions() {
+        assert!(matches!(
+            parse_geometry_type("Polygon Z"),
+            Some(GeometryType::Polygon)
+        ));
+        assert!(matches!(
+            parse_geometry_type("MULTILINESTRING"),
+            Some(GeometryType::MultiLineString)
+        ));
+        assert!(parse_geometry_type("CIRCULARSTRING").is_none());
+        assert!(parse_geometry_type("").is_none());
+    }
+
+    #[test]
+    fn tile_cast_follows_the_column_type() {
+        let column = |column_type: &str| ParquetColumn {
+            name: "value".to_string(),
+            column_type: column_type.to_string(),
+        };
+        assert_eq!(column("INTEGER").tile_cast(), Some("BIGINT"));
+        assert_eq!(column("DECIMAL(18,3)").tile_cast(), Some("DOUBLE"));
+        assert_eq!(column("timestamp").tile_cast(), Some("VARCHAR"));
+        assert_eq!(column("INTEGER[]").tile_cast(), None);
+        assert!(matches!(
+            column("BOOLEAN").field_type(),
+            Some(FieldType::Boolean)
+        ));
+        assert!(column("STRUCT(a INTEGER)").field_type().is_none());
+    }
+
+    #[test]
+    fn cached_sources_are_dropped_once_their_file_changes() {
+        let cache = SourceCache::default();
+        let described = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(10);
+        let changed = described + std::time::Duration::from_secs(1);
+        assert!(cache.get("roads", described).is_none());
+
+        cache.insert("roads", described, source(4326));
+        assert_eq!(cache.get("roads", described).unwrap().srid, 4326);
+        assert!(cache.get("roads", changed).is_none());
+        assert!(cache.get("rivers", described).is_none());
+
+        cache.insert("roads", changed, source(27700));
+        assert_eq!(cache.get("roads", changed).unwrap().srid, 27700);
+        assert!(cache.get("roads", described).is_none());
+    }
+
+    #[test]
+    fn bbox_conditions_bind_the_bbox() {
+        let (condition, params) = source(4326)
+            .bbox_condition([-1.5, 50.0, 0.5, 51.25], 4326)
+            .unwrap();
+        assert_eq!(
+            condition,
+            "ST_Intersects(ST_GeomFromWKB(t.\"geometry\"), ST_MakeEnvelope(?, ?, ?, ?))"
+        );
+        assert_eq!(
+            params,
+            vec![
+                Value::Double(-1.5),
+                Value::Double(50.0),
+                Value::Double(0.5),
+                Value::Double(51.25)
+            ]
+        );
+
+        let (condition, _) = source(27700).bbox_condition([0.0; 4], 3857).unwrap();
+        assert!(condition.contains("ST_Transform(ST_MakeEnvelope(?, ?, ?, ?), 'EPSG:3857'"));
+        assert!(source(0).bbox_condition([0.0; 4], 3857).is_err());
+    }
+}
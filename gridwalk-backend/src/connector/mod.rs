@@ -1,5 +1,19 @@
+mod config;
 mod connector;
 mod endpoints;
+mod export;
+mod geopackage;
+mod geoparquet;
+mod mvt;
+mod tile_archive;
+mod tile_cache;
 
+pub use config::*;
 pub use connector::*;
 pub use endpoints::*;
+pub use export::*;
+pub use geopackage::*;
+pub use geoparquet::*;
+pub use mvt::*;
+pub use tile_archive::*;
+pub use tile_cache::*;
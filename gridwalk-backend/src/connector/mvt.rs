@@ -0,0 +1,491 @@
+use crate::connector::UnsupportedCrsError;
+use anyhow::Result;
+use geo_types::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Polygon, Triangle,
+};
+use geozero::mvt::{tile, Message, TagsBuilder, Tile, TileValue};
+use geozero::ToMvt;
+
+// Tile size and buffer in tile coordinates, as used for PostGIS tiles
+pub const TILE_EXTENT: u32 = 4096;
+pub const TILE_BUFFER: u32 = 256;
+// Most features read for one tile, so a dense source cannot build huge tiles
+pub const MAX_TILE_FEATURES: usize = 20_000;
+
+const WEB_MERCATOR_HALF_WIDTH: f64 = 20_037_508.342_789_244;
+const WEB_MERCATOR_MAX_LAT: f64 = 85.051_128_779_806_59;
+
+// A feature to encode, with its geometry in web mercator
+pub struct TileFeature {
+    pub geometry: Geometry<f64>,
+    pub properties: Vec<(String, TileValue)>,
+}
+
+// Bounds of a tile in web mercator as [west, south, east, north]
+pub fn tile_bounds(z: u32, x: u32, y: u32) -> [f64; 4] {
+    let size = 2.0 * WEB_MERCATOR_HALF_WIDTH / 2f64.powi(z as i32);
+    let west = -WEB_MERCATOR_HALF_WIDTH + x as f64 * size;
+    let north = WEB_MERCATOR_HALF_WIDTH - y as f64 * size;
+    [west, north - size, west + size, north]
+}
+
+// Tile bounds grown by the tile buffer, for selecting the features of a tile
+pub fn buffered_tile_bounds(z: u32, x: u32, y: u32) -> [f64; 4] {
+    let [west, south, east, north] = tile_bounds(z, x, y);
+    let buffer = (east - west) * TILE_BUFFER as f64 / TILE_EXTENT as f64;
+    [west - buffer, south - buffer, east + buffer, north + buffer]
+}
+
+// Without a database to lean on only EPSG:4326 and EPSG:3857 are handled
+pub fn check_transform(from: i32, to: i32) -> Result<()> {
+    match [from, to]
+        .into_iter()
+        .find(|srid| ![4326, 3857].contains(srid))
+    {
+        Some(srid) if from != to => Err(UnsupportedCrsError { srid }.into()),
+        _ => Ok(()),
+    }
+}
+
+fn transform_coord(coord: &mut Coord<f64>, from: i32, to: i32) {
+    match (from, to) {
+        (4326, 3857) => {
+            let lat = coord.y.clamp(-WEB_MERCATOR_MAX_LAT, WEB_MERCATOR_MAX_LAT);
+            coord.x = coord.x.to_radians() * 6_378_137.0;
+            coord.y = (std::f64::consts::FRAC_PI_4 + lat.to_radians() / 2.0)
+                .tan()
+                .ln()
+                * 6_378_137.0;
+        }
+        (3857, 4326) => {
+            coord.x = (coord.x / 6_378_137.0).to_degrees();
+            coord.y = (2.0 * (coord.y / 6_378_137.0).exp().atan() - std::f64::consts::FRAC_PI_2)
+                .to_degrees();
+        }
+        _ => {}
+    }
+}
+
+pub fn transform_geometry(geometry: &mut Geometry<f64>, from: i32, to: i32) -> Result<()> {
+    check_transform(from, to)?;
+    if from != to {
+        for_each_coord(geometry, &mut |coord| transform_coord(coord, from, to));
+    }
+    Ok(())
+}
+
+// Transform a [west, south, east, north] bbox. The two CRSs keep axis order, so
+// the corners are enough.
+pub fn transform_bbox(bbox: [f64; 4], from: i32, to: i32) -> Result<[f64; 4]> {
+    check_transform(from, to)?;
+    let [mut min, mut max] = [
+        Coord {
+            x: bbox[0],
+            y: bbox[1],
+        },
+        Coord {
+            x: bbox[2],
+            y: bbox[3],
+        },
+    ];
+    transform_coord(&mut min, from, to);
+    transform_coord(&mut max, from, to);
+    Ok([min.x, min.y, max.x, max.y])
+}
+
+fn for_each_coord(geometry: &mut Geometry<f64>, f: &mut impl FnMut(&mut Coord<f64>)) {
+    match geometry {
+        Geometry::Point(point) => f(&mut point.0),
+        Geometry::Line(line) => {
+            f(&mut line.start);
+            f(&mut line.end);
+        }
+        Geometry::LineString(line) => line.0.iter_mut().for_each(f),
+        Geometry::Polygon(polygon) => polygon_coords(polygon, f),
+        Geometry::MultiPoint(points) => points.0.iter_mut().for_each(|p| f(&mut p.0)),
+        Geometry::MultiLineString(lines) => {
+            for line in &mut lines.0 {
+                line.0.iter_mut().for_each(&mut *f);
+            }
+        }
+        Geometry::MultiPolygon(polygons) => {
+            for polygon in &mut polygons.0 {
+                polygon_coords(polygon, f);
+            }
+        }
+        Geometry::GeometryCollection(collection) => {
+            for geometry in &mut collection.0 {
+                for_each_coord(geometry, f);
+            }
+        }
+        Geometry::Rect(rect) => {
+            let (mut min, mut max) = (rect.min(), rect.max());
+            f(&mut min);
+            f(&mut max);
+            rect.set_min(min);
+            rect.set_max(max);
+        }
+        Geometry::Triangle(triangle) => {
+            let [mut v1, mut v2, mut v3] = [triangle.v1(), triangle.v2(), triangle.v3()];
+            f(&mut v1);
+            f(&mut v2);
+            f(&mut v3);
+            *triangle = Triangle::new(v1, v2, v3);
+        }
+    }
+}
+
+fn polygon_coords(polygon: &mut Polygon<f64>, f: &mut impl FnMut(&mut Coord<f64>)) {
+    polygon.exterior_mut(|ring| ring.0.iter_mut().for_each(&mut *f));
+    polygon.interiors_mut(|rings| {
+        for ring in rings {
+            ring.0.iter_mut().for_each(&mut *f);
+        }
+    });
+}
+
+// Clip a geometry to a [west, south, east, north] box, None if nothing is left.
+// Lines are cut into the parts inside the box and polygon rings are clipped with
+// Sutherland-Hodgman, which can leave edges along the box as PostGIS does.
+fn clip_geometry(geometry: Geometry<f64>, bounds: [f64; 4]) -> Option<Geometry<f64>> {
+    let [min_x, min_y, max_x, max_y] = bounds;
+    let inside = |c: &Coord<f64>| c.x >= min_x && c.x <= max_x && c.y >= min_y && c.y <= max_y;
+    match geometry {
+        Geometry::Point(point) => inside(&point.0).then_some(Geometry::Point(point)),
+        Geometry::MultiPoint(points) => {
+            let points: Vec<_> = points.0.into_iter().filter(|p| inside(&p.0)).collect();
+            (!points.is_empty()).then(|| Geometry::MultiPoint(MultiPoint(points)))
+        }
+        Geometry::Line(line) => clip_geometry(Geometry::LineString(line.into()), bounds),
+        Geometry::LineString(line) => lines_geometry(clip_line(&line, bounds)),
+        Geometry::MultiLineString(lines) => lines_geometry(
+            lines
+                .0
+                .iter()
+                .flat_map(|line| clip_line(line, bounds))
+                .collect(),
+        ),
+        Geometry::Polygon(polygon) => clip_polygon(&polygon, bounds).map(Geometry::Polygon),
+        Geometry::MultiPolygon(polygons) => {
+            let polygons: Vec<_> = polygons
+                .0
+                .iter()
+                .filter_map(|polygon| clip_polygon(polygon, bounds))
+                .collect();
+            (!polygons.is_empty()).then(|| Geometry::MultiPolygon(MultiPolygon(polygons)))
+        }
+        Geometry::Rect(rect) => clip_geometry(Geometry::Polygon(rect.to_polygon()), bounds),
+        Geometry::Triangle(triangle) => {
+            clip_geometry(Geometry::Polygon(triangle.to_polygon()), bounds)
+        }
+        Geometry::GeometryCollection(collection) => {
+            let parts: Vec<_> = collection
+                .0
+                .into_iter()
+                .filter_map(|part| clip_geometry(part, bounds))
+                .collect();
+            (!parts.is_empty()).then(|| Geometry::GeometryCollection(GeometryCollection(parts)))
+        }
+    }
+}
+
+fn lines_geometry(mut lines: Vec<LineString<f64>>) -> Option<Geometry<f64>> {
+    match lines.len() {
+        0 => None,
+        1 => lines.pop().map(Geometry::LineString),
+        _ => Some(Geometry::MultiLineString(MultiLineString(lines))),
+    }
+}
+
+// Parts of a line inside the box, each segment clipped with Liang-Barsky
+fn clip_line(line: &LineString<f64>, bounds: [f64; 4]) -> Vec<LineString<f64>> {
+    let mut parts = Vec::new();
+    let mut part: Vec<Coord<f64>> = Vec::new();
+    for segment in line.0.windows(2) {
+        let (a, b) = (segment[0], segment[1]);
+        let Some((t0, t1)) = clip_segment(a, b, bounds) else {
+            continue;
+        };
+        let at = |t: f64| Coord {
+            x: a.x + t * (b.x - a.x),
+            y: a.y + t * (b.y - a.y),
+        };
+        // The segment enters the box, so it starts a new part
+        if t0 > 0.0 || part.is_empty() {
+            if part.len() > 1 {
+                parts.push(LineString(std::mem::take(&mut part)));
+            }
+            part = vec![at(t0)];
+        }
+        part.push(at(t1));
+        // The segment leaves the box, so the part ends here
+        if t1 < 1.0 {
+            parts.push(LineString(std::mem::take(&mut part)));
+        }
+    }
+    if part.len() > 1 {
+        parts.push(LineString(part));
+    }
+    parts
+}
+
+// Range of t along a to b that lies in the box, None if the segment misses it
+fn clip_segment(a: Coord<f64>, b: Coord<f64>, bounds: [f64; 4]) -> Option<(f64, f64)> {
+    let [min_x, min_y, max_x, max_y] = bounds;
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let (mut t0, mut t1) = (0.0, 1.0);
+    for (p, q) in [
+        (-dx, a.x - min_x),
+        (dx, max_x - a.x),
+        (-dy, a.y - min_y),
+        (dy, max_y - a.y),
+    ] {
+        if p == 0.0 {
+            if q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if p < 0.0 {
+                if t > t1 {
+                    return None;
+                }
+                t0 = f64::max(t0, t);
+            } else {
+                if t < t0 {
+                    return None;
+                }
+                t1 = f64::min(t1, t);
+            }
+        }
+    }
+    Some((t0, t1))
+}
+
+fn clip_polygon(polygon: &Polygon<f64>, bounds: [f64; 4]) -> Option<Polygon<f64>> {
+    let exterior = clip_ring(polygon.exterior(), bounds)?;
+    let interiors = polygon
+        .interiors()
+        .iter()
+        .filter_map(|ring| clip_ring(ring, bounds))
+        .collect();
+    Some(Polygon::new(exterior, interiors))
+}
+
+// Clip a closed ring against each side of the box in turn
+fn clip_ring(
+    ring: &LineString<f64>,
+    [min_x, min_y, max_x, max_y]: [f64; 4],
+) -> Option<LineString<f64>> {
+    let mut points = ring.0.clone();
+    if points.len() > 1 && points.first() == points.last() {
+        points.pop();
+    }
+    // Each side as the axis it bounds, its position and whether inside is above it
+    let sides = [
+        (true, min_x, true),
+        (true, max_x, false),
+        (false, min_y, true),
+        (false, max_y, false),
+    ];
+    for (is_x, edge, above) in sides {
+        let value = |c: &Coord<f64>| if is_x { c.x } else { c.y };
+        let inside = |c: &Coord<f64>| {
+            if above {
+                value(c) >= edge
+            } else {
+                value(c) <= edge
+            }
+        };
+        let crossing = |a: &Coord<f64>, b: &Coord<f64>| {
+            let t = (edge - value(a)) / (value(b) - value(a));
+            if is_x {
+                Coord {
+                    x: edge,
+                    y: a.y + t * (b.y - a.y),
+                }
+            } else {
+                Coord {
+                    x: a.x + t * (b.x - a.x),
+                    y: edge,
+                }
+            }
+        };
+        let input = std::mem::take(&mut points);
+        let mut previous = *input.last()?;
+        for current in input {
+            match (inside(&previous), inside(&current)) {
+                (true, true) => points.push(current),
+                (true, false) => points.push(crossing(&previous, &current)),
+                (false, true) => {
+                    points.push(crossing(&previous, &current));
+                    points.push(current);
+                }
+                (false, false) => {}
+            }
+            previous = current;
+        }
+    }
+    if points.len() < 3 {
+        return None;
+    }
+    points.push(points[0]);
+    Some(LineString(points))
+}
+
+// MVT wants exterior rings clockwise and holes anticlockwise once y points down,
+// which is the reverse with y pointing up as it does in web mercator
+fn orient_polygons(geometry: &mut Geometry<f64>) {
+    let orient = |polygon: &mut Polygon<f64>| {
+        polygon.exterior_mut(|ring| orient_ring(ring, true));
+        polygon.interiors_mut(|rings| rings.iter_mut().for_each(|ring| orient_ring(ring, false)));
+    };
+    match geometry {
+        Geometry::Polygon(polygon) => orient(polygon),
+        Geometry::MultiPolygon(polygons) => polygons.0.iter_mut().for_each(orient),
+        _ => {}
+    }
+}
+
+fn orient_ring(ring: &mut LineString<f64>, anticlockwise: bool) {
+    let area: f64 = ring
+        .0
+        .windows(2)
+        .map(|pair| pair[0].x * pair[1].y - pair[1].x * pair[0].y)
+        .sum();
+    if area != 0.0 && (area > 0.0) != anticlockwise {
+        ring.0.reverse();
+    }
+}
+
+// Encode features as a vector tile with a single layer. Geometries are clipped to
+// the buffered tile, and those left empty or that collapse to too few coordinates
+// are left out. No features gives an empty tile, as from PostGIS.
+pub fn encode_tile(
+    layer_name: &str,
+    z: u32,
+    x: u32,
+    y: u32,
+    features: Vec<TileFeature>,
+) -> Result<Vec<u8>> {
+    let [west, south, east, north] = tile_bounds(z, x, y);
+    let buffered = buffered_tile_bounds(z, x, y);
+
+    let mut tags = TagsBuilder::new();
+    let mut encoded = Vec::new();
+    for feature in features {
+        let mut feature_tags = Vec::new();
+        for (key, value) in feature.properties {
+            let (key, value) = tags.insert(key, value);
+            feature_tags.extend([key, value]);
+        }
+
+        // Each part of a collection becomes a feature of its own
+        let parts = match feature.geometry {
+            Geometry::GeometryCollection(collection) => collection.0,
+            geometry => vec![geometry],
+        };
+        for geometry in parts {
+            let Some(mut geometry) = clip_geometry(geometry, buffered) else {
+                continue;
+            };
+            orient_polygons(&mut geometry);
+            if let Ok(mut mvt_feature) = geometry.to_mvt(TILE_EXTENT, west, south, east, north) {
+                mvt_feature.tags = feature_tags.clone();
+                encoded.push(mvt_feature);
+            }
+        }
+    }
+    if encoded.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let (keys, values) = tags.into_tags();
+    let tile = Tile {
+        layers: vec![tile::Layer {
+            version: 2,
+            name: layer_name.to_string(),
+            features: encoded,
+            keys,
+            values: values.into_iter().map(Into::into).collect(),
+            extent: Some(TILE_EXTENT),
+        }],
+    };
+    Ok(tile.encode_to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use geo_types::{coord, line_string, polygon};
+
+    #[test]
+    fn tile_bounds_cover_web_mercator() {
+        let world = tile_bounds(0, 0, 0);
+        assert_eq!(
+            world,
+            [
+                -WEB_MERCATOR_HALF_WIDTH,
+                -WEB_MERCATOR_HALF_WIDTH,
+                WEB_MERCATOR_HALF_WIDTH,
+                WEB_MERCATOR_HALF_WIDTH
+            ]
+        );
+        // Tile 1/1/0 is the north east quarter
+        let [west, south, east, north] = tile_bounds(1, 1, 0);
+        assert_eq!([west, south], [0.0, 0.0]);
+        assert_eq!([east, north], [WEB_MERCATOR_HALF_WIDTH; 2]);
+
+        let [west, south, east, north] = buffered_tile_bounds(1, 1, 0);
+        let buffer = WEB_MERCATOR_HALF_WIDTH * TILE_BUFFER as f64 / TILE_EXTENT as f64;
+        assert_eq!([west, south], [-buffer, -buffer]);
+        assert_eq!(east, WEB_MERCATOR_HALF_WIDTH + buffer);
+        assert_eq!(north, WEB_MERCATOR_HALF_WIDTH + buffer);
+    }
+
+    #[test]
+    fn clip_line_keeps_the_parts_inside() {
+        // Leaves through the right side and comes back in
+        let line = line_string![
+            (x: 1.0, y: 1.0),
+            (x: 15.0, y: 1.0),
+            (x: 15.0, y: 5.0),
+            (x: 5.0, y: 5.0),
+        ];
+        let parts = clip_line(&line, [0.0, 0.0, 10.0, 10.0]);
+        assert_eq!(
+            parts,
+            vec![
+                line_string![(x: 1.0, y: 1.0), (x: 10.0, y: 1.0)],
+                line_string![(x: 10.0, y: 5.0), (x: 5.0, y: 5.0)],
+            ]
+        );
+        assert!(clip_line(&line, [20.0, 20.0, 30.0, 30.0]).is_empty());
+    }
+
+    #[test]
+    fn clip_polygon_cuts_rings_to_the_box() {
+        let square = polygon![
+            (x: -5.0, y: -5.0),
+            (x: 5.0, y: -5.0),
+            (x: 5.0, y: 5.0),
+            (x: -5.0, y: 5.0),
+            (x: -5.0, y: -5.0),
+        ];
+        let clipped = clip_polygon(&square, [0.0, 0.0, 10.0, 10.0]).unwrap();
+        let mut corners = clipped.exterior().0.clone();
+        corners.pop();
+        corners.sort_by(|a, b| (a.x, a.y).partial_cmp(&(b.x, b.y)).unwrap());
+        assert_eq!(
+            corners,
+            vec![
+                coord! { x: 0.0, y: 0.0 },
+                coord! { x: 0.0, y: 5.0 },
+                coord! { x: 5.0, y: 0.0 },
+                coord! { x: 5.0, y: 5.0 },
+            ]
+        );
+        assert!(clip_polygon(&square, [20.0, 20.0, 30.0, 30.0]).is_none());
+    }
+}
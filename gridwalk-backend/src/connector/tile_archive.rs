@@ -0,0 +1,839 @@
+use crate::connector::{
+    ExportOptions, FeaturePage, FeatureQuery, FieldType, GeoConnector, GeometryType, SourceField,
+    SourceNotFoundError, TileSet, UnknownFieldsError, VectorLayer,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use flate2::read::GzDecoder;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::warn;
+
+// MBTiles connections kept open between requests, per archive
+const MAX_IDLE_CONNECTIONS: usize = 4;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TileArchiveConnection {
+    // Directory on the server holding the .mbtiles and .pmtiles files
+    pub directory: String,
+}
+
+// Read-only connector serving pre-rendered vector tiles from MBTiles and PMTiles
+// files. Each file is a source named after its file stem, shared by every
+// workspace with access to the connection, so namespaces are not used. Tiles are
+// served whole, as rendered, and there are no features to query. Archives are
+// read when the connector is built, and ones added later when first requested.
+pub struct TileArchiveConnector {
+    directory: PathBuf,
+    archives: RwLock<HashMap<String, Arc<Archive>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ArchiveKind {
+    MbTiles,
+    PmTiles,
+}
+
+impl ArchiveKind {
+    const ALL: [ArchiveKind; 2] = [ArchiveKind::PmTiles, ArchiveKind::MbTiles];
+
+    fn extension(&self) -> &'static str {
+        match self {
+            ArchiveKind::MbTiles => "mbtiles",
+            ArchiveKind::PmTiles => "pmtiles",
+        }
+    }
+}
+
+// What an archive's metadata says about its tiles
+#[derive(Debug, Default)]
+struct ArchiveMetadata {
+    // [west, south, east, north] in EPSG:4326
+    bounds: Option<[f64; 4]>,
+    fields: Vec<SourceField>,
+    geometry_type: Option<GeometryType>,
+    tile_set: TileSet,
+}
+
+impl ArchiveMetadata {
+    // Layers and fields come from the TileJSON vector_layers and the geometry type
+    // from the tippecanoe tilestats layer with the most features
+    fn from_json(
+        bounds: Option<[f64; 4]>,
+        minzoom: Option<u32>,
+        maxzoom: Option<u32>,
+        json: &serde_json::Value,
+    ) -> Self {
+        let zoom = |value: &serde_json::Value| value.as_u64().and_then(|z| u32::try_from(z).ok());
+        let mut fields: Vec<SourceField> = Vec::new();
+        let mut vector_layers = Vec::new();
+        for layer in json["vector_layers"].as_array().into_iter().flatten() {
+            let mut layer_fields = BTreeMap::new();
+            for (name, field_type) in layer["fields"].as_object().into_iter().flatten() {
+                let Ok(field_type) = serde_json::from_value::<FieldType>(field_type.clone()) else {
+                    continue;
+                };
+                layer_fields.insert(name.clone(), field_type);
+                if !fields.iter().any(|f| &f.name == name) {
+                    fields.push(SourceField {
+                        name: name.clone(),
+                        field_type,
+                    });
+                }
+            }
+            if let Some(id) = layer["id"].as_str() {
+                vector_layers.push(VectorLayer {
+                    id: id.to_string(),
+                    fields: layer_fields,
+                    minzoom: zoom(&layer["minzoom"]).or(minzoom).unwrap_or(0),
+                    maxzoom: zoom(&layer["maxzoom"]).or(maxzoom).unwrap_or(22),
+                });
+            }
+        }
+
+        let geometry_type = json["tilestats"]["layers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .max_by_key(|layer| layer["count"].as_u64().unwrap_or_default())
+            .and_then(|layer| match layer["geometry"].as_str()? {
+                "Point" => Some(GeometryType::Point),
+                "LineString" => Some(GeometryType::LineString),
+                "Polygon" => Some(GeometryType::Polygon),
+                _ => None,
+            });
+
+        ArchiveMetadata {
+            bounds,
+            fields,
+            geometry_type,
+            tile_set: TileSet {
+                minzoom,
+                maxzoom,
+                vector_layers,
+            },
+        }
+    }
+}
+
+// How the tiles of an archive are read
+enum ArchiveReader {
+    MbTiles {
+        // Whether the metadata format allows the tiles to be served as vector tiles
+        vector_tiles: bool,
+        idle: Mutex<Vec<Connection>>,
+    },
+    PmTiles {
+        header: PmTilesHeader,
+        root: Vec<DirectoryEntry>,
+    },
+}
+
+// An archive file and what is read from it once
+struct Archive {
+    path: PathBuf,
+    metadata: ArchiveMetadata,
+    reader: ArchiveReader,
+}
+
+impl Archive {
+    fn load(path: PathBuf, kind: ArchiveKind) -> Result<Self> {
+        let (metadata, reader) = match kind {
+            ArchiveKind::MbTiles => {
+                let conn = open_mbtiles(&path)?;
+                let (metadata, vector_tiles) = mbtiles_metadata(&conn)?;
+                let idle = Mutex::new(vec![conn]);
+                (metadata, ArchiveReader::MbTiles { vector_tiles, idle })
+            }
+            ArchiveKind::PmTiles => {
+                let (metadata, header, root) = pmtiles_index(&path)?;
+                (metadata, ArchiveReader::PmTiles { header, root })
+            }
+        };
+        Ok(Archive {
+            path,
+            metadata,
+            reader,
+        })
+    }
+
+    // Tiles of an MBTiles archive are read with a connection taken from the idle
+    // ones or opened for the call
+    fn tile(&self, z: u32, x: u32, y: u32) -> Result<Vec<u8>> {
+        match &self.reader {
+            ArchiveReader::PmTiles { header, root } => {
+                pmtiles_tile(&self.path, header, root, z, x, y)
+            }
+            ArchiveReader::MbTiles { vector_tiles, idle } => {
+                if !vector_tiles {
+                    return Err(anyhow!("Only vector tile archives can be served"));
+                }
+                let conn = match idle.lock().unwrap().pop() {
+                    Some(conn) => conn,
+                    None => open_mbtiles(&self.path)?,
+                };
+                let result = mbtiles_tile(&conn, z, x, y);
+                let mut idle = idle.lock().unwrap();
+                if idle.len() < MAX_IDLE_CONNECTIONS {
+                    idle.push(conn);
+                }
+                result
+            }
+        }
+    }
+}
+
+// The archive file of a source, PMTiles first if there are both
+fn find_archive(directory: &Path, source_name: &str) -> Result<Option<Archive>> {
+    for kind in ArchiveKind::ALL {
+        let path = directory.join(format!("{}.{}", source_name, kind.extension()));
+        if path.is_file() {
+            return Archive::load(path, kind).map(Some);
+        }
+    }
+    Ok(None)
+}
+
+// Stem of an archive file, None for other files
+fn archive_stem(path: &Path) -> Option<&str> {
+    let extension = path.extension()?.to_str()?;
+    if !ArchiveKind::ALL
+        .iter()
+        .any(|kind| extension == kind.extension())
+    {
+        return None;
+    }
+    path.file_stem()?.to_str()
+}
+
+impl TileArchiveConnector {
+    pub fn new(connection: TileArchiveConnection) -> Result<Self> {
+        let directory = PathBuf::from(connection.directory);
+        if !directory.is_dir() {
+            return Err(anyhow!(
+                "Tile archive directory not found: {}",
+                directory.display()
+            ));
+        }
+
+        // An archive that cannot be read is left out, and reported if requested
+        let mut archives = HashMap::new();
+        for entry in std::fs::read_dir(&directory)? {
+            let path = entry?.path();
+            let Some(stem) = archive_stem(&path) else {
+                continue;
+            };
+            if archives.contains_key(stem) {
+                continue;
+            }
+            match find_archive(&directory, stem) {
+                Ok(Some(archive)) => {
+                    archives.insert(stem.to_string(), Arc::new(archive));
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read tile archive {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(TileArchiveConnector {
+            directory,
+            archives: RwLock::new(archives),
+        })
+    }
+
+    async fn archive(&self, source_name: &str) -> Result<Arc<Archive>> {
+        let not_found = || -> anyhow::Error {
+            SourceNotFoundError {
+                source_name: source_name.to_string(),
+            }
+            .into()
+        };
+        if source_name.is_empty() || source_name.contains(['/', '\\']) || source_name == ".." {
+            return Err(not_found());
+        }
+        if let Some(archive) = self.archives.read().unwrap().get(source_name) {
+            return Ok(archive.clone());
+        }
+
+        let (directory, name) = (self.directory.clone(), source_name.to_string());
+        let archive = tokio::task::spawn_blocking(move || find_archive(&directory, &name))
+            .await??
+            .map(Arc::new)
+            .ok_or_else(not_found)?;
+        self.archives
+            .write()
+            .unwrap()
+            .insert(source_name.to_string(), archive.clone());
+        Ok(archive)
+    }
+}
+
+fn open_mbtiles(path: &Path) -> Result<Connection> {
+    Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )
+    .map_err(|e| anyhow!("Failed to open {}: {}", path.display(), e))
+}
+
+// The metadata of an MBTiles archive, and whether its tiles are vector tiles
+fn mbtiles_metadata(conn: &Connection) -> Result<(ArchiveMetadata, bool)> {
+    let value = |name: &str| {
+        conn.query_row(
+            "SELECT value FROM metadata WHERE name = ?1",
+            [name],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+    };
+
+    let bounds = value("bounds")?.and_then(|bounds| {
+        let parts: Vec<f64> = bounds
+            .split(',')
+            .filter_map(|part| part.trim().parse().ok())
+            .collect();
+        <[f64; 4]>::try_from(parts).ok()
+    });
+    let zoom = |name: &str| -> Result<Option<u32>> {
+        Ok(value(name)?.and_then(|zoom| zoom.trim().parse().ok()))
+    };
+    let (minzoom, maxzoom) = (zoom("minzoom")?, zoom("maxzoom")?);
+    let json = match value("json")? {
+        Some(json) => serde_json::from_str(&json)?,
+        None => serde_json::Value::Null,
+    };
+    let vector_tiles = value("format")?.is_none_or(|format| format == "pbf");
+    Ok((
+        ArchiveMetadata::from_json(bounds, minzoom, maxzoom, &json),
+        vector_tiles,
+    ))
+}
+
+// Tiles in an MBTiles file are stored with TMS rows, counted from the south
+fn mbtiles_tile(conn: &Connection, z: u32, x: u32, y: u32) -> Result<Vec<u8>> {
+    if z >= 30 || y as u64 >= 1 << z {
+        return Ok(Vec::new());
+    }
+    let row = (1u64 << z) - 1 - y as u64;
+    let data: Option<Vec<u8>> = conn
+        .query_row(
+            "SELECT tile_data FROM tiles WHERE zoom_level = ?1 AND tile_column = ?2 AND tile_row = ?3",
+            [z as i64, x as i64, row as i64],
+            |row| row.get(0),
+        )
+        .optional()?;
+    match data {
+        // Vector tiles in MBTiles are usually gzipped
+        Some(data) if data.starts_with(&[0x1f, 0x8b]) => decompress(data, 2),
+        Some(data) => Ok(data),
+        None => Ok(Vec::new()),
+    }
+}
+
+// The fixed size header of a PMTiles v3 archive
+struct PmTilesHeader {
+    root_directory: (u64, u64),
+    metadata: (u64, u64),
+    leaf_directories_offset: u64,
+    tile_data_offset: u64,
+    internal_compression: u8,
+    tile_compression: u8,
+    tile_type: u8,
+    min_zoom: u8,
+    max_zoom: u8,
+    bounds: [f64; 4],
+}
+
+const PMTILES_HEADER_LENGTH: usize = 127;
+const PMTILES_TILE_TYPE_MVT: u8 = 1;
+
+impl PmTilesHeader {
+    fn read(file: &mut File) -> Result<Self> {
+        let mut header = [0u8; PMTILES_HEADER_LENGTH];
+        file.read_exact(&mut header)
+            .map_err(|_| anyhow!("Not a PMTiles archive"))?;
+        if &header[0..7] != b"PMTiles" || header[7] != 3 {
+            return Err(anyhow!("Only PMTiles version 3 archives are supported"));
+        }
+        let u64_at = |at: usize| u64::from_le_bytes(header[at..at + 8].try_into().unwrap());
+        let degrees_at =
+            |at: usize| i32::from_le_bytes(header[at..at + 4].try_into().unwrap()) as f64 / 1e7;
+        Ok(PmTilesHeader {
+            root_directory: (u64_at(8), u64_at(16)),
+            metadata: (u64_at(24), u64_at(32)),
+            leaf_directories_offset: u64_at(40),
+            tile_data_offset: u64_at(56),
+            internal_compression: header[97],
+            tile_compression: header[98],
+            tile_type: header[99],
+            min_zoom: header[100],
+            max_zoom: header[101],
+            bounds: [
+                degrees_at(102),
+                degrees_at(106),
+                degrees_at(110),
+                degrees_at(114),
+            ],
+        })
+    }
+}
+
+// A run of tiles, or a leaf directory if run_length is 0
+struct DirectoryEntry {
+    tile_id: u64,
+    offset: u64,
+    length: u64,
+    run_length: u64,
+}
+
+fn read_at(file: &mut File, offset: u64, length: u64) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut data)?;
+    Ok(data)
+}
+
+// PMTiles compression codes: 1 none, 2 gzip, 3 brotli, 4 zstd
+fn decompress(data: Vec<u8>, compression: u8) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    match compression {
+        0 | 1 => return Ok(data),
+        2 => {
+            GzDecoder::new(&data[..]).read_to_end(&mut output)?;
+        }
+        3 => {
+            brotli::Decompressor::new(&data[..], 4096).read_to_end(&mut output)?;
+        }
+        _ => return Err(anyhow!("Unsupported compression: {}", compression)),
+    }
+    Ok(output)
+}
+
+fn read_varint(data: &[u8], position: &mut usize) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *data
+            .get(*position)
+            .ok_or_else(|| anyhow!("Truncated PMTiles directory"))?;
+        *position += 1;
+        value |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(anyhow!("Invalid varint in PMTiles directory"))
+}
+
+// Entries are stored column by column: tile id deltas, run lengths, lengths and
+// offsets, where an offset of 0 means directly after the previous entry
+fn parse_directory(data: &[u8]) -> Result<Vec<DirectoryEntry>> {
+    let mut position = 0;
+    let count = read_varint(data, &mut position)? as usize;
+    let mut entries: Vec<DirectoryEntry> = Vec::with_capacity(count);
+    let mut tile_id = 0;
+    for _ in 0..count {
+        tile_id += read_varint(data, &mut position)?;
+        entries.push(DirectoryEntry {
+            tile_id,
+            offset: 0,
+            length: 0,
+            run_length: 0,
+        });
+    }
+    for entry in entries.iter_mut() {
+        entry.run_length = read_varint(data, &mut position)?;
+    }
+    for entry in entries.iter_mut() {
+        entry.length = read_varint(data, &mut position)?;
+    }
+    for i in 0..count {
+        let offset = read_varint(data, &mut position)?;
+        entries[i].offset = if offset == 0 && i > 0 {
+            entries[i - 1].offset + entries[i - 1].length
+        } else {
+            offset.saturating_sub(1)
+        };
+    }
+    Ok(entries)
+}
+
+// Tiles are numbered by zoom and then along a Hilbert curve within each zoom
+fn pmtiles_tile_id(z: u32, x: u32, y: u32) -> u64 {
+    let base = ((1u64 << (2 * z)) - 1) / 3;
+    let n = 1u64 << z;
+    let (mut x, mut y) = (x as u64, y as u64);
+    let mut d = 0;
+    let mut s = n / 2;
+    while s > 0 {
+        let rx = u64::from(x & s > 0);
+        let ry = u64::from(y & s > 0);
+        d += s * s * ((3 * rx) ^ ry);
+        if ry == 0 {
+            if rx == 1 {
+                x = n - 1 - x;
+                y = n - 1 - y;
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s /= 2;
+    }
+    base + d
+}
+
+// The metadata, header and root directory of a PMTiles archive
+fn pmtiles_index(path: &Path) -> Result<(ArchiveMetadata, PmTilesHeader, Vec<DirectoryEntry>)> {
+    let mut file = File::open(path)?;
+    let header = PmTilesHeader::read(&mut file)?;
+    let (offset, length) = header.metadata;
+    let json = if length > 0 {
+        let data = read_at(&mut file, offset, length)?;
+        serde_json::from_slice(&decompress(data, header.internal_compression)?)?
+    } else {
+        serde_json::Value::Null
+    };
+    let (offset, length) = header.root_directory;
+    let data = read_at(&mut file, offset, length)?;
+    let root = parse_directory(&decompress(data, header.internal_compression)?)?;
+    Ok((
+        ArchiveMetadata::from_json(
+            Some(header.bounds),
+            Some(header.min_zoom.into()),
+            Some(header.max_zoom.into()),
+            &json,
+        ),
+        header,
+        root,
+    ))
+}
+
+fn pmtiles_tile(
+    path: &Path,
+    header: &PmTilesHeader,
+    root: &[DirectoryEntry],
+    z: u32,
+    x: u32,
+    y: u32,
+) -> Result<Vec<u8>> {
+    if header.tile_type != PMTILES_TILE_TYPE_MVT {
+        return Err(anyhow!("Only vector tile archives can be served"));
+    }
+    if z > 26 || x >= 1 << z || y >= 1 << z {
+        return Ok(Vec::new());
+    }
+    let tile_id = pmtiles_tile_id(z, x, y);
+    let mut file = File::open(path)?;
+
+    // The root directory may point to leaf directories, at most three deep
+    let mut leaf: Vec<DirectoryEntry>;
+    let mut entries = root;
+    for depth in 0..4 {
+        let index = entries.partition_point(|entry| entry.tile_id <= tile_id);
+        let Some(entry) = index.checked_sub(1).map(|i| &entries[i]) else {
+            return Ok(Vec::new());
+        };
+        if entry.run_length == 0 {
+            if depth == 3 {
+                break;
+            }
+            let data = read_at(
+                &mut file,
+                header.leaf_directories_offset + entry.offset,
+                entry.length,
+            )?;
+            leaf = parse_directory(&decompress(data, header.internal_compression)?)?;
+            entries = &leaf;
+        } else if tile_id < entry.tile_id + entry.run_length {
+            let data = read_at(
+                &mut file,
+                header.tile_data_offset + entry.offset,
+                entry.length,
+            )?;
+            return decompress(data, header.tile_compression);
+        } else {
+            return Ok(Vec::new());
+        }
+    }
+    Err(anyhow!("PMTiles directories are nested too deeply"))
+}
+
+fn read_only_error() -> anyhow::Error {
+    anyhow!("Tile archive connections are read only")
+}
+
+#[async_trait]
+impl GeoConnector for TileArchiveConnector {
+    async fn connect(&mut self) -> Result<()> {
+        std::fs::read_dir(&self.directory)
+            .map_err(|e| anyhow!("Failed to read {}: {}", self.directory.display(), e))?;
+        Ok(())
+    }
+
+    async fn disconnect(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // Every workspace sees the same archives, so there is nothing to create
+    async fn create_namespace(&self, _name: &str) -> Result<()> {
+        Ok(())
+    }
+
+    async fn list_sources(&self, _namespace: &str) -> Result<Vec<String>> {
+        let mut sources = Vec::new();
+        let mut entries = tokio::fs::read_dir(&self.directory).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if let Some(stem) = archive_stem(&entry.path()) {
+                sources.push(stem.to_string());
+            }
+        }
+        sources.sort();
+        sources.dedup();
+        Ok(sources)
+    }
+
+    async fn delete_source(&self, _namespace: &str, _source_name: &str) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    // Tiles are pre-rendered, so a field selection can only be checked against
+    // the archive's metadata and the whole tile is returned
+    async fn get_tile(
+        &self,
+        _namespace: &str,
+        source_name: &str,
+        z: u32,
+        x: u32,
+        y: u32,
+        fields: Option<&[String]>,
+    ) -> Result<Vec<u8>> {
+        let archive = self.archive(source_name).await?;
+        if let Some(fields) = fields {
+            let unknown: Vec<String> = fields
+                .iter()
+                .filter(|name| !archive.metadata.fields.iter().any(|f| &f.name == *name))
+                .cloned()
+                .collect();
+            if !unknown.is_empty() {
+                return Err(UnknownFieldsError { fields: unknown }.into());
+            }
+        }
+
+        tokio::task::spawn_blocking(move || archive.tile(z, x, y)).await?
+    }
+
+    async fn get_tile_set(&self, _namespace: &str, source_name: &str) -> Result<Option<TileSet>> {
+        Ok(Some(
+            self.archive(source_name).await?.metadata.tile_set.clone(),
+        ))
+    }
+
+    async fn get_fields(&self, _namespace: &str, source_name: &str) -> Result<Vec<SourceField>> {
+        Ok(self.archive(source_name).await?.metadata.fields.clone())
+    }
+
+    async fn get_bounds(&self, _namespace: &str, source_name: &str) -> Result<Option<[f64; 4]>> {
+        Ok(self.archive(source_name).await?.metadata.bounds)
+    }
+
+    async fn query_features(
+        &self,
+        _namespace: &str,
+        _source_name: &str,
+        _query: &FeatureQuery,
+    ) -> Result<FeaturePage> {
+        Err(anyhow!("Tile archives have no features to query"))
+    }
+
+    async fn count_source(&self, _namespace: &str, _source_name: &str) -> Result<u64> {
+        Err(anyhow!("Tile archives have no features to count"))
+    }
+
+    async fn export_source(
+        &self,
+        _namespace: &str,
+        _source_name: &str,
+        _options: &ExportOptions,
+        _output_dir: &Path,
+    ) -> Result<PathBuf> {
+        Err(anyhow!("Tile archives cannot be exported"))
+    }
+
+    async fn get_geometry_type(&self, _namespace: &str, source_name: &str) -> Result<GeometryType> {
+        self.archive(source_name)
+            .await?
+            .metadata
+            .geometry_type
+            .clone()
+            .ok_or_else(|| anyhow!("Archive {} does not record a geometry type", source_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pmtiles_tile_ids_follow_the_hilbert_curve() {
+        assert_eq!(pmtiles_tile_id(0, 0, 0), 0);
+        assert_eq!(
+            [(0, 0), (0, 1), (1, 1), (1, 0)].map(|(x, y)| pmtiles_tile_id(1, x, y)),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(pmtiles_tile_id(12, 3423, 1763), 19078479);
+    }
+
+    #[test]
+    fn parse_directory_reads_columns_and_contiguous_offsets() {
+        // Tile ids 5, 6 and 10 as deltas, then run lengths, lengths and offsets,
+        // with 201 (offset 200) as a two byte varint
+        let data = [3, 5, 1, 4, 1, 2, 0, 100, 50, 30, 1, 0, 0xc9, 0x01];
+        let entries = parse_directory(&data).unwrap();
+        let read: Vec<(u64, u64, u64, u64)> = entries
+            .iter()
+            .map(|e| (e.tile_id, e.run_length, e.length, e.offset))
+            .collect();
+        assert_eq!(
+            read,
+            vec![(5, 1, 100, 0), (6, 2, 50, 100), (10, 0, 30, 200)]
+        );
+        assert!(parse_directory(&data[..6]).is_err());
+    }
+
+    // A PMTiles archive holding a single uncompressed tile at 0/0/0
+    fn write_pmtiles(path: &Path, tile: &[u8]) {
+        let directory = [1, 0, 1, tile.len() as u8, 1];
+        let root_offset = PMTILES_HEADER_LENGTH as u64;
+        let data_offset = root_offset + directory.len() as u64;
+        let mut header = vec![0u8; PMTILES_HEADER_LENGTH];
+        header[0..7].copy_from_slice(b"PMTiles");
+        header[7] = 3;
+        for (at, value) in [
+            (8, root_offset),
+            (16, directory.len() as u64),
+            (24, data_offset),
+            (40, data_offset),
+            (56, data_offset),
+            (64, tile.len() as u64),
+        ] {
+            header[at..at + 8].copy_from_slice(&value.to_le_bytes());
+        }
+        header[97] = 1;
+        header[98] = 1;
+        header[99] = PMTILES_TILE_TYPE_MVT;
+        std::fs::write(path, [header, directory.to_vec(), tile.to_vec()].concat()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn serves_tiles_from_archives_read_when_built() {
+        let directory = std::env::temp_dir().join(format!("gw-tiles-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        write_pmtiles(&directory.join("roads.pmtiles"), b"tile");
+        std::fs::write(directory.join("notes.txt"), "").unwrap();
+
+        let connector = TileArchiveConnector::new(TileArchiveConnection {
+            directory: directory.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+        assert!(connector.archives.read().unwrap().contains_key("roads"));
+
+        // Archives added later are found when first requested
+        write_pmtiles(&directory.join("rivers.pmtiles"), b"water");
+        let sources = connector.list_sources("").await.unwrap();
+        let tile = connector.get_tile("", "rivers", 0, 0, 0, None).await;
+        let empty = connector.get_tile("", "roads", 1, 0, 0, None).await;
+        let roads = connector.get_tile("", "roads", 0, 0, 0, None).await;
+        let missing = connector.get_tile("", "notes", 0, 0, 0, None).await;
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(sources, vec!["rivers", "roads"]);
+        assert_eq!(tile.unwrap(), b"water");
+        assert_eq!(roads.unwrap(), b"tile");
+        assert!(empty.unwrap().is_empty());
+        assert!(missing.unwrap_err().is::<SourceNotFoundError>());
+    }
+
+    // An MBTiles archive holding a single tile at 0/0/0 and the given metadata
+    fn write_mbtiles(path: &Path, metadata: &[(&str, &str)], tile: &[u8]) {
+        let conn = Connection::open(path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE metadata (name TEXT, value TEXT);
+            CREATE TABLE tiles (zoom_level INTEGER, tile_column INTEGER, tile_row INTEGER,
+                tile_data BLOB);",
+        )
+        .unwrap();
+        for (name, value) in metadata {
+            conn.execute("INSERT INTO metadata VALUES (?1, ?2)", [name, value])
+                .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO tiles VALUES (0, 0, 0, ?1)",
+            rusqlite::params![tile],
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn mbtiles_archives_describe_their_tile_set_and_reuse_connections() {
+        let directory = std::env::temp_dir().join(format!("gw-tiles-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let json = serde_json::json!({
+            "vector_layers": [
+                { "id": "roads", "fields": { "name": "String" }, "maxzoom": 12 },
+                { "id": "rivers", "fields": { "width": "Number" } }
+            ]
+        })
+        .to_string();
+        write_mbtiles(
+            &directory.join("basemap.mbtiles"),
+            &[
+                ("format", "pbf"),
+                ("minzoom", "2"),
+                ("maxzoom", "14"),
+                ("json", &json),
+            ],
+            b"tile",
+        );
+        write_mbtiles(
+            &directory.join("imagery.mbtiles"),
+            &[("format", "png")],
+            b"png",
+        );
+
+        let connector = TileArchiveConnector::new(TileArchiveConnection {
+            directory: directory.to_string_lossy().into_owned(),
+        })
+        .unwrap();
+        let tile_set = connector.get_tile_set("", "basemap").await;
+        let first = connector.get_tile("", "basemap", 0, 0, 0, None).await;
+        let second = connector.get_tile("", "basemap", 0, 0, 0, None).await;
+        let imagery = connector.get_tile("", "imagery", 0, 0, 0, None).await;
+        let archive = connector.archive("basemap").await.unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        let tile_set = tile_set.unwrap().unwrap();
+        assert_eq!((tile_set.minzoom, tile_set.maxzoom), (Some(2), Some(14)));
+        let layers: Vec<(&str, u32, u32)> = tile_set
+            .vector_layers
+            .iter()
+            .map(|layer| (layer.id.as_str(), layer.minzoom, layer.maxzoom))
+            .collect();
+        assert_eq!(layers, vec![("roads", 2, 12), ("rivers", 2, 14)]);
+        assert_eq!(
+            tile_set.vector_layers[1].fields.get("width"),
+            Some(&FieldType::Number)
+        );
+
+        assert_eq!(first.unwrap(), b"tile");
+        assert_eq!(second.unwrap(), b"tile");
+        assert!(imagery.is_err());
+        // The connection opened to read the metadata serves every tile
+        let ArchiveReader::MbTiles { idle, .. } = &archive.reader else {
+            panic!("basemap should be read as MBTiles");
+        };
+        assert_eq!(idle.lock().unwrap().len(), 1);
+    }
+}
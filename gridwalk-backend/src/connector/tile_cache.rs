@@ -0,0 +1,488 @@
+use crate::GeoConnector;
+use anyhow::Result;
+use axum::body::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use lru::LruCache;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+use tokio::fs;
+use tracing::warn;
+
+// (connection_id, namespace, source_name)
+type SourceKey = (String, String, String);
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub connection_id: String,
+    pub namespace: String,
+    pub source_name: String,
+    pub z: u32,
+    pub x: u32,
+    pub y: u32,
+    // Attribute columns to include, None for all. Kept sorted so the same
+    // selection in a different order shares a cache entry.
+    pub fields: Option<Vec<String>>,
+}
+
+impl TileKey {
+    fn is_in_source(&self, connection_id: &str, namespace: &str, source_name: &str) -> bool {
+        self.connection_id == connection_id
+            && self.namespace == namespace
+            && self.source_name == source_name
+    }
+
+    fn source_key(&self) -> SourceKey {
+        (
+            self.connection_id.clone(),
+            self.namespace.clone(),
+            self.source_name.clone(),
+        )
+    }
+
+    // Directory name for the field selection in the disk cache
+    fn fields_component(&self) -> String {
+        match &self.fields {
+            None => "all".to_string(),
+            Some(fields) => format!("{:x}", Sha256::digest(fields.join(",").as_bytes())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TileEncoding {
+    Identity,
+    Gzip,
+    Brotli,
+}
+
+impl TileEncoding {
+    // Pick the preferred encoding from an Accept-Encoding header, favouring brotli
+    pub fn from_accept_encoding(accept_encoding: Option<&str>) -> Self {
+        let accepted: Vec<&str> = accept_encoding
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|part| {
+                let mut params = part.split(';').map(str::trim);
+                let coding = params.next()?;
+                let rejected = params
+                    .filter_map(|param| param.strip_prefix("q="))
+                    .any(|q| q.parse::<f32>().map(|q| q <= 0.0).unwrap_or(false));
+                (!rejected).then_some(coding)
+            })
+            .collect();
+
+        let accepts = |coding: &str| {
+            accepted
+                .iter()
+                .any(|c| c.eq_ignore_ascii_case(coding) || *c == "*")
+        };
+        if accepts("br") {
+            TileEncoding::Brotli
+        } else if accepts("gzip") {
+            TileEncoding::Gzip
+        } else {
+            TileEncoding::Identity
+        }
+    }
+
+    pub fn content_encoding(&self) -> Option<&'static str> {
+        match self {
+            TileEncoding::Identity => None,
+            TileEncoding::Gzip => Some("gzip"),
+            TileEncoding::Brotli => Some("br"),
+        }
+    }
+}
+
+// Compressed variants are built on first request and shared by every clone of
+// the tile, so each encoding is only computed once while the tile is cached
+#[derive(Debug, Clone)]
+pub struct CachedTile {
+    pub data: Bytes,
+    pub etag: String,
+    gzip: Arc<OnceLock<Bytes>>,
+    brotli: Arc<OnceLock<Bytes>>,
+}
+
+impl CachedTile {
+    pub fn new(data: Vec<u8>) -> Self {
+        let etag = format!("{:x}", Sha256::digest(&data));
+        CachedTile {
+            data: Bytes::from(data),
+            etag,
+            gzip: Arc::new(OnceLock::new()),
+            brotli: Arc::new(OnceLock::new()),
+        }
+    }
+
+    // Each encoding is a different representation, so it gets its own ETag
+    pub fn etag_for(&self, encoding: TileEncoding) -> String {
+        match encoding.content_encoding() {
+            Some(coding) => format!("\"{}-{}\"", self.etag, coding),
+            None => format!("\"{}\"", self.etag),
+        }
+    }
+
+    pub fn encoded(&self, encoding: TileEncoding) -> Result<Bytes> {
+        match encoding {
+            TileEncoding::Identity => Ok(self.data.clone()),
+            TileEncoding::Gzip => cached_encoding(&self.gzip, || gzip_encode(&self.data)),
+            TileEncoding::Brotli => cached_encoding(&self.brotli, || brotli_encode(&self.data)),
+        }
+    }
+
+    // True if an If-None-Match header value matches this tile in the given encoding
+    pub fn matches_etag(&self, if_none_match: &str, encoding: TileEncoding) -> bool {
+        let etag = self.etag_for(encoding);
+        if_none_match
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag)
+    }
+}
+
+fn cached_encoding(
+    cell: &OnceLock<Bytes>,
+    encode: impl FnOnce() -> Result<Vec<u8>>,
+) -> Result<Bytes> {
+    if let Some(data) = cell.get() {
+        return Ok(data.clone());
+    }
+    let data = Bytes::from(encode()?);
+    Ok(cell.get_or_init(|| data).clone())
+}
+
+fn gzip_encode(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn brotli_encode(data: &[u8]) -> Result<Vec<u8>> {
+    // Quality 11 is too slow to run on a tile request, 5 keeps most of the gain
+    let params = brotli::enc::BrotliEncoderParams {
+        quality: 5,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    brotli::BrotliCompress(&mut &data[..], &mut output, &params)?;
+    Ok(output)
+}
+
+// Read-through cache in front of GeoConnector::get_tile. Tiles are held in an
+// in-memory LRU and, if a directory is configured, also written to disk so they
+// survive a restart. The disk cache is kept under a size limit by removing the
+// oldest tiles.
+#[derive(Clone)]
+pub struct TileCache {
+    memory: Arc<Mutex<LruCache<TileKey, CachedTile>>>,
+    disk_dir: Option<PathBuf>,
+    disk: Arc<DiskUsage>,
+    // Bumped when a source is invalidated, so tiles fetched before then are not
+    // written back into the cache
+    generations: Arc<Mutex<HashMap<SourceKey, u64>>>,
+}
+
+struct DiskUsage {
+    max_bytes: u64,
+    // Size of the directory at the last sweep plus the tiles written since. It
+    // starts at the limit, so the first write sweeps and measures what is there.
+    bytes: AtomicU64,
+    sweeping: AtomicBool,
+}
+
+impl TileCache {
+    pub fn new(capacity: usize, disk_dir: Option<PathBuf>) -> Self {
+        Self::with_disk_limit(capacity, disk_dir, 1024 * 1024 * 1024)
+    }
+
+    pub fn with_disk_limit(capacity: usize, disk_dir: Option<PathBuf>, max_bytes: u64) -> Self {
+        let capacity = NonZeroUsize::new(capacity).unwrap_or(NonZeroUsize::MIN);
+        TileCache {
+            memory: Arc::new(Mutex::new(LruCache::new(capacity))),
+            disk_dir,
+            disk: Arc::new(DiskUsage {
+                max_bytes,
+                bytes: AtomicU64::new(max_bytes),
+                sweeping: AtomicBool::new(false),
+            }),
+            generations: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let capacity = std::env::var("GW_TILE_CACHE_SIZE")
+            .unwrap_or_else(|_| "10000".to_string())
+            .parse::<usize>()
+            .expect("GW_TILE_CACHE_SIZE must be a number");
+        let disk_dir = std::env::var("GW_TILE_CACHE_DIR").ok().map(PathBuf::from);
+        let disk_mb = std::env::var("GW_TILE_CACHE_DISK_MB")
+            .unwrap_or_else(|_| "1024".to_string())
+            .parse::<u64>()
+            .expect("GW_TILE_CACHE_DISK_MB must be a number");
+        Self::with_disk_limit(capacity, disk_dir, disk_mb * 1024 * 1024)
+    }
+
+    fn generation(&self, key: &TileKey) -> u64 {
+        let generations = self.generations.lock().unwrap();
+        generations
+            .get(&key.source_key())
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub async fn get_tile(
+        &self,
+        connector: &Arc<dyn GeoConnector>,
+        key: &TileKey,
+    ) -> Result<CachedTile> {
+        if let Some(tile) = self.memory.lock().unwrap().get(key) {
+            return Ok(tile.clone());
+        }
+
+        if let Some(path) = self.tile_path(key) {
+            if let Ok(data) = fs::read(&path).await {
+                let tile = CachedTile::new(data);
+                self.memory.lock().unwrap().put(key.clone(), tile.clone());
+                return Ok(tile);
+            }
+        }
+
+        let generation = self.generation(key);
+        let data = connector
+            .get_tile(
+                &key.namespace,
+                &key.source_name,
+                key.z,
+                key.x,
+                key.y,
+                key.fields.as_deref(),
+            )
+            .await?;
+        let tile = CachedTile::new(data);
+
+        // The source was invalidated while the tile was fetched, so it may be stale
+        if self.generation(key) != generation {
+            return Ok(tile);
+        }
+
+        if let Some(path) = self.tile_path(key) {
+            match write_tile(&path, &tile.data).await {
+                // Checked again as the invalidation may have cleared the directory
+                // before the write finished
+                Ok(_) if self.generation(key) != generation => {
+                    let _ = fs::remove_file(&path).await;
+                }
+                Ok(_) => self.record_disk_write(tile.data.len() as u64),
+                Err(e) => warn!(
+                    "Failed to write tile to disk cache {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+
+        // Checked under the memory lock, which the invalidation takes after
+        // bumping the generation
+        let mut memory = self.memory.lock().unwrap();
+        if self.generation(key) == generation {
+            memory.put(key.clone(), tile.clone());
+        }
+        Ok(tile)
+    }
+
+    // Drop every cached tile for a source, e.g. after its table is replaced
+    pub async fn invalidate_source(&self, connection_id: &str, namespace: &str, source_name: &str) {
+        {
+            let mut generations = self.generations.lock().unwrap();
+            *generations
+                .entry((
+                    connection_id.to_string(),
+                    namespace.to_string(),
+                    source_name.to_string(),
+                ))
+                .or_default() += 1;
+        }
+
+        {
+            let mut memory = self.memory.lock().unwrap();
+            let stale: Vec<TileKey> = memory
+                .iter()
+                .filter(|(key, _)| key.is_in_source(connection_id, namespace, source_name))
+                .map(|(key, _)| key.clone())
+                .collect();
+            for key in stale {
+                memory.pop(&key);
+            }
+        }
+
+        if let Some(dir) = self.source_dir(connection_id, namespace, source_name) {
+            if let Err(e) = fs::remove_dir_all(&dir).await {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to clear disk tile cache {}: {}", dir.display(), e);
+                }
+            }
+        }
+    }
+
+    // Start a sweep of the disk cache once writes may have taken it over the limit
+    fn record_disk_write(&self, len: u64) {
+        let total = self.disk.bytes.fetch_add(len, Ordering::Relaxed) + len;
+        if total <= self.disk.max_bytes || self.disk.sweeping.swap(true, Ordering::AcqRel) {
+            return;
+        }
+        let (Some(dir), disk) = (self.disk_dir.clone(), self.disk.clone()) else {
+            return;
+        };
+        tokio::task::spawn_blocking(move || {
+            // Trimmed below the limit so a sweep is not needed for every new tile
+            match sweep_disk_cache(&dir, disk.max_bytes / 10 * 9) {
+                Ok(remaining) => disk.bytes.store(remaining, Ordering::Relaxed),
+                Err(e) => warn!("Failed to sweep disk tile cache {}: {}", dir.display(), e),
+            }
+            disk.sweeping.store(false, Ordering::Release);
+        });
+    }
+
+    fn source_dir(
+        &self,
+        connection_id: &str,
+        namespace: &str,
+        source_name: &str,
+    ) -> Option<PathBuf> {
+        self.disk_dir.as_ref().map(|dir| {
+            dir.join(path_component(connection_id))
+                .join(path_component(namespace))
+                .join(path_component(source_name))
+        })
+    }
+
+    fn tile_path(&self, key: &TileKey) -> Option<PathBuf> {
+        self.source_dir(&key.connection_id, &key.namespace, &key.source_name)
+            .map(|dir| {
+                dir.join(key.fields_component())
+                    .join(key.z.to_string())
+                    .join(key.x.to_string())
+                    .join(format!("{}.mvt", key.y))
+            })
+    }
+}
+
+// Names come from the request path, so anything that is not a plain identifier
+// is hex encoded to keep it inside the cache directory
+fn path_component(name: &str) -> String {
+    let is_plain = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if is_plain {
+        name.to_string()
+    } else {
+        let hex: String = name.bytes().map(|b| format!("{:02x}", b)).collect();
+        format!("~{}", hex)
+    }
+}
+
+async fn write_tile(path: &PathBuf, data: &[u8]) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    // Write then rename so a concurrent reader never sees a partial tile. Each
+    // write has its own temp file as several requests can miss the same tile.
+    let temp_path = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4()));
+    fs::write(&temp_path, data).await?;
+    fs::rename(&temp_path, path).await
+}
+
+// Remove the oldest tiles until the cache holds at most target bytes, returning
+// the bytes left
+fn sweep_disk_cache(dir: &Path, target: u64) -> std::io::Result<u64> {
+    let mut tiles: Vec<(SystemTime, u64, PathBuf)> = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            // Removed by an invalidation while the sweep was running
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                tiles.push((modified, metadata.len(), entry.path()));
+            }
+        }
+    }
+
+    let mut total: u64 = tiles.iter().map(|(_, len, _)| len).sum();
+    tiles.sort();
+    for (_, len, path) in tiles {
+        if total <= target {
+            break;
+        }
+        match std::fs::remove_file(&path) {
+            Ok(_) => total -= len,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => total -= len,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sweep_removes_oldest_tiles_first() {
+        let dir = std::env::temp_dir().join(format!("gw-tile-cache-{}", uuid::Uuid::new_v4()));
+        let tile_dir = dir.join("primary").join("ws").join("roads");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(tile_dir.join(format!("{}.mvt", name)), [0u8; 100]).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+
+        let remaining = sweep_disk_cache(&dir, 200).unwrap();
+        assert_eq!(remaining, 200);
+        assert!(!tile_dir.join("a.mvt").exists());
+        assert!(tile_dir.join("b.mvt").exists());
+        assert!(tile_dir.join("c.mvt").exists());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_writes_of_a_tile_leave_one_whole_tile() {
+        let dir = std::env::temp_dir().join(format!("gw-tile-cache-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("roads").join("1.mvt");
+        let writes = (0..8u8).map(|i| {
+            let path = path.clone();
+            tokio::spawn(async move { write_tile(&path, &[i; 10_000]).await })
+        });
+        for write in futures::future::join_all(writes).await {
+            write.unwrap().unwrap();
+        }
+
+        let tile = std::fs::read(&path).unwrap();
+        assert_eq!(tile.len(), 10_000);
+        assert!(tile.iter().all(|b| *b == tile[0]));
+        assert_eq!(
+            std::fs::read_dir(path.parent().unwrap()).unwrap().count(),
+            1
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
-    Connection, ConnectionAccess, Layer, Project, Session, User, Workspace, WorkspaceMember,
-    WorkspaceRole,
+    Connection, ConnectionAccess, ConnectorConfig, CreateUser, GlobalRole, Job, Layer,
+    PostgresConnection, Project, Session, User, Workspace, WorkspaceMember, WorkspaceRole,
 };
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use async_trait::async_trait;
+use std::sync::Arc;
+use tracing::info;
 
 #[async_trait]
 pub trait Database: Send + Sync + UserStore + SessionStore + 'static {}
@@ -36,12 +38,27 @@ pub trait UserStore: Send + Sync + 'static {
         wsp: &Workspace,
         con_id: &str,
     ) -> Result<ConnectionAccess>;
+    // The connections a workspace has access to, each listed once
+    async fn get_workspace_connections(&self, wsp: &Workspace) -> Result<Vec<Connection>>;
     async fn create_layer_record(&self, layer: &Layer) -> Result<()>;
+    async fn get_layers(&self, wsp: &Workspace) -> Result<Vec<Layer>>;
+    async fn get_layer(&self, wsp: &Workspace, layer_name: &str) -> Result<Layer>;
+    async fn delete_layer(&self, layer: &Layer) -> Result<()>;
     async fn create_project(&self, project: &Project) -> Result<()>;
     async fn get_workspaces(&self, user: &User) -> Result<Vec<String>>;
     async fn get_projects(&self, workspace_id: &str) -> Result<Vec<Project>>;
+    async fn get_project(&self, workspace_id: &str, project_id: &str) -> Result<Project>;
+    async fn update_project_document(
+        &self,
+        project: &Project,
+        expected_revision: u64,
+    ) -> Result<()>;
     async fn delete_project(&self, project: &Project) -> Result<()>;
     async fn update_user_password(&self, user: &User) -> Result<()>;
+    async fn create_job(&self, job: &Job) -> Result<()>;
+    async fn get_job(&self, id: &str) -> Result<Job>;
+    async fn update_job(&self, job: &Job) -> Result<()>;
+    async fn get_unfinished_jobs(&self) -> Result<Vec<Job>>;
 }
 
 #[async_trait]
@@ -50,3 +67,85 @@ pub trait SessionStore: Send + Sync + 'static {
     async fn create_session(&self, user: Option<&'life1 User>, session_id: &str) -> Result<()>;
     async fn delete_session(&self, session_id: &str) -> Result<()>;
 }
+
+// Primary geodatabase connection details, shared by every app database backend
+pub fn primary_connection_from_env() -> PostgresConnection {
+    let postgres_host = std::env::var("GW_POSTGRES_HOST").expect("GW_POSTGRES_HOST must be set");
+    // Default to 5432 if not set
+    let postgres_port = std::env::var("GW_POSTGRES_PORT")
+        .unwrap_or_else(|_| "5432".to_string())
+        .parse::<u16>()
+        .expect("GW_POSTGRES_PORT must be a number");
+
+    let postgres_db = std::env::var("GW_POSTGRES_DB").expect("GW_POSTGRES_DB must be set");
+    let postgres_username =
+        std::env::var("GW_POSTGRES_USERNAME").expect("GW_POSTGRES_USERNAME must be set");
+    let postgres_password =
+        std::env::var("GW_POSTGRES_PASSWORD").expect("GW_POSTGRES_PASSWORD must be set");
+
+    PostgresConnection {
+        host: postgres_host,
+        port: postgres_port,
+        database: postgres_db,
+        schema: None,
+        username: postgres_username,
+        password: postgres_password,
+    }
+}
+
+pub fn initial_user_from_env() -> CreateUser {
+    let gw_user_email = std::env::var("GW_USER_EMAIL").expect("GW_USER_EMAIL must be set");
+
+    let gw_user_password =
+        std::env::var("GW_USER_PASSWORD").expect("GW_USER_PASSWORD must be set");
+
+    CreateUser {
+        email: gw_user_email,
+        first_name: "Initial".to_string(),
+        last_name: "User".to_string(),
+        global_role: Some(GlobalRole::Super),
+        password: gw_user_password,
+    }
+}
+
+// Create the admin user and primary connection records if they do not exist yet
+pub async fn init_app_data(
+    database: &Arc<dyn Database>,
+    geoconnection: PostgresConnection,
+    initial_user: &CreateUser,
+) -> Result<()> {
+    // If inital user type is not super, return error
+    if initial_user.global_role != Some(GlobalRole::Super) {
+        return Err(anyhow!("Initial user must be of type Super"));
+    }
+
+    // Get initial user
+    let user = User::from_email(database, &initial_user.email).await;
+    match user {
+        Ok(_) => {
+            info!("db init: admin user exists.");
+        }
+        Err(_) => {
+            info!("db init: creating admin user.");
+            User::create(database, initial_user).await?;
+            info!("db init: admin user created.");
+        }
+    }
+
+    // Check if primary connection exists, create if it doesn't
+    match database.get_connection("primary").await {
+        Ok(_) => {
+            info!("db init: primary connection exists.");
+        }
+        Err(_) => {
+            info!("db init: creating primary connection.");
+            let primary_connection = Connection {
+                id: "primary".to_string(),
+                name: "Primary".to_string(),
+                config: ConnectorConfig::Postgis(geoconnection),
+            };
+            primary_connection.create_record(database).await?;
+        }
+    }
+    Ok(())
+}
@@ -1,7 +1,9 @@
+use crate::data::{init_app_data, initial_user_from_env, primary_connection_from_env};
 use crate::data::{Database, UserStore};
 use crate::{
-    Connection, ConnectionAccess, CreateUser, Email, GlobalRole, Layer, PostgresConnection,
-    Project, User, Workspace, WorkspaceMember, WorkspaceRole,
+    Connection, ConnectionAccess, ConnectorConfig, CreateUser, Email, Job, JobStatus, Layer,
+    LayerNotFoundError, PostgresConnection, Project, ProjectNotFoundError, ProjectRevisionConflict,
+    User, Workspace, WorkspaceMember, WorkspaceRole,
 };
 use anyhow::{anyhow, Result};
 use async_trait::async_trait;
@@ -29,25 +31,6 @@ impl Dynamodb {
         let dynamodb_table =
             std::env::var("GW_DYNAMODB_TABLE").expect("GW_DYNAMODB_TABLE must be set");
 
-        let postgres_host =
-            std::env::var("GW_POSTGRES_HOST").expect("GW_POSTGRES_HOST must be set");
-        // Default to 5432 if not set
-        let postgres_port = std::env::var("GW_POSTGRES_PORT")
-            .unwrap_or_else(|_| "5432".to_string())
-            .parse::<u16>()
-            .expect("GW_POSTGRES_PORT must be a number");
-
-        let postgres_db = std::env::var("GW_POSTGRES_DB").expect("GW_POSTGRES_DB must be set");
-        let postgres_username =
-            std::env::var("GW_POSTGRES_USERNAME").expect("GW_POSTGRES_USERNAME must be set");
-        let postgres_password =
-            std::env::var("GW_POSTGRES_PASSWORD").expect("GW_POSTGRES_PASSWORD must be set");
-
-        let gw_user_email = std::env::var("GW_USER_EMAIL").expect("GW_USER_EMAIL must be set");
-
-        let gw_user_password =
-            std::env::var("GW_USER_PASSWORD").expect("GW_USER_PASSWORD must be set");
-
         let local = std::env::var("GW_LOCAL")
             .unwrap_or_else(|_| "false".to_string())
             .parse::<bool>()
@@ -56,21 +39,8 @@ impl Dynamodb {
         Self::new_with_config(
             local,
             &dynamodb_table,
-            PostgresConnection {
-                host: postgres_host,
-                port: postgres_port,
-                database: postgres_db,
-                schema: None,
-                username: postgres_username,
-                password: postgres_password,
-            },
-            CreateUser {
-                email: gw_user_email,
-                first_name: "Initial".to_string(),
-                last_name: "User".to_string(),
-                global_role: Some(GlobalRole::Super),
-                password: gw_user_password,
-            },
+            primary_connection_from_env(),
+            initial_user_from_env(),
         )
         .await
     }
@@ -87,11 +57,6 @@ impl Dynamodb {
             info!("Running in local mode");
         }
 
-        // If inital user type is not super, return error
-        if initial_user.global_role != Some(GlobalRole::Super) {
-            return Err(anyhow!("Initial user must be of type Super"));
-        }
-
         // Set endpoint url to localhost to run locally
         let config = match local {
             true => {
@@ -120,35 +85,7 @@ impl Dynamodb {
             table_name: app_db_table_name.into(),
         }) as Arc<dyn Database>;
 
-        // Get initial user
-        let user = User::from_email(&dynamodb, &initial_user.email).await;
-        match user {
-            Ok(_) => {
-                info!("db init: admin user exists.");
-            }
-            Err(_) => {
-                info!("db init: creating admin user.");
-                User::create(&dynamodb, &initial_user).await?;
-                info!("db init: admin user created.");
-            }
-        }
-
-        // Check if primary connection exists, create if it doesn't
-        match dynamodb.get_connection("primary").await {
-            Ok(_) => {
-                info!("db init: primary connection exists.");
-            }
-            Err(_) => {
-                info!("db init: creating primary connection.");
-                let primary_connection = Connection {
-                    id: "primary".to_string(),
-                    name: "Primary".to_string(),
-                    connector_type: "Postgres".to_string(),
-                    config: geoconnection,
-                };
-                primary_connection.create_record(&dynamodb).await?;
-            }
-        }
+        init_app_data(&dynamodb, geoconnection, &initial_user).await?;
         Ok(dynamodb)
     }
 
@@ -486,7 +423,10 @@ impl UserStore for Dynamodb {
             .send()
             .await
         {
-            Ok(response) => Ok(response.item.unwrap().into()),
+            Ok(response) => response
+                .item
+                .map(|item| item.into())
+                .ok_or_else(|| anyhow!("workspace member not found")),
             Err(_e) => Err(anyhow!("workspace not found")),
         }
     }
@@ -595,22 +535,26 @@ impl UserStore for Dynamodb {
         item.insert(String::from("name"), AV::S(con.clone().name));
         item.insert(
             String::from("connector_type"),
-            AV::S(con.clone().connector_type),
-        );
-        item.insert(String::from("pg_host"), AV::S(con.clone().config.host));
-        item.insert(
-            String::from("pg_port"),
-            AV::S(con.clone().config.port.to_string()),
-        );
-        item.insert(String::from("pg_db"), AV::S(con.clone().config.database));
-        item.insert(
-            String::from("pg_username"),
-            AV::S(con.clone().config.username),
-        );
-        item.insert(
-            String::from("pg_password"),
-            AV::S(con.clone().config.password),
+            AV::S(con.connector_type().to_string()),
         );
+        match &con.config {
+            ConnectorConfig::Postgis(config) => {
+                item.insert(String::from("pg_host"), AV::S(config.host.clone()));
+                item.insert(String::from("pg_port"), AV::S(config.port.to_string()));
+                item.insert(String::from("pg_db"), AV::S(config.database.clone()));
+                item.insert(String::from("pg_username"), AV::S(config.username.clone()));
+                item.insert(String::from("pg_password"), AV::S(config.password.clone()));
+                if let Some(schema) = &config.schema {
+                    item.insert(String::from("pg_schema"), AV::S(schema.clone()));
+                }
+            }
+            config => {
+                item.insert(
+                    String::from("connector_config"),
+                    AV::S(serde_json::to_string(config)?),
+                );
+            }
+        }
 
         self.client
             .put_item()
@@ -636,8 +580,8 @@ impl UserStore for Dynamodb {
         {
             Ok(response) => response
                 .item
-                .ok_or_else(|| anyhow!("connection not found"))
-                .map(Into::into),
+                .ok_or_else(|| anyhow!("connection not found"))?
+                .try_into(),
             Err(e) => Err(anyhow!("failed to fetch connection: {}", e)),
         }
     }
@@ -731,6 +675,53 @@ impl UserStore for Dynamodb {
         Ok(connections[0].clone())
     }
 
+    async fn get_workspace_connections(&self, wsp: &Workspace) -> Result<Vec<Connection>> {
+        let mut connection_ids: Vec<String> = self
+            .get_accessible_connections(wsp)
+            .await?
+            .into_iter()
+            .map(|ca| ca.connection_id)
+            .collect();
+        connection_ids.sort();
+        connection_ids.dedup();
+        if connection_ids.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let keys: Vec<HashMap<String, AV>> = connection_ids
+            .iter()
+            .map(|connection_id| {
+                let key = format!("CON#{connection_id}");
+                HashMap::from([
+                    ("PK".to_string(), AV::S(key.clone())),
+                    ("SK".to_string(), AV::S(key)),
+                ])
+            })
+            .collect();
+        let keys_and_attributes = KeysAndAttributes::builder().set_keys(Some(keys)).build()?;
+
+        // Get all connection records in one batch
+        let connection_responses = self
+            .client
+            .batch_get_item()
+            .set_request_items(Some(HashMap::from([(
+                self.table_name.clone(),
+                keys_and_attributes,
+            )])))
+            .send()
+            .await?;
+
+        let mut connections = connection_responses
+            .responses
+            .and_then(|mut r| r.remove(&self.table_name))
+            .unwrap_or_default()
+            .into_iter()
+            .map(Connection::try_from)
+            .collect::<Result<Vec<_>>>()?;
+        connections.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(connections)
+    }
+
     async fn create_layer_record(&self, layer: &Layer) -> Result<()> {
         let mut item = std::collections::HashMap::new();
 
@@ -748,6 +739,29 @@ impl UserStore for Dynamodb {
             String::from("created_at"),
             AV::N(layer.created_at.to_string()),
         );
+        if let Some(source_srid) = layer.source_srid {
+            item.insert(String::from("source_srid"), AV::N(source_srid.to_string()));
+        }
+        if let Some(storage_srid) = layer.storage_srid {
+            item.insert(
+                String::from("storage_srid"),
+                AV::N(storage_srid.to_string()),
+            );
+        }
+        item.insert(String::from("mode"), AV::S(layer.mode.to_string()));
+        item.insert(
+            String::from("connection_id"),
+            AV::S(layer.connection_id.clone()),
+        );
+        if let Some(updated_at) = layer.updated_at {
+            item.insert(String::from("updated_at"), AV::N(updated_at.to_string()));
+        }
+        if let Some(validation) = &layer.validation {
+            item.insert(
+                String::from("validation"),
+                AV::S(serde_json::to_string(validation)?),
+            );
+        }
 
         self.client
             .put_item()
@@ -759,6 +773,74 @@ impl UserStore for Dynamodb {
         Ok(())
     }
 
+    async fn get_layers(&self, wsp: &Workspace) -> Result<Vec<Layer>> {
+        let mut layers = Vec::new();
+        let mut start_key = None;
+        loop {
+            let response = self
+                .client
+                .query()
+                .table_name(&self.table_name)
+                .key_condition_expression("PK = :pk AND begins_with(SK, :prefix)")
+                .expression_attribute_values(":pk", AV::S(format!("WSP#{}", wsp.id)))
+                .expression_attribute_values(":prefix", AV::S("LAYER#".to_string()))
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| anyhow!("Failed to query DynamoDB: {}", e))?;
+
+            layers.extend(
+                response
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(Layer::from),
+            );
+
+            start_key = response.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(layers)
+    }
+
+    async fn get_layer(&self, wsp: &Workspace, layer_name: &str) -> Result<Layer> {
+        match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(format!("WSP#{}", wsp.id)))
+            .key("SK", AV::S(format!("LAYER#{}", layer_name)))
+            .send()
+            .await
+        {
+            Ok(response) => response
+                .item
+                .ok_or_else(|| {
+                    LayerNotFoundError {
+                        name: layer_name.to_string(),
+                    }
+                    .into()
+                })
+                .map(Into::into),
+            Err(e) => Err(anyhow!("failed to fetch layer: {}", e)),
+        }
+    }
+
+    async fn delete_layer(&self, layer: &Layer) -> Result<()> {
+        self.client
+            .delete_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(format!("WSP#{}", layer.workspace_id)))
+            .key("SK", AV::S(format!("LAYER#{}", layer.name)))
+            .send()
+            .await?;
+
+        Ok(())
+    }
+
     async fn delete_project(&self, project: &Project) -> Result<()> {
         let mut key = std::collections::HashMap::new();
         key.insert(
@@ -794,6 +876,14 @@ impl UserStore for Dynamodb {
             String::from("created_at"),
             AV::N(project.created_at.to_string()),
         );
+        item.insert(
+            String::from("revision"),
+            AV::N(project.revision.to_string()),
+        );
+        item.insert(
+            String::from("document"),
+            AV::S(serde_json::to_string(&project.document)?),
+        );
 
         self.client
             .put_item()
@@ -804,4 +894,184 @@ impl UserStore for Dynamodb {
 
         Ok(())
     }
+
+    async fn get_project(&self, workspace_id: &str, project_id: &str) -> Result<Project> {
+        match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(format!("WSP#{}", workspace_id)))
+            .key("SK", AV::S(format!("PROJ#{}", project_id)))
+            .send()
+            .await
+        {
+            Ok(response) => response
+                .item
+                .ok_or_else(|| ProjectNotFoundError.into())
+                .map(Into::into),
+            Err(e) => Err(anyhow!("failed to fetch project: {}", e)),
+        }
+    }
+
+    async fn update_project_document(
+        &self,
+        project: &Project,
+        expected_revision: u64,
+    ) -> Result<()> {
+        // Projects created before documents existed have no revision attribute
+        let condition = if expected_revision == 0 {
+            "attribute_exists(PK) AND (attribute_not_exists(#revision) OR #revision = :expected)"
+        } else {
+            "#revision = :expected"
+        };
+
+        let result = self
+            .client
+            .update_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(format!("WSP#{}", project.workspace_id)))
+            .key("SK", AV::S(format!("PROJ#{}", project.id)))
+            .update_expression("SET #document = :document, #revision = :revision")
+            .condition_expression(condition)
+            .expression_attribute_names("#document", "document")
+            .expression_attribute_names("#revision", "revision")
+            .expression_attribute_values(
+                ":document",
+                AV::S(serde_json::to_string(&project.document)?),
+            )
+            .expression_attribute_values(":revision", AV::N(project.revision.to_string()))
+            .expression_attribute_values(":expected", AV::N(expected_revision.to_string()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(()),
+            Err(e) => {
+                let service_error = e.into_service_error();
+                if service_error.is_conditional_check_failed_exception() {
+                    Err(ProjectRevisionConflict.into())
+                } else {
+                    Err(anyhow!("Failed to update project: {}", service_error))
+                }
+            }
+        }
+    }
+
+    async fn create_job(&self, job: &Job) -> Result<()> {
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(job_item(job)?))
+            .condition_expression("attribute_not_exists(PK)")
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to create job: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> Result<Job> {
+        let key = format!("JOB#{}", id);
+        match self
+            .client
+            .get_item()
+            .table_name(&self.table_name)
+            .key("PK", AV::S(key.clone()))
+            .key("SK", AV::S(key))
+            .send()
+            .await
+        {
+            Ok(response) => response
+                .item
+                .ok_or_else(|| anyhow!("job not found"))?
+                .try_into(),
+            Err(e) => Err(anyhow!("failed to fetch job: {}", e)),
+        }
+    }
+
+    async fn update_job(&self, job: &Job) -> Result<()> {
+        self.client
+            .put_item()
+            .table_name(&self.table_name)
+            .set_item(Some(job_item(job)?))
+            .condition_expression("attribute_exists(PK)")
+            .send()
+            .await
+            .map_err(|e| anyhow!("Failed to update job: {}", e))?;
+
+        Ok(())
+    }
+
+    async fn get_unfinished_jobs(&self) -> Result<Vec<Job>> {
+        // Only run at startup, so a filtered scan is acceptable here
+        let mut jobs = Vec::new();
+        let mut start_key = None;
+        loop {
+            let response = self
+                .client
+                .scan()
+                .table_name(&self.table_name)
+                .filter_expression(
+                    "begins_with(PK, :prefix) AND (#status = :queued OR #status = :running)",
+                )
+                .expression_attribute_names("#status", "status")
+                .expression_attribute_values(":prefix", AV::S("JOB#".to_string()))
+                .expression_attribute_values(":queued", AV::S(JobStatus::Queued.to_string()))
+                .expression_attribute_values(":running", AV::S(JobStatus::Running.to_string()))
+                .set_exclusive_start_key(start_key)
+                .send()
+                .await
+                .map_err(|e| anyhow!("Failed to scan DynamoDB: {}", e))?;
+
+            for item in response.items.unwrap_or_default() {
+                jobs.push(item.try_into()?);
+            }
+
+            start_key = response.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        Ok(jobs)
+    }
+}
+
+fn job_item(job: &Job) -> Result<HashMap<String, AV>> {
+    let key = format!("JOB#{}", job.id);
+    let mut item = HashMap::new();
+    item.insert(String::from("PK"), AV::S(key.clone()));
+    item.insert(String::from("SK"), AV::S(key));
+    item.insert(
+        String::from("workspace_id"),
+        AV::S(job.workspace_id.clone()),
+    );
+    item.insert(String::from("created_by"), AV::S(job.created_by.clone()));
+    item.insert(String::from("status"), AV::S(job.status.to_string()));
+    item.insert(
+        String::from("layer"),
+        AV::S(serde_json::to_string(&job.layer)?),
+    );
+    item.insert(String::from("file_path"), AV::S(job.file_path.clone()));
+    if let Some(row_count) = job.row_count {
+        item.insert(String::from("row_count"), AV::N(row_count.to_string()));
+    }
+    if let Some(error) = &job.error {
+        item.insert(String::from("error"), AV::S(error.clone()));
+    }
+    if let Some(failed_rows) = &job.failed_rows {
+        item.insert(
+            String::from("failed_rows"),
+            AV::S(serde_json::to_string(failed_rows)?),
+        );
+    }
+    item.insert(
+        String::from("created_at"),
+        AV::N(job.created_at.to_string()),
+    );
+    item.insert(
+        String::from("updated_at"),
+        AV::N(job.updated_at.to_string()),
+    );
+    Ok(item)
 }
@@ -1,7 +1,8 @@
 use crate::{
-    Connection, ConnectionAccess, ConnectionAccessConfig, Email, PostgresConnection, Project,
-    Session, User, Workspace, WorkspaceMember,
+    Connection, ConnectionAccess, ConnectionAccessConfig, ConnectorConfig, Email, Job, Layer,
+    PostgresConnection, Project, Session, User, Workspace, WorkspaceMember,
 };
+use anyhow::{anyhow, Result};
 use aws_sdk_dynamodb::types::AttributeValue as AV;
 use std::collections::HashMap;
 
@@ -103,31 +104,41 @@ impl From<HashMap<String, AV>> for Session {
     }
 }
 
-// Convert DynamoDB response into Connection struct
-impl From<HashMap<String, AV>> for Connection {
-    fn from(value: HashMap<String, AV>) -> Self {
-        Connection {
-            id: value.get("PK").unwrap().as_s().unwrap().into(),
-            name: value.get("name").unwrap().as_s().unwrap().into(),
-            connector_type: value.get("connector_type").unwrap().as_s().unwrap().into(),
-            config: PostgresConnection {
-                host: value.get("pg_host").unwrap().as_s().unwrap().into(),
-                port: value
-                    .get("pg_port")
-                    .unwrap()
-                    .as_s()
-                    .unwrap()
-                    .parse()
-                    .unwrap(),
-                database: value.get("pg_db").unwrap().as_s().unwrap().into(),
-                username: value.get("pg_username").unwrap().as_s().unwrap().into(),
-                password: value.get("pg_password").unwrap().as_s().unwrap().into(),
-                schema: value
-                    .get("pg_schema")
-                    .and_then(|v| v.as_s().ok())
-                    .map(Into::into),
-            },
-        }
+// Postgis connections keep their details in pg_* attributes, as they did before
+// other connector types existed. Other connectors store their config as JSON.
+impl TryFrom<HashMap<String, AV>> for Connection {
+    type Error = anyhow::Error;
+
+    fn try_from(value: HashMap<String, AV>) -> Result<Self> {
+        let string = |name: &str| -> Result<String> {
+            value
+                .get(name)
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .ok_or_else(|| anyhow!("connection item is missing {}", name))
+        };
+
+        let connector_type = string("connector_type")?;
+        let config = match connector_type.to_lowercase().as_str() {
+            "postgis" | "postgres" => ConnectorConfig::Postgis(PostgresConnection {
+                host: string("pg_host")?,
+                port: string("pg_port")?.parse()?,
+                database: string("pg_db")?,
+                username: string("pg_username")?,
+                password: string("pg_password")?,
+                schema: string("pg_schema").ok(),
+            }),
+            _ => ConnectorConfig::from_stored(
+                &connector_type,
+                serde_json::from_str(&string("connector_config")?)?,
+            )?,
+        };
+
+        Ok(Connection {
+            id: string("PK")?,
+            name: string("name")?,
+            config,
+        })
     }
 }
 
@@ -176,6 +187,105 @@ impl From<HashMap<String, AV>> for Project {
                 .unwrap()
                 .parse()
                 .unwrap(),
+            revision: value
+                .get("revision")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok())
+                .unwrap_or(0),
+            document: value
+                .get("document")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+impl From<HashMap<String, AV>> for Layer {
+    fn from(value: HashMap<String, AV>) -> Self {
+        let srid = |name: &str| {
+            value
+                .get(name)
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok())
+        };
+        Layer {
+            source_srid: srid("source_srid"),
+            storage_srid: srid("storage_srid"),
+            geometry: None,
+            validation_options: None,
+            validation: value
+                .get("validation")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|json| serde_json::from_str(json).ok()),
+            mode: value
+                .get("mode")
+                .and_then(|v| v.as_s().ok())
+                .and_then(|mode| mode.parse().ok())
+                .unwrap_or_default(),
+            updated_at: value
+                .get("updated_at")
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok()),
+            // Layers saved before connections could be chosen are on the primary one
+            connection_id: value
+                .get("connection_id")
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .unwrap_or_else(|| "primary".to_string()),
+            workspace_id: split_at_hash(value.get("PK").unwrap().as_s().unwrap()).to_string(),
+            name: split_at_hash(value.get("SK").unwrap().as_s().unwrap()).to_string(),
+            uploaded_by: value
+                .get("uploaded_by")
+                .unwrap()
+                .as_s()
+                .unwrap()
+                .to_string(),
+            created_at: value
+                .get("created_at")
+                .unwrap()
+                .as_n()
+                .unwrap()
+                .parse()
+                .unwrap(),
         }
     }
 }
+
+// Jobs hold the layer as JSON, so a corrupt item is reported rather than panicking
+impl TryFrom<HashMap<String, AV>> for Job {
+    type Error = anyhow::Error;
+
+    fn try_from(value: HashMap<String, AV>) -> Result<Self> {
+        let string = |name: &str| -> Result<String> {
+            value
+                .get(name)
+                .and_then(|v| v.as_s().ok())
+                .cloned()
+                .ok_or_else(|| anyhow!("job item is missing {}", name))
+        };
+        let number = |name: &str| -> Option<u64> {
+            value
+                .get(name)
+                .and_then(|v| v.as_n().ok())
+                .and_then(|n| n.parse().ok())
+        };
+
+        Ok(Job {
+            id: split_at_hash(&string("PK")?).to_string(),
+            workspace_id: string("workspace_id")?,
+            created_by: string("created_by")?,
+            layer: serde_json::from_str(&string("layer")?)?,
+            file_path: string("file_path")?,
+            status: string("status")?.parse()?,
+            row_count: number("row_count"),
+            error: string("error").ok(),
+            failed_rows: match string("failed_rows") {
+                Ok(json) => Some(serde_json::from_str(&json)?),
+                Err(_) => None,
+            },
+            created_at: number("created_at").unwrap_or_default(),
+            updated_at: number("updated_at").unwrap_or_default(),
+        })
+    }
+}
@@ -0,0 +1,428 @@
+use crate::data::{init_app_data, initial_user_from_env, primary_connection_from_env};
+use crate::data::{Database, UserStore};
+use crate::utils::create_id;
+use crate::{
+    Connection, ConnectionAccess, CreateUser, GlobalRole, Job, JobStatus, Layer,
+    LayerNotFoundError, PostgresConnection, Project, ProjectNotFoundError, ProjectRevisionConflict,
+    Session, User, Workspace, WorkspaceMember, WorkspaceRole,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+// Records are keyed the same way as the DynamoDB single table, so iteration
+// order matches a DynamoDB query on the same partition.
+#[derive(Debug, Default)]
+pub struct InMemoryTables {
+    pub users: BTreeMap<String, User>,
+    // email -> user_id
+    pub emails: BTreeMap<String, String>,
+    pub sessions: BTreeMap<String, Session>,
+    pub workspaces: BTreeMap<String, Workspace>,
+    // (workspace_id, user_id) -> member
+    pub members: BTreeMap<(String, String), WorkspaceMember>,
+    pub connections: BTreeMap<String, Connection>,
+    // (workspace_id, connection_id, path, access level)
+    pub connection_access: BTreeMap<(String, String, String, String), ConnectionAccess>,
+    // (workspace_id, layer_name)
+    pub layers: BTreeMap<(String, String), Layer>,
+    // (workspace_id, project_id)
+    pub projects: BTreeMap<(String, String), Project>,
+    pub jobs: BTreeMap<String, Job>,
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryDatabase {
+    pub tables: RwLock<InMemoryTables>,
+}
+
+impl Database for InMemoryDatabase {}
+
+impl InMemoryDatabase {
+    // Nothing outside the process is needed, so settings missing from the
+    // environment fall back to local defaults
+    pub async fn new() -> Result<Arc<dyn Database>> {
+        let geoconnection = match std::env::var("GW_POSTGRES_HOST") {
+            Ok(_) => primary_connection_from_env(),
+            Err(_) => PostgresConnection {
+                host: "localhost".to_string(),
+                port: 5432,
+                database: "gridwalk".to_string(),
+                schema: None,
+                username: "postgres".to_string(),
+                password: "postgres".to_string(),
+            },
+        };
+        let initial_user = match (
+            std::env::var("GW_USER_EMAIL"),
+            std::env::var("GW_USER_PASSWORD"),
+        ) {
+            (Ok(_), Ok(_)) => initial_user_from_env(),
+            _ => {
+                let password = create_id(20).await;
+                info!(
+                    "GW_USER_EMAIL and GW_USER_PASSWORD not set, admin@localhost has password {}",
+                    password
+                );
+                CreateUser {
+                    email: "admin@localhost".to_string(),
+                    first_name: "Initial".to_string(),
+                    last_name: "User".to_string(),
+                    global_role: Some(GlobalRole::Super),
+                    password,
+                }
+            }
+        };
+        Self::new_with_config(geoconnection, initial_user).await
+    }
+
+    pub async fn new_with_config(
+        geoconnection: PostgresConnection,
+        initial_user: CreateUser,
+    ) -> Result<Arc<dyn Database>> {
+        info!("Running with in-memory app database, data will not be persisted");
+        let database = Arc::new(InMemoryDatabase::default()) as Arc<dyn Database>;
+        init_app_data(&database, geoconnection, &initial_user).await?;
+        Ok(database)
+    }
+}
+
+#[async_trait]
+impl UserStore for InMemoryDatabase {
+    async fn create_user(&self, user: &User) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        // Checked under the write lock so concurrent registrations cannot race
+        if tables.emails.contains_key(&user.email) {
+            return Err(anyhow!("email address already registered"));
+        }
+        tables.emails.insert(user.email.clone(), user.id.clone());
+        tables.users.insert(user.id.clone(), user.clone());
+        Ok(())
+    }
+
+    async fn update_user_password(&self, user: &User) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        let stored = tables
+            .users
+            .get_mut(&user.id)
+            .ok_or_else(|| anyhow!("Failed to update password: user not found"))?;
+        stored.hash = user.hash.clone();
+        Ok(())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User> {
+        let tables = self.tables.read().await;
+        tables
+            .emails
+            .get(email)
+            .and_then(|user_id| tables.users.get(user_id))
+            .cloned()
+            .ok_or_else(|| anyhow!("email not found"))
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User> {
+        let tables = self.tables.read().await;
+        tables
+            .users
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("user not found"))
+    }
+
+    async fn create_workspace(&self, wsp: &Workspace) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.workspaces.insert(wsp.id.clone(), wsp.clone());
+        Ok(())
+    }
+
+    async fn delete_workspace(&self, wsp: &Workspace) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.workspaces.remove(&wsp.id);
+        Ok(())
+    }
+
+    async fn get_workspace_by_id(&self, id: &str) -> Result<Workspace> {
+        let tables = self.tables.read().await;
+        tables
+            .workspaces
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("workspace not found"))
+    }
+
+    async fn get_workspaces(&self, user: &User) -> Result<Vec<String>> {
+        let tables = self.tables.read().await;
+        let workspace_ids = tables
+            .members
+            .keys()
+            .filter(|(_, user_id)| *user_id == user.id)
+            .map(|(workspace_id, _)| workspace_id.clone())
+            .collect();
+        Ok(workspace_ids)
+    }
+
+    async fn get_projects(&self, workspace_id: &str) -> Result<Vec<Project>> {
+        let tables = self.tables.read().await;
+        let projects = tables
+            .projects
+            .iter()
+            .filter(|((wsp_id, _), _)| wsp_id == workspace_id)
+            .map(|(_, project)| project.clone())
+            .collect();
+        Ok(projects)
+    }
+
+    async fn add_workspace_member(
+        &self,
+        wsp: &Workspace,
+        user: &User,
+        role: WorkspaceRole,
+        _joined_at: u64,
+    ) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.members.insert(
+            (wsp.id.clone(), user.id.clone()),
+            WorkspaceMember {
+                workspace_id: wsp.id.clone(),
+                user_id: user.id.clone(),
+                role,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get_workspace_member(&self, wsp: &Workspace, user: &User) -> Result<WorkspaceMember> {
+        let tables = self.tables.read().await;
+        tables
+            .members
+            .get(&(wsp.id.clone(), user.id.clone()))
+            .cloned()
+            .ok_or_else(|| anyhow!("workspace member not found"))
+    }
+
+    async fn get_workspace_members(&self, wsp: &Workspace) -> Result<Vec<WorkspaceMember>> {
+        let tables = self.tables.read().await;
+        // Members whose user record no longer exists are skipped, as in DynamoDB
+        let members = tables
+            .members
+            .iter()
+            .filter(|((wsp_id, user_id), _)| {
+                *wsp_id == wsp.id && tables.users.contains_key(user_id)
+            })
+            .map(|(_, member)| member.clone())
+            .collect();
+        Ok(members)
+    }
+
+    async fn remove_workspace_member(&self, wsp: &Workspace, user: &User) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.members.remove(&(wsp.id.clone(), user.id.clone()));
+        Ok(())
+    }
+
+    async fn create_connection(&self, con: &Connection) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.connections.insert(con.id.clone(), con.clone());
+        Ok(())
+    }
+
+    async fn get_connection(&self, connection_id: &str) -> Result<Connection> {
+        let tables = self.tables.read().await;
+        tables
+            .connections
+            .get(connection_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("connection not found"))
+    }
+
+    async fn create_connection_access(&self, ca: &ConnectionAccess) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.connection_access.insert(
+            (
+                ca.workspace_id.clone(),
+                ca.connection_id.clone(),
+                ca.access_config.path().clone(),
+                ca.access_config.variant_name().to_string(),
+            ),
+            ca.clone(),
+        );
+        Ok(())
+    }
+
+    async fn get_accessible_connections(&self, wsp: &Workspace) -> Result<Vec<ConnectionAccess>> {
+        let tables = self.tables.read().await;
+        let connections = tables
+            .connection_access
+            .iter()
+            .filter(|((wsp_id, _, _, _), _)| *wsp_id == wsp.id)
+            .map(|(_, ca)| ca.clone())
+            .collect();
+        Ok(connections)
+    }
+
+    async fn get_accessible_connection(
+        &self,
+        wsp: &Workspace,
+        con_id: &str,
+    ) -> Result<ConnectionAccess> {
+        let tables = self.tables.read().await;
+        let connections: Vec<&ConnectionAccess> = tables
+            .connection_access
+            .iter()
+            .filter(|((wsp_id, connection_id, _, _), _)| {
+                *wsp_id == wsp.id && connection_id == con_id
+            })
+            .map(|(_, ca)| ca)
+            .collect();
+
+        // If connections is empty or has more than one item, return an error
+        if connections.is_empty() {
+            return Err(anyhow!("connection not found"));
+        } else if connections.len() > 1 {
+            return Err(anyhow!("multiple connections found"));
+        }
+
+        Ok(connections[0].clone())
+    }
+
+    async fn get_workspace_connections(&self, wsp: &Workspace) -> Result<Vec<Connection>> {
+        let tables = self.tables.read().await;
+        let mut connection_ids: Vec<&String> = tables
+            .connection_access
+            .keys()
+            .filter(|(wsp_id, _, _, _)| *wsp_id == wsp.id)
+            .map(|(_, connection_id, _, _)| connection_id)
+            .collect();
+        connection_ids.sort();
+        connection_ids.dedup();
+        Ok(connection_ids
+            .into_iter()
+            .filter_map(|connection_id| tables.connections.get(connection_id).cloned())
+            .collect())
+    }
+
+    async fn create_layer_record(&self, layer: &Layer) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.layers.insert(
+            (layer.workspace_id.clone(), layer.name.clone()),
+            layer.clone(),
+        );
+        Ok(())
+    }
+
+    async fn get_layers(&self, wsp: &Workspace) -> Result<Vec<Layer>> {
+        let tables = self.tables.read().await;
+        let layers = tables
+            .layers
+            .iter()
+            .filter(|((wsp_id, _), _)| *wsp_id == wsp.id)
+            .map(|(_, layer)| layer.clone())
+            .collect();
+        Ok(layers)
+    }
+
+    async fn get_layer(&self, wsp: &Workspace, layer_name: &str) -> Result<Layer> {
+        let tables = self.tables.read().await;
+        tables
+            .layers
+            .get(&(wsp.id.clone(), layer_name.to_string()))
+            .cloned()
+            .ok_or_else(|| {
+                LayerNotFoundError {
+                    name: layer_name.to_string(),
+                }
+                .into()
+            })
+    }
+
+    async fn delete_layer(&self, layer: &Layer) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables
+            .layers
+            .remove(&(layer.workspace_id.clone(), layer.name.clone()));
+        Ok(())
+    }
+
+    async fn delete_project(&self, project: &Project) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables
+            .projects
+            .remove(&(project.workspace_id.clone(), project.id.clone()));
+        Ok(())
+    }
+
+    async fn create_project(&self, project: &Project) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.projects.insert(
+            (project.workspace_id.clone(), project.id.clone()),
+            project.clone(),
+        );
+        Ok(())
+    }
+
+    async fn get_project(&self, workspace_id: &str, project_id: &str) -> Result<Project> {
+        let tables = self.tables.read().await;
+        tables
+            .projects
+            .get(&(workspace_id.to_string(), project_id.to_string()))
+            .cloned()
+            .ok_or_else(|| ProjectNotFoundError.into())
+    }
+
+    async fn update_project_document(
+        &self,
+        project: &Project,
+        expected_revision: u64,
+    ) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        let stored = tables
+            .projects
+            .get_mut(&(project.workspace_id.clone(), project.id.clone()))
+            .ok_or(ProjectNotFoundError)?;
+        if stored.revision != expected_revision {
+            return Err(ProjectRevisionConflict.into());
+        }
+        stored.revision = project.revision;
+        stored.document = project.document.clone();
+        Ok(())
+    }
+
+    async fn create_job(&self, job: &Job) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.jobs.insert(job.id.clone(), job.clone());
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> Result<Job> {
+        let tables = self.tables.read().await;
+        tables
+            .jobs
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("job not found"))
+    }
+
+    async fn update_job(&self, job: &Job) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        let stored = tables
+            .jobs
+            .get_mut(&job.id)
+            .ok_or_else(|| anyhow!("job not found"))?;
+        *stored = job.clone();
+        Ok(())
+    }
+
+    async fn get_unfinished_jobs(&self) -> Result<Vec<Job>> {
+        let tables = self.tables.read().await;
+        let jobs = tables
+            .jobs
+            .values()
+            .filter(|job| matches!(job.status, JobStatus::Queued | JobStatus::Running))
+            .cloned()
+            .collect();
+        Ok(jobs)
+    }
+}
@@ -0,0 +1,4 @@
+mod config;
+mod session;
+
+pub use config::*;
@@ -0,0 +1,33 @@
+use crate::data::{InMemoryDatabase, SessionStore};
+use crate::{Session, User};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+#[async_trait]
+impl SessionStore for InMemoryDatabase {
+    async fn create_session(&self, user: Option<&'life1 User>, session_id: &str) -> Result<()> {
+        let session = Session {
+            id: session_id.to_string(),
+            user_id: user.map(|u| u.id.to_string()),
+        };
+
+        let mut tables = self.tables.write().await;
+        tables.sessions.insert(session_id.to_string(), session);
+        Ok(())
+    }
+
+    async fn get_session_by_id(&self, id: &str) -> Result<Session> {
+        let tables = self.tables.read().await;
+        tables
+            .sessions
+            .get(id)
+            .cloned()
+            .ok_or_else(|| anyhow!("session not found"))
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        let mut tables = self.tables.write().await;
+        tables.sessions.remove(session_id);
+        Ok(())
+    }
+}
@@ -1,6 +1,10 @@
 pub mod config;
 
 mod dynamodb;
+mod memory;
+mod postgres;
 
 pub use config::*;
 pub use dynamodb::*;
+pub use memory::*;
+pub use postgres::*;
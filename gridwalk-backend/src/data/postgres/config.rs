@@ -0,0 +1,676 @@
+use super::migrations::run_migrations;
+use crate::connector::create_pool;
+use crate::data::{init_app_data, initial_user_from_env, primary_connection_from_env};
+use crate::data::{Database, UserStore};
+use crate::{
+    Connection, ConnectionAccess, CreateUser, Job, JobStatus, Layer, LayerNotFoundError,
+    PostgresConnection, Project, ProjectNotFoundError, ProjectRevisionConflict, User, Workspace,
+    WorkspaceMember, WorkspaceRole,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use deadpool_postgres::{Object, Pool};
+use std::sync::Arc;
+use tokio_postgres::types::Json;
+use tracing::info;
+
+#[derive(Clone)]
+pub struct PostgresAppStore {
+    pool: Arc<Pool>,
+}
+
+impl Database for PostgresAppStore {}
+
+impl PostgresAppStore {
+    pub async fn new() -> Result<Arc<dyn Database>> {
+        let app_db_schema =
+            std::env::var("GW_APP_DB_SCHEMA").unwrap_or_else(|_| "gridwalk_app".to_string());
+
+        // App tables live alongside the primary geodatabase, in their own schema
+        let geoconnection = primary_connection_from_env();
+        let app_db = PostgresConnection {
+            schema: Some(app_db_schema),
+            ..geoconnection.clone()
+        };
+
+        Self::new_with_config(app_db, geoconnection, initial_user_from_env()).await
+    }
+
+    pub async fn new_with_config(
+        app_db: PostgresConnection,
+        geoconnection: PostgresConnection,
+        initial_user: CreateUser,
+    ) -> Result<Arc<dyn Database>> {
+        let schema = app_db
+            .schema
+            .clone()
+            .ok_or_else(|| anyhow!("App database schema must be set"))?;
+        info!("Running with Postgres app database, schema: {}", schema);
+
+        let pool = create_pool(&app_db, Some(format!("-c search_path=\"{}\"", schema)))?;
+        let mut client = pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))?;
+        run_migrations(&mut client, &schema).await?;
+        drop(client);
+
+        let database = Arc::new(PostgresAppStore {
+            pool: Arc::new(pool),
+        }) as Arc<dyn Database>;
+
+        init_app_data(&database, geoconnection, &initial_user).await?;
+        Ok(database)
+    }
+
+    pub(super) async fn client(&self) -> Result<Object> {
+        self.pool
+            .get()
+            .await
+            .map_err(|e| anyhow!("Failed to get client from pool: {}", e))
+    }
+}
+
+#[async_trait]
+impl UserStore for PostgresAppStore {
+    async fn create_user(&self, user: &User) -> Result<()> {
+        let client = self.client().await?;
+        let global_role = user.global_role.as_ref().map(|r| r.to_string());
+        // primary_email is unique, so a duplicate registration fails here
+        client
+            .execute(
+                "INSERT INTO users
+                    (id, primary_email, first_name, last_name, global_role, active, created_at, hash)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+                &[
+                    &user.id,
+                    &user.email,
+                    &user.first_name,
+                    &user.last_name,
+                    &global_role,
+                    &user.active,
+                    &(user.created_at as i64),
+                    &user.hash,
+                ],
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to create user: {}", e))?;
+        Ok(())
+    }
+
+    async fn update_user_password(&self, user: &User) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "UPDATE users SET hash = $2 WHERE id = $1",
+                &[&user.id, &user.hash],
+            )
+            .await
+            .map_err(|e| anyhow!("Failed to update password: {}", e))?;
+        Ok(())
+    }
+
+    async fn get_user_by_email(&self, email: &str) -> Result<User> {
+        let client = self.client().await?;
+        client
+            .query_opt("SELECT * FROM users WHERE primary_email = $1", &[&email])
+            .await?
+            .map(|row| (&row).into())
+            .ok_or_else(|| anyhow!("email not found"))
+    }
+
+    async fn get_user_by_id(&self, id: &str) -> Result<User> {
+        let client = self.client().await?;
+        client
+            .query_opt("SELECT * FROM users WHERE id = $1", &[&id])
+            .await?
+            .map(|row| (&row).into())
+            .ok_or_else(|| anyhow!("user not found"))
+    }
+
+    async fn create_workspace(&self, wsp: &Workspace) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO workspaces (id, name, owner, created_at, active)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (id) DO UPDATE
+                SET name = EXCLUDED.name, owner = EXCLUDED.owner, active = EXCLUDED.active",
+                &[
+                    &wsp.id,
+                    &wsp.name,
+                    &wsp.owner,
+                    &(wsp.created_at as i64),
+                    &wsp.active,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_workspace(&self, wsp: &Workspace) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute("DELETE FROM workspaces WHERE id = $1", &[&wsp.id])
+            .await?;
+        Ok(())
+    }
+
+    async fn get_workspace_by_id(&self, id: &str) -> Result<Workspace> {
+        let client = self.client().await?;
+        client
+            .query_opt("SELECT * FROM workspaces WHERE id = $1", &[&id])
+            .await
+            .map_err(|e| anyhow!("failed to query workspace: {}", e))?
+            .map(|row| (&row).into())
+            .ok_or_else(|| anyhow!("workspace not found"))
+    }
+
+    async fn get_workspaces(&self, user: &User) -> Result<Vec<String>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT workspace_id FROM workspace_members WHERE user_id = $1",
+                &[&user.id],
+            )
+            .await?;
+        Ok(rows.iter().map(|row| row.get(0)).collect())
+    }
+
+    async fn get_projects(&self, workspace_id: &str) -> Result<Vec<Project>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM projects WHERE workspace_id = $1 ORDER BY id",
+                &[&workspace_id],
+            )
+            .await?;
+        Ok(rows.iter().map(Into::into).collect())
+    }
+
+    async fn add_workspace_member(
+        &self,
+        wsp: &Workspace,
+        user: &User,
+        role: WorkspaceRole,
+        joined_at: u64,
+    ) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO workspace_members (workspace_id, user_id, role, joined_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (workspace_id, user_id) DO UPDATE
+                SET role = EXCLUDED.role, joined_at = EXCLUDED.joined_at",
+                &[&wsp.id, &user.id, &role.to_string(), &(joined_at as i64)],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_workspace_member(&self, wsp: &Workspace, user: &User) -> Result<WorkspaceMember> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                "SELECT * FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+                &[&wsp.id, &user.id],
+            )
+            .await?
+            .map(|row| (&row).into())
+            .ok_or_else(|| anyhow!("workspace member not found"))
+    }
+
+    async fn get_workspace_members(&self, wsp: &Workspace) -> Result<Vec<WorkspaceMember>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM workspace_members WHERE workspace_id = $1 ORDER BY user_id",
+                &[&wsp.id],
+            )
+            .await?;
+        Ok(rows.iter().map(Into::into).collect())
+    }
+
+    async fn remove_workspace_member(&self, wsp: &Workspace, user: &User) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "DELETE FROM workspace_members WHERE workspace_id = $1 AND user_id = $2",
+                &[&wsp.id, &user.id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn create_connection(&self, con: &Connection) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO connections (id, name, connector_type, config)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (id) DO UPDATE
+                SET name = EXCLUDED.name,
+                    connector_type = EXCLUDED.connector_type,
+                    config = EXCLUDED.config",
+                &[
+                    &con.id,
+                    &con.name,
+                    &con.connector_type(),
+                    &Json(&con.config),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_connection(&self, connection_id: &str) -> Result<Connection> {
+        let client = self.client().await?;
+        client
+            .query_opt("SELECT * FROM connections WHERE id = $1", &[&connection_id])
+            .await
+            .map_err(|e| anyhow!("failed to fetch connection: {}", e))?
+            .map(|row| Connection::try_from(&row))
+            .transpose()?
+            .ok_or_else(|| anyhow!("connection not found"))
+    }
+
+    async fn create_connection_access(&self, ca: &ConnectionAccess) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO connection_access (workspace_id, connection_id, path, access_level)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT DO NOTHING",
+                &[
+                    &ca.workspace_id,
+                    &ca.connection_id,
+                    ca.access_config.path(),
+                    &ca.access_config.variant_name(),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_accessible_connections(&self, wsp: &Workspace) -> Result<Vec<ConnectionAccess>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM connection_access WHERE workspace_id = $1 ORDER BY connection_id",
+                &[&wsp.id],
+            )
+            .await?;
+        rows.iter().map(ConnectionAccess::try_from).collect()
+    }
+
+    async fn get_accessible_connection(
+        &self,
+        wsp: &Workspace,
+        con_id: &str,
+    ) -> Result<ConnectionAccess> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM connection_access WHERE workspace_id = $1 AND connection_id = $2",
+                &[&wsp.id, &con_id],
+            )
+            .await?;
+
+        // If connections is empty or has more than one item, return an error
+        match rows.as_slice() {
+            [] => Err(anyhow!("connection not found")),
+            [row] => ConnectionAccess::try_from(row),
+            _ => Err(anyhow!("multiple connections found")),
+        }
+    }
+
+    async fn get_workspace_connections(&self, wsp: &Workspace) -> Result<Vec<Connection>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM connections WHERE id IN
+                    (SELECT connection_id FROM connection_access WHERE workspace_id = $1)
+                ORDER BY id",
+                &[&wsp.id],
+            )
+            .await?;
+        rows.iter().map(Connection::try_from).collect()
+    }
+
+    async fn create_layer_record(&self, layer: &Layer) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO layers (workspace_id, name, uploaded_by, created_at, source_srid,
+                    storage_srid, validation, mode, updated_at, connection_id)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+                ON CONFLICT (workspace_id, name) DO UPDATE
+                SET uploaded_by = EXCLUDED.uploaded_by, created_at = EXCLUDED.created_at,
+                    source_srid = EXCLUDED.source_srid, storage_srid = EXCLUDED.storage_srid,
+                    validation = EXCLUDED.validation, mode = EXCLUDED.mode,
+                    updated_at = EXCLUDED.updated_at, connection_id = EXCLUDED.connection_id",
+                &[
+                    &layer.workspace_id,
+                    &layer.name,
+                    &layer.uploaded_by,
+                    &(layer.created_at as i64),
+                    &layer.source_srid,
+                    &layer.storage_srid,
+                    &layer.validation.as_ref().map(Json),
+                    &layer.mode.to_string(),
+                    &layer.updated_at.map(|updated_at| updated_at as i64),
+                    &layer.connection_id,
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_layers(&self, wsp: &Workspace) -> Result<Vec<Layer>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM layers WHERE workspace_id = $1 ORDER BY name",
+                &[&wsp.id],
+            )
+            .await?;
+        Ok(rows.iter().map(Into::into).collect())
+    }
+
+    async fn get_layer(&self, wsp: &Workspace, layer_name: &str) -> Result<Layer> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                "SELECT * FROM layers WHERE workspace_id = $1 AND name = $2",
+                &[&wsp.id, &layer_name],
+            )
+            .await
+            .map_err(|e| anyhow!("failed to fetch layer: {}", e))?
+            .map(|row| (&row).into())
+            .ok_or_else(|| {
+                LayerNotFoundError {
+                    name: layer_name.to_string(),
+                }
+                .into()
+            })
+    }
+
+    async fn delete_layer(&self, layer: &Layer) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "DELETE FROM layers WHERE workspace_id = $1 AND name = $2",
+                &[&layer.workspace_id, &layer.name],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_project(&self, project: &Project) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "DELETE FROM projects WHERE workspace_id = $1 AND id = $2",
+                &[&project.workspace_id, &project.id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn create_project(&self, project: &Project) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO projects
+                    (workspace_id, id, name, uploaded_by, created_at, revision, document)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (workspace_id, id) DO UPDATE
+                SET name = EXCLUDED.name",
+                &[
+                    &project.workspace_id,
+                    &project.id,
+                    &project.name,
+                    &project.uploaded_by,
+                    &(project.created_at as i64),
+                    &(project.revision as i64),
+                    &Json(&project.document),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_project(&self, workspace_id: &str, project_id: &str) -> Result<Project> {
+        let client = self.client().await?;
+        client
+            .query_opt(
+                "SELECT * FROM projects WHERE workspace_id = $1 AND id = $2",
+                &[&workspace_id, &project_id],
+            )
+            .await
+            .map_err(|e| anyhow!("failed to fetch project: {}", e))?
+            .map(|row| (&row).into())
+            .ok_or_else(|| ProjectNotFoundError.into())
+    }
+
+    async fn update_project_document(
+        &self,
+        project: &Project,
+        expected_revision: u64,
+    ) -> Result<()> {
+        let client = self.client().await?;
+        let updated = client
+            .execute(
+                "UPDATE projects SET document = $3, revision = $4
+                WHERE workspace_id = $1 AND id = $2 AND revision = $5",
+                &[
+                    &project.workspace_id,
+                    &project.id,
+                    &Json(&project.document),
+                    &(project.revision as i64),
+                    &(expected_revision as i64),
+                ],
+            )
+            .await?;
+        if updated == 0 {
+            return Err(ProjectRevisionConflict.into());
+        }
+        Ok(())
+    }
+
+    async fn create_job(&self, job: &Job) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute(
+                "INSERT INTO jobs (id, workspace_id, created_by, status, layer, file_path,
+                    row_count, error, failed_rows, created_at, updated_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+                &[
+                    &job.id,
+                    &job.workspace_id,
+                    &job.created_by,
+                    &job.status.to_string(),
+                    &Json(&job.layer),
+                    &job.file_path,
+                    &job.row_count.map(|count| count as i64),
+                    &job.error,
+                    &job.failed_rows.as_ref().map(Json),
+                    &(job.created_at as i64),
+                    &(job.updated_at as i64),
+                ],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_job(&self, id: &str) -> Result<Job> {
+        let client = self.client().await?;
+        client
+            .query_opt("SELECT * FROM jobs WHERE id = $1", &[&id])
+            .await
+            .map_err(|e| anyhow!("failed to fetch job: {}", e))?
+            .map(|row| (&row).into())
+            .ok_or_else(|| anyhow!("job not found"))
+    }
+
+    async fn update_job(&self, job: &Job) -> Result<()> {
+        let client = self.client().await?;
+        let updated = client
+            .execute(
+                "UPDATE jobs SET status = $2, layer = $3, row_count = $4, error = $5,
+                    failed_rows = $6, updated_at = $7
+                WHERE id = $1",
+                &[
+                    &job.id,
+                    &job.status.to_string(),
+                    &Json(&job.layer),
+                    &job.row_count.map(|count| count as i64),
+                    &job.error,
+                    &job.failed_rows.as_ref().map(Json),
+                    &(job.updated_at as i64),
+                ],
+            )
+            .await?;
+        if updated == 0 {
+            return Err(anyhow!("job not found"));
+        }
+        Ok(())
+    }
+
+    async fn get_unfinished_jobs(&self) -> Result<Vec<Job>> {
+        let client = self.client().await?;
+        let rows = client
+            .query(
+                "SELECT * FROM jobs WHERE status IN ($1, $2) ORDER BY created_at",
+                &[
+                    &JobStatus::Queued.to_string(),
+                    &JobStatus::Running.to_string(),
+                ],
+            )
+            .await?;
+        Ok(rows.iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connector::ConnectionAccessConfig;
+    use crate::LayerMode;
+
+    fn test_job(id: &str) -> Job {
+        Job {
+            id: id.to_string(),
+            workspace_id: "workspace".to_string(),
+            created_by: "user".to_string(),
+            layer: Layer {
+                workspace_id: "workspace".to_string(),
+                name: "roads".to_string(),
+                uploaded_by: "user".to_string(),
+                created_at: 1,
+                source_srid: None,
+                storage_srid: None,
+                geometry: None,
+                validation_options: None,
+                validation: None,
+                mode: LayerMode::Create,
+                updated_at: None,
+                connection_id: "primary".to_string(),
+            },
+            file_path: "roads.gpkg".to_string(),
+            status: JobStatus::Queued,
+            row_count: None,
+            error: None,
+            failed_rows: None,
+            created_at: 1,
+            updated_at: 1,
+        }
+    }
+
+    // A store over a schema of its own, dropped with drop_schema
+    async fn test_store() -> (PostgresAppStore, String) {
+        let schema = format!("gw_test_{}", uuid::Uuid::new_v4().simple());
+        let pool = create_pool(
+            &primary_connection_from_env(),
+            Some(format!("-c search_path=\"{}\"", schema)),
+        )
+        .unwrap();
+        let mut client = pool.get().await.unwrap();
+        run_migrations(&mut client, &schema).await.unwrap();
+        let store = PostgresAppStore {
+            pool: Arc::new(pool),
+        };
+        (store, schema)
+    }
+
+    async fn drop_schema(store: &PostgresAppStore, schema: &str) {
+        store
+            .client()
+            .await
+            .unwrap()
+            .batch_execute(&format!("DROP SCHEMA \"{}\" CASCADE", schema))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database set in the GW_POSTGRES_* variables"]
+    async fn update_job_saves_the_layer() {
+        let (store, schema) = test_store().await;
+
+        let mut job = test_job("job-1");
+        store.create_job(&job).await.unwrap();
+        job.status = JobStatus::Succeeded;
+        job.row_count = Some(12);
+        job.layer.source_srid = Some(27700);
+        job.layer.storage_srid = Some(4326);
+        store.update_job(&job).await.unwrap();
+
+        let saved = store.get_job("job-1").await.unwrap();
+        drop_schema(&store, &schema).await;
+        assert_eq!(saved.status, JobStatus::Succeeded);
+        assert_eq!(saved.row_count, Some(12));
+        assert_eq!(saved.layer.source_srid, Some(27700));
+        assert_eq!(saved.layer.storage_srid, Some(4326));
+    }
+
+    #[tokio::test]
+    #[ignore = "needs a Postgres database set in the GW_POSTGRES_* variables"]
+    async fn workspace_connections_are_listed_once() {
+        let (store, schema) = test_store().await;
+        let workspace = Workspace {
+            id: "workspace".to_string(),
+            name: "Workspace".to_string(),
+            owner: "user".to_string(),
+            created_at: 1,
+            active: true,
+        };
+        let connection = Connection {
+            id: "parcels".to_string(),
+            name: "Parcels".to_string(),
+            config: serde_json::from_value(
+                serde_json::json!({ "type": "geopackage", "path": "/data/parcels.gpkg" }),
+            )
+            .unwrap(),
+        };
+        store.create_connection(&connection).await.unwrap();
+        for access_config in [
+            ConnectionAccessConfig::ReadOnly("a".to_string()),
+            ConnectionAccessConfig::ReadWrite("b".to_string()),
+        ] {
+            store
+                .create_connection_access(&ConnectionAccess {
+                    connection_id: connection.id.clone(),
+                    workspace_id: workspace.id.clone(),
+                    access_config,
+                })
+                .await
+                .unwrap();
+        }
+
+        let connections = store.get_workspace_connections(&workspace).await.unwrap();
+        drop_schema(&store, &schema).await;
+        assert_eq!(connections.len(), 1);
+        assert_eq!(connections[0].connector_type(), "geopackage");
+    }
+}
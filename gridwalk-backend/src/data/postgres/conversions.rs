@@ -0,0 +1,158 @@
+use crate::{
+    Connection, ConnectionAccess, ConnectionAccessConfig, ConnectorConfig, FailedRows, Job, Layer,
+    Project, ProjectDocument, Session, User, ValidationReport, Workspace, WorkspaceMember,
+};
+use tokio_postgres::types::Json;
+use tokio_postgres::Row;
+
+// Convert Postgres row into User struct
+impl From<&Row> for User {
+    fn from(row: &Row) -> Self {
+        let global_role: Option<String> = row.get("global_role");
+        let created_at: i64 = row.get("created_at");
+        User {
+            id: row.get("id"),
+            email: row.get("primary_email"),
+            first_name: row.get("first_name"),
+            last_name: row.get("last_name"),
+            global_role: global_role.and_then(|s| s.parse().ok()),
+            active: row.get("active"),
+            created_at: created_at as u64,
+            hash: row.get("hash"),
+        }
+    }
+}
+
+// Convert Postgres row into Workspace struct
+impl From<&Row> for Workspace {
+    fn from(row: &Row) -> Self {
+        let created_at: i64 = row.get("created_at");
+        Workspace {
+            id: row.get("id"),
+            name: row.get("name"),
+            owner: row.get("owner"),
+            created_at: created_at as u64,
+            active: row.get("active"),
+        }
+    }
+}
+
+// Convert Postgres row into WorkspaceMember struct
+impl From<&Row> for WorkspaceMember {
+    fn from(row: &Row) -> Self {
+        let role: String = row.get("role");
+        WorkspaceMember {
+            workspace_id: row.get("workspace_id"),
+            user_id: row.get("user_id"),
+            role: (&role).into(),
+        }
+    }
+}
+
+// Convert Postgres row into Session struct
+impl From<&Row> for Session {
+    fn from(row: &Row) -> Self {
+        Session {
+            id: row.get("id"),
+            user_id: row.get("user_id"),
+        }
+    }
+}
+
+// Convert Postgres row into Connection struct
+impl TryFrom<&Row> for Connection {
+    type Error = anyhow::Error;
+
+    fn try_from(row: &Row) -> anyhow::Result<Self> {
+        let connector_type: String = row.get("connector_type");
+        let Json(config): Json<serde_json::Value> = row.get("config");
+        Ok(Connection {
+            id: row.get("id"),
+            name: row.get("name"),
+            config: ConnectorConfig::from_stored(&connector_type, config)?,
+        })
+    }
+}
+
+// Convert Postgres row into ConnectionAccess struct
+impl TryFrom<&Row> for ConnectionAccess {
+    type Error = anyhow::Error;
+
+    fn try_from(row: &Row) -> anyhow::Result<Self> {
+        let access_level: String = row.get("access_level");
+        let access_config = ConnectionAccessConfig::from_str(&access_level, row.get("path"))
+            .map_err(|e| anyhow::anyhow!(e))?;
+        Ok(ConnectionAccess {
+            workspace_id: row.get("workspace_id"),
+            connection_id: row.get("connection_id"),
+            access_config,
+        })
+    }
+}
+
+// Convert Postgres row into Layer struct
+impl From<&Row> for Layer {
+    fn from(row: &Row) -> Self {
+        let created_at: i64 = row.get("created_at");
+        let validation: Option<Json<ValidationReport>> = row.get("validation");
+        let mode: String = row.get("mode");
+        let updated_at: Option<i64> = row.get("updated_at");
+        Layer {
+            workspace_id: row.get("workspace_id"),
+            name: row.get("name"),
+            uploaded_by: row.get("uploaded_by"),
+            created_at: created_at as u64,
+            source_srid: row.get("source_srid"),
+            storage_srid: row.get("storage_srid"),
+            geometry: None,
+            validation_options: None,
+            validation: validation.map(|Json(validation)| validation),
+            mode: mode.parse().unwrap_or_default(),
+            updated_at: updated_at.map(|updated_at| updated_at as u64),
+            connection_id: row.get("connection_id"),
+        }
+    }
+}
+
+// Convert Postgres row into Project struct
+impl From<&Row> for Project {
+    fn from(row: &Row) -> Self {
+        let created_at: i64 = row.get("created_at");
+        let revision: i64 = row.get("revision");
+        let document: Option<Json<ProjectDocument>> = row.get("document");
+        Project {
+            id: row.get("id"),
+            workspace_id: row.get("workspace_id"),
+            name: row.get("name"),
+            uploaded_by: row.get("uploaded_by"),
+            created_at: created_at as u64,
+            revision: revision as u64,
+            document: document.map(|Json(d)| d).unwrap_or_default(),
+        }
+    }
+}
+
+// Convert Postgres row into Job struct
+impl From<&Row> for Job {
+    fn from(row: &Row) -> Self {
+        let status: String = row.get("status");
+        let Json(layer) = row.get("layer");
+        let row_count: Option<i64> = row.get("row_count");
+        let failed_rows: Option<Json<FailedRows>> = row.get("failed_rows");
+        let created_at: i64 = row.get("created_at");
+        let updated_at: i64 = row.get("updated_at");
+        Job {
+            id: row.get("id"),
+            workspace_id: row.get("workspace_id"),
+            created_by: row.get("created_by"),
+            layer,
+            file_path: row.get("file_path"),
+            status: status.parse().unwrap(),
+            row_count: row_count.map(|count| count as u64),
+            error: row.get("error"),
+            failed_rows: failed_rows.map(|Json(failed_rows)| failed_rows),
+            created_at: created_at as u64,
+            updated_at: updated_at as u64,
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use deadpool_postgres::Client;
+use tracing::info;
+
+// Ordered schema migrations for the app store. Never edit an applied
+// migration, append a new version instead.
+const MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        "
+    CREATE TABLE users (
+        id TEXT PRIMARY KEY,
+        primary_email TEXT NOT NULL UNIQUE,
+        first_name TEXT NOT NULL,
+        last_name TEXT NOT NULL,
+        global_role TEXT,
+        active BOOLEAN NOT NULL,
+        created_at BIGINT NOT NULL,
+        hash TEXT NOT NULL
+    );
+
+    CREATE TABLE sessions (
+        id TEXT PRIMARY KEY,
+        user_id TEXT REFERENCES users (id) ON DELETE CASCADE
+    );
+
+    CREATE TABLE workspaces (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        active BOOLEAN NOT NULL
+    );
+
+    CREATE TABLE workspace_members (
+        workspace_id TEXT NOT NULL,
+        user_id TEXT NOT NULL REFERENCES users (id) ON DELETE CASCADE,
+        role TEXT NOT NULL,
+        joined_at BIGINT NOT NULL,
+        PRIMARY KEY (workspace_id, user_id)
+    );
+    CREATE INDEX workspace_members_user_idx ON workspace_members (user_id);
+
+    CREATE TABLE connections (
+        id TEXT PRIMARY KEY,
+        name TEXT NOT NULL,
+        connector_type TEXT NOT NULL,
+        config JSONB NOT NULL
+    );
+
+    CREATE TABLE connection_access (
+        workspace_id TEXT NOT NULL,
+        connection_id TEXT NOT NULL,
+        path TEXT NOT NULL,
+        access_level TEXT NOT NULL,
+        PRIMARY KEY (workspace_id, connection_id, path, access_level)
+    );
+
+    CREATE TABLE layers (
+        workspace_id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        uploaded_by TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        PRIMARY KEY (workspace_id, name)
+    );
+
+    CREATE TABLE projects (
+        workspace_id TEXT NOT NULL,
+        id TEXT NOT NULL,
+        name TEXT NOT NULL,
+        uploaded_by TEXT NOT NULL,
+        created_at BIGINT NOT NULL,
+        PRIMARY KEY (workspace_id, id)
+    );
+    ",
+    ),
+    (
+        2,
+        "
+    ALTER TABLE projects
+        ADD COLUMN revision BIGINT NOT NULL DEFAULT 0,
+        ADD COLUMN document JSONB;
+    ",
+    ),
+    (
+        3,
+        "
+    CREATE TABLE jobs (
+        id TEXT PRIMARY KEY,
+        workspace_id TEXT NOT NULL,
+        created_by TEXT NOT NULL,
+        status TEXT NOT NULL,
+        layer JSONB NOT NULL,
+        file_path TEXT NOT NULL,
+        row_count BIGINT,
+        error TEXT,
+        created_at BIGINT NOT NULL,
+        updated_at BIGINT NOT NULL
+    );
+    CREATE INDEX jobs_status_idx ON jobs (status);
+    ",
+    ),
+    (
+        4,
+        "
+    ALTER TABLE layers
+        ADD COLUMN source_srid INTEGER,
+        ADD COLUMN storage_srid INTEGER;
+    ",
+    ),
+    (
+        5,
+        "
+    ALTER TABLE jobs ADD COLUMN failed_rows JSONB;
+    ",
+    ),
+    (
+        6,
+        "
+    ALTER TABLE layers ADD COLUMN validation JSONB;
+    ",
+    ),
+    (
+        7,
+        "
+    ALTER TABLE layers
+        ADD COLUMN mode TEXT NOT NULL DEFAULT 'create',
+        ADD COLUMN updated_at BIGINT;
+    ",
+    ),
+    (
+        8,
+        "
+    ALTER TABLE layers ADD COLUMN connection_id TEXT NOT NULL DEFAULT 'primary';
+    ",
+    ),
+];
+
+pub async fn run_migrations(client: &mut Client, schema: &str) -> Result<()> {
+    client
+        .batch_execute(&format!(
+            "CREATE SCHEMA IF NOT EXISTS \"{schema}\";
+            CREATE TABLE IF NOT EXISTS \"{schema}\".schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            );"
+        ))
+        .await
+        .map_err(|e| anyhow!("Failed to create migrations table: {}", e))?;
+
+    // Hold a table lock for the whole run so concurrent instances apply each migration once
+    let transaction = client.transaction().await?;
+    transaction
+        .batch_execute(&format!(
+            "SET LOCAL search_path TO \"{schema}\";
+            LOCK TABLE schema_migrations IN EXCLUSIVE MODE;"
+        ))
+        .await?;
+
+    let current: i32 = transaction
+        .query_one(
+            "SELECT COALESCE(MAX(version), 0) FROM schema_migrations",
+            &[],
+        )
+        .await?
+        .get(0);
+
+    for (version, sql) in MIGRATIONS.iter().filter(|(v, _)| *v > current) {
+        info!("db init: applying app store migration {}", version);
+        transaction
+            .batch_execute(sql)
+            .await
+            .map_err(|e| anyhow!("Failed to apply migration {}: {}", version, e))?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version) VALUES ($1)",
+                &[version],
+            )
+            .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(())
+}
@@ -0,0 +1,6 @@
+mod config;
+mod conversions;
+mod migrations;
+mod session;
+
+pub use config::*;
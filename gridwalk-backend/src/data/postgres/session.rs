@@ -0,0 +1,39 @@
+use crate::data::{PostgresAppStore, SessionStore};
+use crate::{Session, User};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+#[async_trait]
+impl SessionStore for PostgresAppStore {
+    async fn create_session(&self, user: Option<&'life1 User>, session_id: &str) -> Result<()> {
+        let client = self.client().await?;
+        let user_id = user.map(|u| u.id.to_string());
+        client
+            .execute(
+                "INSERT INTO sessions (id, user_id) VALUES ($1, $2)",
+                &[&session_id, &user_id],
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn get_session_by_id(&self, id: &str) -> Result<Session> {
+        let client = self.client().await?;
+        match client
+            .query_opt("SELECT id, user_id FROM sessions WHERE id = $1", &[&id])
+            .await
+            .map_err(|e| anyhow!("Failed to get session: {}", e))?
+        {
+            Some(row) => Ok((&row).into()),
+            None => Err(anyhow!("session not found")),
+        }
+    }
+
+    async fn delete_session(&self, session_id: &str) -> Result<()> {
+        let client = self.client().await?;
+        client
+            .execute("DELETE FROM sessions WHERE id = $1", &[&session_id])
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,52 @@
+use crate::app_state::AppState;
+use crate::auth::AuthUser;
+use crate::{Job, Workspace};
+use axum::{
+    extract::{Extension, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use serde_json::json;
+use std::sync::Arc;
+
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path(job_id): Path<String>,
+) -> impl IntoResponse {
+    let user = auth_user.user.ok_or_else(|| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Unauthorized request", "details": null })),
+        )
+    })?;
+
+    let job = Job::from_id(&state.app_data, &job_id).await.map_err(|e| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(json!({ "error": "Job not found", "details": e.to_string() })),
+        )
+    })?;
+
+    // Any member of the workspace the upload went to may follow its progress
+    let workspace = Workspace::from_id(&state.app_data, &job.workspace_id)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::NOT_FOUND,
+                Json(json!({ "error": "Workspace not found", "details": e.to_string() })),
+            )
+        })?;
+    workspace
+        .get_member(&state.app_data, &user)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::FORBIDDEN,
+                Json(json!({ "error": "Access forbidden", "details": e.to_string() })),
+            )
+        })?;
+
+    Ok::<_, (StatusCode, Json<serde_json::Value>)>(Json(job))
+}
@@ -0,0 +1,101 @@
+use crate::data::Database;
+use crate::utils::get_unix_timestamp;
+use crate::{FailedRows, Layer, User};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use strum_macros::{Display, EnumString};
+use uuid::Uuid;
+
+#[derive(PartialEq, Debug, Display, EnumString, Clone, Copy, Deserialize, Serialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+}
+
+// An upload waiting to be loaded into PostGIS, or the outcome of loading it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub workspace_id: String,
+    pub created_by: String,
+    pub layer: Layer,
+    pub file_path: String,
+    pub status: JobStatus,
+    pub row_count: Option<u64>,
+    pub error: Option<String>,
+    // Rows of a CSV or Excel upload that had no usable geometry
+    #[serde(default)]
+    pub failed_rows: Option<FailedRows>,
+    pub created_at: u64,
+    pub updated_at: u64,
+}
+
+impl Job {
+    // The uploaded file is kept in the job's own directory until the job finishes
+    pub fn new_ingest(layer: &Layer, user: &User, file_name: &str) -> Self {
+        let now = get_unix_timestamp();
+        let id = Uuid::new_v4().to_string();
+        let file_path = Self::upload_dir_for(&id).join(file_name);
+        Job {
+            id,
+            workspace_id: layer.workspace_id.clone(),
+            created_by: user.id.clone(),
+            layer: layer.clone(),
+            file_path: file_path.to_string_lossy().to_string(),
+            status: JobStatus::Queued,
+            row_count: None,
+            error: None,
+            failed_rows: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn upload_dir_for(id: &str) -> PathBuf {
+        Path::new("uploads").join("jobs").join(id)
+    }
+
+    pub fn upload_dir(&self) -> PathBuf {
+        Self::upload_dir_for(&self.id)
+    }
+
+    pub async fn create_record(&self, database: &Arc<dyn Database>) -> Result<()> {
+        database.create_job(self).await
+    }
+
+    pub async fn from_id(database: &Arc<dyn Database>, id: &str) -> Result<Self> {
+        database.get_job(id).await
+    }
+
+    // Jobs that were queued or running when the server last stopped
+    pub async fn get_unfinished(database: &Arc<dyn Database>) -> Result<Vec<Self>> {
+        database.get_unfinished_jobs().await
+    }
+
+    pub async fn set_status(
+        &mut self,
+        database: &Arc<dyn Database>,
+        status: JobStatus,
+    ) -> Result<()> {
+        self.status = status;
+        self.updated_at = get_unix_timestamp();
+        database.update_job(self).await
+    }
+
+    pub async fn succeed(&mut self, database: &Arc<dyn Database>, row_count: u64) -> Result<()> {
+        self.row_count = Some(row_count);
+        self.error = None;
+        self.set_status(database, JobStatus::Succeeded).await
+    }
+
+    pub async fn fail(&mut self, database: &Arc<dyn Database>, error: String) -> Result<()> {
+        self.error = Some(error);
+        self.set_status(database, JobStatus::Failed).await
+    }
+}
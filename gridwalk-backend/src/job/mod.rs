@@ -0,0 +1,7 @@
+mod endpoints;
+mod job;
+mod queue;
+
+pub use endpoints::*;
+pub use job::*;
+pub use queue::*;
@@ -0,0 +1,349 @@
+use crate::app_state::AppState;
+use crate::connector::{CrsOptions, ValidationOptions};
+use crate::utils::get_unix_timestamp;
+use crate::{
+    build_tabular_layer, detect_file_srid, extract_shapefile_zip, is_zip_file, Connection, Job,
+    JobStatus, Layer, LayerMode, User, Workspace,
+};
+use anyhow::{anyhow, Result};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::fs;
+use tokio::sync::{mpsc, Mutex, Semaphore};
+use tracing::{error, info};
+
+// Hands job ids from request handlers to the ingest workers
+#[derive(Clone)]
+pub struct IngestQueue {
+    sender: mpsc::UnboundedSender<String>,
+    receiver: Arc<Mutex<Option<mpsc::UnboundedReceiver<String>>>>,
+}
+
+impl IngestQueue {
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        IngestQueue {
+            sender,
+            receiver: Arc::new(Mutex::new(Some(receiver))),
+        }
+    }
+
+    pub fn enqueue(&self, job_id: &str) -> Result<()> {
+        self.sender
+            .send(job_id.to_string())
+            .map_err(|_| anyhow!("Ingest queue is closed"))
+    }
+
+    // Start processing jobs, first re-queueing any left unfinished by a restart.
+    // At most GW_INGEST_WORKERS jobs (default 2) run at once.
+    pub async fn start(&self, state: AppState) -> Result<()> {
+        let mut receiver = self
+            .receiver
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| anyhow!("Ingest workers already started"))?;
+
+        let workers = std::env::var("GW_INGEST_WORKERS")
+            .unwrap_or_else(|_| "2".to_string())
+            .parse::<usize>()
+            .expect("GW_INGEST_WORKERS must be a number")
+            .max(1);
+
+        for job in Job::get_unfinished(&state.app_data).await? {
+            info!("Resuming ingest job {}", job.id);
+            self.enqueue(&job.id)?;
+        }
+
+        let state = Arc::new(state);
+        let permits = Arc::new(Semaphore::new(workers));
+        tokio::spawn(async move {
+            while let Some(job_id) = receiver.recv().await {
+                let permit = permits.clone().acquire_owned().await.unwrap();
+                let state = state.clone();
+                tokio::spawn(async move {
+                    process_job(&state, &job_id).await;
+                    drop(permit);
+                });
+            }
+        });
+        Ok(())
+    }
+}
+
+// Move an uploaded file, and any files that must sit next to it such as the
+// parts of a shapefile, into a new ingest job's directory and queue the job
+pub async fn queue_ingest(
+    state: &AppState,
+    layer: &Layer,
+    user: &User,
+    file_path: &Path,
+    sidecars: &[PathBuf],
+) -> Result<Job> {
+    let file_name = file_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(&layer.name);
+    let job = Job::new_ingest(layer, user, file_name);
+
+    let queued = async {
+        fs::create_dir_all(job.upload_dir()).await?;
+        fs::rename(file_path, &job.file_path).await?;
+        for sidecar in sidecars {
+            let name = sidecar
+                .file_name()
+                .ok_or_else(|| anyhow!("Invalid file name: {}", sidecar.display()))?;
+            fs::rename(sidecar, job.upload_dir().join(name)).await?;
+        }
+        job.create_record(&state.app_data).await?;
+        state.ingest_queue.enqueue(&job.id)
+    };
+    if let Err(e) = queued.await {
+        if let Err(cleanup_err) = fs::remove_dir_all(job.upload_dir()).await {
+            error!(
+                "Failed to clean up upload for job {}: {}",
+                job.id, cleanup_err
+            );
+        }
+        return Err(e);
+    }
+    Ok(job)
+}
+
+async fn process_job(state: &Arc<AppState>, job_id: &str) {
+    let mut job = match Job::from_id(&state.app_data, job_id).await {
+        Ok(job) => job,
+        Err(e) => {
+            error!("Failed to load ingest job {}: {}", job_id, e);
+            return;
+        }
+    };
+
+    if let Err(e) = job.set_status(&state.app_data, JobStatus::Running).await {
+        error!("Failed to mark ingest job {} as running: {}", job.id, e);
+        return;
+    }
+
+    let result = match run_ingest(state, &mut job).await {
+        Ok(row_count) => {
+            info!("Ingest job {} loaded {} rows", job.id, row_count);
+            job.succeed(&state.app_data, row_count).await
+        }
+        Err(e) => {
+            error!("Ingest job {} failed: {}", job.id, e);
+            job.fail(&state.app_data, e.to_string()).await
+        }
+    };
+    if let Err(e) = result {
+        error!("Failed to save ingest job {}: {}", job.id, e);
+    }
+
+    if let Err(e) = fs::remove_dir_all(job.upload_dir()).await {
+        error!("Failed to clean up upload for job {}: {}", job.id, e);
+    }
+}
+
+async fn run_ingest(state: &Arc<AppState>, job: &mut Job) -> Result<u64> {
+    let mut layer = job.layer.clone();
+
+    // Zipped shapefiles are loaded from their extracted .shp
+    let file_path = if is_zip_file(&job.file_path) {
+        let (zip_path, output_dir) = (
+            PathBuf::from(&job.file_path),
+            job.upload_dir().join("shapefile"),
+        );
+        tokio::task::spawn_blocking(move || extract_shapefile_zip(&zip_path, &output_dir)).await??
+    } else {
+        PathBuf::from(&job.file_path)
+    };
+
+    // CSV and Excel rows become points or WKT geometries in a GeoPackage, and the
+    // rows without a usable geometry are reported on the job
+    let file_path = match layer.geometry.clone() {
+        Some(spec) => {
+            let output_dir = job.upload_dir().join("tabular");
+            let (gpkg_path, failed_rows) = tokio::task::spawn_blocking(move || {
+                build_tabular_layer(&file_path, &spec, &output_dir)
+            })
+            .await??;
+            if failed_rows.count > 0 {
+                info!(
+                    "Ingest job {} skipped {} rows without a geometry",
+                    job.id, failed_rows.count
+                );
+            }
+            job.failed_rows = Some(failed_rows);
+            gpkg_path
+        }
+        None => file_path,
+    };
+
+    // A CRS that cannot be read is not fatal, the data may still carry one
+    let detected_srid = {
+        let file_path = file_path.clone();
+        match tokio::task::spawn_blocking(move || detect_file_srid(&file_path)).await? {
+            Ok(srid) => srid,
+            Err(e) => {
+                error!("Failed to detect the CRS for job {}: {}", job.id, e);
+                None
+            }
+        }
+    };
+
+    // Uploads load into a staging table first so a failed load never touches the
+    // layer's current table
+    let workspace = Workspace::from_id(&state.app_data, &layer.workspace_id).await?;
+    let existing = layer.check_mode(&state.app_data, &workspace).await?;
+    // Access may have been revoked since the upload was queued
+    layer
+        .check_connection_access(&state.app_data, &workspace)
+        .await?;
+    let connection_record = Connection::from_name(&state.app_data, &layer.connection_id).await?;
+    let connection = state
+        .geo_connections
+        .get_connection(&state.app_data, &layer.connection_id)
+        .await?;
+
+    let staging_name = layer.staging_table_name(&job.id);
+    layer
+        .load_into_postgis(
+            &connection_record,
+            &file_path.to_string_lossy(),
+            &staging_name,
+        )
+        .await?;
+
+    let mut validation_options = layer.validation_options.take().unwrap_or_default();
+    // Split sources become layers, so they must not take the name of a layer on
+    // any connection of the workspace, and are named after the layer rather than
+    // the staging table
+    if validation_options.split_by_type {
+        validation_options.reserved_names = Layer::get_all(&state.app_data, &workspace)
+            .await?
+            .into_iter()
+            .map(|existing| existing.table_name().to_string())
+            .collect();
+        validation_options.split_base = Some(layer.table_name().to_string());
+    }
+
+    // The staged rows are validated, repaired and split before the commit, so the
+    // only step left once the layer's table has changed is writing its records
+    let mut split_sources = Vec::new();
+    let staged = async {
+        // Appended rows must be stored in the CRS of the existing table
+        let storage = match (layer.mode, &existing) {
+            (LayerMode::Append, Some(existing)) => existing.storage_srid.or(layer.storage_srid),
+            _ => layer.storage_srid,
+        };
+        let crs = connection
+            .set_source_crs(
+                &layer.workspace_id,
+                &staging_name,
+                &CrsOptions {
+                    declared: layer
+                        .source_srid
+                        .or(layer.geometry.as_ref().and_then(|spec| spec.srid())),
+                    detected: detected_srid,
+                    storage,
+                },
+            )
+            .await?;
+
+        let row_count = connection
+            .count_source(&layer.workspace_id, &staging_name)
+            .await?;
+
+        let validation = connection
+            .validate_source(&layer.workspace_id, &staging_name, &validation_options)
+            .await?;
+        split_sources.clone_from(&validation.split_sources);
+        let mut split_validations = Vec::new();
+        for split_source in &validation.split_sources {
+            split_validations.push(
+                connection
+                    .validate_source(
+                        &layer.workspace_id,
+                        split_source,
+                        &ValidationOptions::default(),
+                    )
+                    .await?,
+            );
+        }
+
+        connection
+            .commit_staged_source(
+                &layer.workspace_id,
+                &staging_name,
+                layer.table_name(),
+                layer.mode,
+            )
+            .await?;
+        Ok::<_, anyhow::Error>((crs, row_count, validation, split_validations))
+    };
+    let (crs, row_count, validation, split_validations) = match staged.await {
+        Ok(staged) => staged,
+        Err(e) => {
+            for source in std::iter::once(&staging_name).chain(&split_sources) {
+                if let Err(cleanup_err) =
+                    connection.delete_source(&layer.workspace_id, source).await
+                {
+                    error!(
+                        "Failed to drop staged table {} for job {}: {}",
+                        source, job.id, cleanup_err
+                    );
+                }
+            }
+            return Err(e);
+        }
+    };
+    info!(
+        "Ingest job {} stored EPSG:{} data as EPSG:{} in {} mode",
+        job.id, crs.source_srid, crs.storage_srid, layer.mode
+    );
+    info!(
+        "Ingest job {} loaded {} features, {} invalid and {} repaired",
+        job.id, validation.feature_count, validation.invalid_count, validation.repaired_count
+    );
+    // The table was created, swapped or appended to, so drop any stale tiles
+    state
+        .tile_cache
+        .invalidate_source(
+            &layer.connection_id,
+            &layer.workspace_id,
+            layer.table_name(),
+        )
+        .await;
+
+    layer.source_srid = Some(crs.source_srid);
+    layer.storage_srid = Some(crs.storage_srid);
+    layer.geometry = None;
+    // Replacing or appending keeps the layer's creation time
+    if let Some(existing) = &existing {
+        layer.created_at = existing.created_at;
+    }
+    layer.updated_at = Some(get_unix_timestamp());
+
+    // Each part of a split source becomes a layer of its own
+    for (split_source, split_validation) in split_sources.iter().zip(split_validations) {
+        let split_layer = Layer {
+            name: split_source.clone(),
+            validation: Some(split_validation),
+            ..layer.clone()
+        };
+        split_layer.write_record(&state.app_data).await?;
+    }
+
+    // Appended rows only add to the report of the rows already in the layer
+    layer.validation = match (layer.mode, existing.and_then(|e| e.validation)) {
+        (LayerMode::Append, Some(mut report)) => {
+            report.merge(&validation);
+            Some(report)
+        }
+        _ => Some(validation),
+    };
+    layer.write_record(&state.app_data).await?;
+
+    // The job reports the layer as loaded, with its CRS and validation report
+    job.layer = layer;
+    Ok(row_count)
+}
@@ -0,0 +1,184 @@
+use anyhow::{anyhow, Result};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use std::path::{Path, PathBuf};
+
+// SRIDs a layer can be normalised to when it is stored
+pub const STORAGE_SRIDS: &[i32] = &[4326, 3857];
+
+// ESRI .prj files often carry no authority code, so common CRS names are matched.
+// Names are compared without case, spaces, underscores or hyphens.
+const KNOWN_CRS_NAMES: &[(&str, i32)] = &[
+    ("britishnationalgrid", 27700),
+    ("osgb1936britishnationalgrid", 27700),
+    ("osgb36britishnationalgrid", 27700),
+    ("irenet95irishtransversemercator", 2157),
+    ("tm65irishgrid", 29902),
+    ("gcswgs1984", 4326),
+    ("wgs84", 4326),
+    ("wgs1984", 4326),
+    ("gcsetrs1989", 4258),
+    ("etrs89", 4258),
+    ("wgs1984webmercatorauxiliarysphere", 3857),
+    ("wgs84pseudomercator", 3857),
+    ("webmercator", 3857),
+];
+
+pub fn check_storage_srid(srid: i32) -> Result<()> {
+    if !STORAGE_SRIDS.contains(&srid) {
+        return Err(anyhow!(
+            "Layers can only be stored in EPSG:4326 or EPSG:3857, not EPSG:{}",
+            srid
+        ));
+    }
+    Ok(())
+}
+
+// EPSG code of a WKT CRS, from the authority of the root element or its name
+pub fn srid_from_wkt(wkt: &str) -> Option<i32> {
+    root_authority(wkt).or_else(|| {
+        let name = root_name(wkt)?;
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        KNOWN_CRS_NAMES
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, srid)| *srid)
+    })
+}
+
+// AUTHORITY["EPSG","27700"] (WKT1) or ID["EPSG",27700] (WKT2) directly inside
+// the root element. Nested ones belong to the datum, ellipsoid and so on.
+fn root_authority(wkt: &str) -> Option<i32> {
+    let mut depth = 0;
+    let mut authority = None;
+    for (i, c) in wkt.char_indices() {
+        match c {
+            '[' | '(' => depth += 1,
+            ']' | ')' => depth -= 1,
+            // Keywords only start after a separator, e.g. not the ID in ELLIPSOID
+            _ if depth == 1 && wkt[..i].ends_with([',', ' ', '\n', '\t']) => {
+                let rest = &wkt[i..];
+                let body = rest
+                    .strip_prefix("AUTHORITY[")
+                    .or_else(|| rest.strip_prefix("ID["));
+                if let Some(body) = body {
+                    let body = &body[..body.find(']')?];
+                    let mut parts = body.split(',').map(|p| p.trim().trim_matches('"'));
+                    if parts.next()?.eq_ignore_ascii_case("EPSG") {
+                        authority = parts.next()?.parse().ok();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    authority
+}
+
+fn root_name(wkt: &str) -> Option<&str> {
+    let start = wkt.find('"')? + 1;
+    let end = start + wkt[start..].find('"')?;
+    Some(&wkt[start..end])
+}
+
+// Detect the CRS of an uploaded file: the .prj next to a shapefile or the
+// spatial reference of a GeoPackage's geometry table. None if the file does not
+// say, in which case the loaded data keeps whatever SRID the loader gave it.
+pub fn detect_file_srid(file_path: &Path) -> Result<Option<i32>> {
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+    match extension.as_deref() {
+        Some("shp") => match sidecar_path(file_path, "prj") {
+            Some(prj_path) => {
+                let wkt = std::fs::read_to_string(prj_path)?;
+                Ok(srid_from_wkt(&wkt))
+            }
+            None => Ok(None),
+        },
+        Some("gpkg") => geopackage_srid(file_path),
+        _ => Ok(None),
+    }
+}
+
+// A file with the same stem and the given extension in any case
+fn sidecar_path(file_path: &Path, extension: &str) -> Option<PathBuf> {
+    let stem = file_path.file_stem()?;
+    let dir = file_path.parent()?;
+    std::fs::read_dir(dir).ok()?.find_map(|entry| {
+        let path = entry.ok()?.path();
+        let matches = path.file_stem() == Some(stem)
+            && path
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| e.eq_ignore_ascii_case(extension));
+        matches.then_some(path)
+    })
+}
+
+fn geopackage_srid(file_path: &Path) -> Result<Option<i32>> {
+    let conn = Connection::open_with_flags(file_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    // srs_id 0 and -1 are the GeoPackage's undefined geographic and cartesian systems
+    let srs = conn
+        .query_row(
+            "SELECT s.srs_id, s.organization, s.organization_coordsys_id, s.definition
+            FROM gpkg_geometry_columns g
+            JOIN gpkg_spatial_ref_sys s ON s.srs_id = g.srs_id
+            ORDER BY g.table_name
+            LIMIT 1",
+            [],
+            |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i32>(2)?,
+                    row.get::<_, String>(3)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    Ok(
+        srs.and_then(|(srs_id, organization, coordsys_id, definition)| {
+            if srs_id <= 0 {
+                None
+            } else if organization.eq_ignore_ascii_case("EPSG") {
+                Some(coordsys_id)
+            } else {
+                srid_from_wkt(&definition)
+            }
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srid_from_wkt_reads_the_root_authority() {
+        let wkt1 = r#"PROJCS["OSGB 1936 / British National Grid",GEOGCS["OSGB 1936",DATUM["OSGB_1936",SPHEROID["Airy 1830",6377563.396,299.3249646,AUTHORITY["EPSG","7001"]],AUTHORITY["EPSG","6277"]],PRIMEM["Greenwich",0],UNIT["degree",0.0174532925199433],AUTHORITY["EPSG","4277"]],PROJECTION["Transverse_Mercator"],UNIT["metre",1],AUTHORITY["EPSG","27700"]]"#;
+        assert_eq!(srid_from_wkt(wkt1), Some(27700));
+
+        let wkt2 = r#"GEOGCRS["WGS 84",DATUM["World Geodetic System 1984",ELLIPSOID["WGS 84",6378137,298.257223563]],CS[ellipsoidal,2],ID["EPSG",4326]]"#;
+        assert_eq!(srid_from_wkt(wkt2), Some(4326));
+    }
+
+    #[test]
+    fn srid_from_wkt_falls_back_to_known_names() {
+        // ESRI .prj files only carry authorities on nested elements, if at all
+        let esri = r#"PROJCS["British_National_Grid",GEOGCS["GCS_OSGB_1936",DATUM["D_OSGB_1936",SPHEROID["Airy_1849",6377563.396,299.3249646]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Transverse_Mercator"],UNIT["Meter",1.0]]"#;
+        assert_eq!(srid_from_wkt(esri), Some(27700));
+
+        let web_mercator = r#"PROJCS["WGS_1984_Web_Mercator_Auxiliary_Sphere",GEOGCS["GCS_WGS_1984",DATUM["D_WGS_1984",SPHEROID["WGS_1984",6378137.0,298.257223563]],PRIMEM["Greenwich",0.0],UNIT["Degree",0.0174532925199433]],PROJECTION["Mercator_Auxiliary_Sphere"],UNIT["Meter",1.0]]"#;
+        assert_eq!(srid_from_wkt(web_mercator), Some(3857));
+
+        let unknown =
+            r#"PROJCS["Local_Site_Grid",GEOGCS["GCS_WGS_1984",AUTHORITY["EPSG","4326"]]]"#;
+        assert_eq!(srid_from_wkt(unknown), None);
+    }
+}
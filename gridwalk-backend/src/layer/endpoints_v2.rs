@@ -1,6 +1,9 @@
 use crate::app_state::AppState;
 use crate::auth::AuthUser;
-use crate::{CreateLayer, Layer, User, Workspace, WorkspaceRole};
+use crate::{
+    is_shapefile_sidecar, primary_layer_file, queue_ingest, CreateLayer, Layer, UploadedFile, User,
+    Workspace, WorkspaceRole,
+};
 use axum::{
     extract::{Extension, Multipart, State},
     http::{HeaderMap, StatusCode},
@@ -9,15 +12,16 @@ use axum::{
 };
 use serde_json::json;
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::{
     fs::{self, OpenOptions},
     io::AsyncWriteExt,
 };
+use uuid::Uuid;
 
 #[derive(Debug, PartialEq)]
-enum FileType {
+pub(super) enum FileType {
     Geopackage,
     Json,
     GeoJson,
@@ -37,19 +41,9 @@ struct UploadContext {
     upload_id: String,
     dir_path: std::path::PathBuf,
     layer_info: Option<CreateLayer>,
-    file_path: Option<std::path::PathBuf>,
-}
-
-impl UploadContext {
-    fn update_upload_id_from_path(&mut self, path: &Path) {
-        if let Some(filename) = path
-            .file_name()
-            .and_then(|n| n.to_str())
-            .and_then(|n| n.split('_').nth(1))
-        {
-            self.upload_id = filename.to_string();
-        }
-    }
+    bytes_received: usize,
+    // Several files are sent together for the parts of a shapefile
+    files: Vec<UploadedFile>,
 }
 
 async fn initialize_upload_context(
@@ -105,7 +99,20 @@ async fn initialize_upload_context(
         (StatusCode::INTERNAL_SERVER_ERROR, Json(error))
     })?;
 
-    let upload_id = format!("{}_upload", workspace_id);
+    // The first chunk starts a new upload, later chunks name it with x-upload-id
+    let upload_id = if chunk_number == 0 {
+        Uuid::new_v4().to_string()
+    } else {
+        headers
+            .get("x-upload-id")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| Uuid::parse_str(v).ok())
+            .map(|id| id.to_string())
+            .ok_or_else(|| {
+                let error = json!({ "error": "Missing or invalid upload ID" });
+                (StatusCode::BAD_REQUEST, Json(error))
+            })?
+    };
 
     Ok(UploadContext {
         user: user.clone(),
@@ -115,7 +122,8 @@ async fn initialize_upload_context(
         upload_id,
         dir_path: dir_path.to_path_buf(),
         layer_info: None,
-        file_path: None,
+        bytes_received: 0,
+        files: Vec::new(),
     })
 }
 
@@ -152,14 +160,30 @@ pub async fn upload_layer_v2(
                     if let Some(filename) = field.file_name() {
                         tracing::info!("Processing filename: {}", filename);
 
+                        // Only the final path component is kept
+                        let filename = Path::new(filename)
+                            .file_name()
+                            .and_then(|n| n.to_str())
+                            .map(str::to_string)
+                            .ok_or_else(|| {
+                                let error = json!({
+                                    "error": "Invalid file name",
+                                    "details": filename
+                                });
+                                (StatusCode::BAD_REQUEST, Json(error))
+                            })?;
                         let temp_path = context
                             .dir_path
                             .join(format!("{}_{filename}", context.upload_id));
                         tracing::info!("Processing file at path: {}", temp_path.display());
 
+                        // Later chunks append to the file the first chunk created
+                        let first_chunk = context.chunk_number == 0;
                         let mut file = OpenOptions::new()
-                            .create(true)
-                            .append(true)
+                            .create(first_chunk)
+                            .truncate(first_chunk)
+                            .write(true)
+                            .append(!first_chunk)
                             .open(&temp_path)
                             .await
                             .map_err(|e| {
@@ -177,8 +201,9 @@ pub async fn upload_layer_v2(
 
                         let mut chunk_bytes = 0usize;
 
-                        // Get first chunk to validate
-                        if context.chunk_number == 0 {
+                        // Get first chunk to validate. Shapefile sidecars have no
+                        // recognisable header and are checked with the rest of the set.
+                        if context.chunk_number == 0 && !is_shapefile_sidecar(&filename) {
                             if let Some(first_chunk) = field.chunk().await.map_err(|e| {
                                 let error = json!({
                                     "error": "Failed to read file chunk",
@@ -234,26 +259,11 @@ pub async fn upload_layer_v2(
                             chunk_bytes
                         );
 
-                        if context.chunk_number < context.total_chunks - 1 {
-                            tracing::info!("Non-final chunk processed, awaiting more chunks");
-
-                            if context.chunk_number == 0 {
-                                context.update_upload_id_from_path(&temp_path);
-                            }
-
-                            return Ok((
-                                StatusCode::OK,
-                                Json(json!({
-                                    "status": "chunk_received",
-                                    "chunk": context.chunk_number,
-                                    "total": context.total_chunks,
-                                    "upload_id": context.upload_id,
-                                    "bytes_received": chunk_bytes
-                                })),
-                            ));
-                        }
-                        tracing::info!("Final chunk received, processing complete file");
-                        context.file_path = Some(temp_path);
+                        context.bytes_received += chunk_bytes;
+                        context.files.push(UploadedFile {
+                            name: filename,
+                            path: temp_path,
+                        });
                     }
                 }
                 "layer_info" => {
@@ -290,47 +300,85 @@ pub async fn upload_layer_v2(
         }
     }
 
-    // Get final path - use shapefile path if it's a shapefile upload
-    let final_path = context.file_path.ok_or_else(|| {
+    if context.files.is_empty() {
         let error = json!({
             "error": "No file was uploaded",
             "details": null
         });
-        (StatusCode::BAD_REQUEST, Json(error))
-    })?;
+        return Err((StatusCode::BAD_REQUEST, Json(error)));
+    }
 
-    let layer_info = context.layer_info.ok_or_else(|| {
-        let error = json!({
-            "error": "No layer info provided",
-            "details": null
-        });
-        (StatusCode::BAD_REQUEST, Json(error))
-    })?;
+    if context.chunk_number < context.total_chunks - 1 {
+        tracing::info!("Non-final chunk processed, awaiting more chunks");
+
+        return Ok((
+            StatusCode::OK,
+            Json(json!({
+                "status": "chunk_received",
+                "chunk": context.chunk_number,
+                "total": context.total_chunks,
+                "upload_id": context.upload_id,
+                "bytes_received": context.bytes_received
+            })),
+        ));
+    }
+    tracing::info!("Final chunk received, processing complete upload");
+    let files = context.files;
 
-    let layer = Layer::from_req(layer_info, &context.user);
+    let layer_info = match context.layer_info {
+        Some(layer_info) => layer_info,
+        None => {
+            remove_uploaded_files(&files).await;
+            let error = json!({
+                "error": "No layer info provided",
+                "details": null
+            });
+            return Err((StatusCode::BAD_REQUEST, Json(error)));
+        }
+    };
 
-    match process_layer(&state, &layer, &context.user, &final_path).await {
-        Ok(json_response) => {
-            // Cleanup file after successful processing
-            if let Err(cleanup_err) = fs::remove_file(&final_path).await {
-                tracing::error!(
-                    "Failed to clean up file after successful upload: {}",
-                    cleanup_err
-                );
-                // Continue with success response even if cleanup fails - NEED TO CHANGE THIS
-            } else {
-                tracing::info!(
-                    "Successfully cleaned up temporary file: {}",
-                    final_path.display()
-                );
-            }
-            Ok((StatusCode::OK, Json(json_response)))
+    // Check the files make up one layer, e.g. that a shapefile has its .shx and .dbf
+    let checked = {
+        let files = files.clone();
+        tokio::task::spawn_blocking(move || primary_layer_file(&files).map(|f| f.path.clone()))
+            .await
+            .unwrap_or_else(|e| Err(e.into()))
+    };
+    let final_path = match checked {
+        Ok(path) => path,
+        Err(e) => {
+            remove_uploaded_files(&files).await;
+            let error = json!({
+                "error": "Invalid layer upload",
+                "details": e.to_string()
+            });
+            return Err((StatusCode::BAD_REQUEST, Json(error)));
         }
+    };
+    let sidecars: Vec<PathBuf> = files
+        .iter()
+        .map(|f| f.path.clone())
+        .filter(|path| *path != final_path)
+        .collect();
+
+    let layer = Layer::from_req(layer_info, &context.user);
+
+    match process_layer(&state, &layer, &context.user, &final_path, &sidecars).await {
+        // The files now belong to the job, which removes them once loaded
+        Ok(json_response) => Ok((StatusCode::ACCEPTED, Json(json_response))),
         Err(e) => {
-            if let Err(cleanup_err) = fs::remove_file(&final_path).await {
+            remove_uploaded_files(&files).await;
+            Err(e)
+        }
+    }
+}
+
+async fn remove_uploaded_files(files: &[UploadedFile]) {
+    for file in files {
+        if let Err(cleanup_err) = fs::remove_file(&file.path).await {
+            if cleanup_err.kind() != std::io::ErrorKind::NotFound {
                 tracing::error!("Failed to clean up file after error: {}", cleanup_err);
             }
-            Err(e)
         }
     }
 }
@@ -340,6 +388,7 @@ async fn process_layer(
     layer: &Layer,
     user: &User,
     file_path: &Path,
+    sidecars: &[PathBuf],
 ) -> Result<serde_json::Value, (StatusCode, Json<serde_json::Value>)> {
     // Validate workspace access
     let workspace = Workspace::from_id(&state.app_data, &layer.workspace_id)
@@ -384,49 +433,60 @@ async fn process_layer(
             (StatusCode::INTERNAL_SERVER_ERROR, Json(error))
         })?;
 
-    // Process the file
-    println!("Processing layer: {}", layer.name);
     layer
-        .send_to_postgis(file_path.to_str().unwrap())
+        .check_ingest_options(&file_path.to_string_lossy())
+        .map_err(|e| {
+            let error = json!({
+                "error": "Invalid layer info",
+                "details": e.to_string()
+            });
+            (StatusCode::BAD_REQUEST, Json(error))
+        })?;
+
+    layer
+        .check_connection_access(&state.app_data, &workspace)
+        .await
+        .map_err(|e| {
+            let error = json!({
+                "error": "Connection access denied",
+                "details": e.to_string()
+            });
+            (StatusCode::FORBIDDEN, Json(error))
+        })?;
+
+    // Checked again when the job runs, as the layer may change in the meantime
+    layer
+        .check_mode(&state.app_data, &workspace)
         .await
         .map_err(|e| {
             let error = json!({
-                "error": "Failed to process file",
+                "error": "Layer mode conflict",
+                "details": e.to_string()
+            });
+            (StatusCode::CONFLICT, Json(error))
+        })?;
+
+    // Loading into PostGIS can take minutes, so hand the file to an ingest job
+    // and let the client poll the job for the outcome
+    let job = queue_ingest(state, layer, user, file_path, sidecars)
+        .await
+        .map_err(|e| {
+            let error = json!({
+                "error": "Failed to queue layer processing",
                 "details": e.to_string()
             });
             (StatusCode::INTERNAL_SERVER_ERROR, Json(error))
         })?;
 
-    println!("Layer '{}' sent to PostGIS", layer.name);
-
-    // Strip the file extension in the name before writing to database
-    // the send to postgis does this automatically in the duckdb code that is called in it
-    let clean_name = Path::new(&layer.name)
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or(&layer.name);
-    
-    println!("Writing layer record to database with name: {}", clean_name);
-    
-    // Write the record to the admin database (e.g. DynamoDB)
-    layer.write_record(&state.app_data).await.map_err(|e| {
-        let error = json!({
-            "error": "Failed to write layer record to Database",
-            "details": e.to_string()
-        });
-        (StatusCode::INTERNAL_SERVER_ERROR, Json(error))
-    })?;
+    tracing::info!("Layer '{}' queued as job {}", layer.name, job.id);
 
-    // Return success response
-    Ok(serde_json::to_value(layer).unwrap_or_else(|_| {
-        json!({
-            "status": "success",
-            "message": "Layer created successfully"
-        })
+    Ok(json!({
+        "status": job.status,
+        "job_id": job.id
     }))
 }
 
-async fn validate_first_chunk(
+pub(super) async fn validate_first_chunk(
     chunk: &[u8],
 ) -> Result<FileType, (StatusCode, Json<serde_json::Value>)> {
     match determine_file_type(chunk) {
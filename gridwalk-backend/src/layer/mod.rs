@@ -1,7 +1,15 @@
+mod crs;
 mod endpoints;
 mod endpoints_v2;
 mod layer;
+mod shapefile;
+mod tabular;
+mod upload_session;
 
+pub use crs::*;
 pub use endpoints::*;
 pub use endpoints_v2::*;
 pub use layer::*;
+pub use shapefile::*;
+pub use tabular::*;
+pub use upload_session::*;
@@ -0,0 +1,233 @@
+use anyhow::{anyhow, Result};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use zip::ZipArchive;
+
+// .shx and .dbf must come with the .shp. .prj carries the CRS and .cpg the
+// attribute encoding, so they are kept when present.
+const REQUIRED_COMPONENTS: &[&str] = &["shp", "shx", "dbf"];
+const OPTIONAL_COMPONENTS: &[&str] = &["prj", "cpg"];
+
+// A .shp or .dbf cannot be larger than 2 GiB, so nothing in a zip needs more
+const MAX_ENTRY_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+const MAX_EXTRACTED_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct UploadedFile {
+    // Name the client gave the file
+    pub name: String,
+    pub path: PathBuf,
+}
+
+// The components found for one shapefile
+#[derive(Debug, Clone)]
+pub struct ShapefileParts {
+    pub stem: String,
+    pub extensions: BTreeSet<String>,
+}
+
+impl ShapefileParts {
+    pub fn has_prj(&self) -> bool {
+        self.extensions.contains("prj")
+    }
+
+    fn validate(&self) -> Result<()> {
+        let missing: Vec<String> = REQUIRED_COMPONENTS
+            .iter()
+            .filter(|ext| !self.extensions.contains(**ext))
+            .map(|ext| format!(".{}", ext))
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Shapefile '{}' is missing its {} file{}",
+                self.stem,
+                missing.join(" and "),
+                if missing.len() > 1 { "s" } else { "" }
+            ));
+        }
+        if !self.has_prj() {
+            tracing::warn!(
+                "Shapefile '{}' has no .prj file, its CRS cannot be read",
+                self.stem
+            );
+        }
+        Ok(())
+    }
+}
+
+fn split_component(name: &str) -> Option<(&str, String)> {
+    let (stem, ext) = name.rsplit_once('.')?;
+    let ext = ext.to_lowercase();
+    let known =
+        REQUIRED_COMPONENTS.contains(&ext.as_str()) || OPTIONAL_COMPONENTS.contains(&ext.as_str());
+    (known && !stem.is_empty()).then_some((stem, ext))
+}
+
+pub fn is_shapefile_component(name: &str) -> bool {
+    split_component(name).is_some()
+}
+
+// Parts that cannot be recognised from their first bytes alone
+pub fn is_shapefile_sidecar(name: &str) -> bool {
+    split_component(name).is_some_and(|(_, ext)| ext != "shp")
+}
+
+pub fn is_zip_file(name: &str) -> bool {
+    name.to_lowercase().ends_with(".zip")
+}
+
+// Group component file names by stem, expecting exactly one complete shapefile
+fn group_components<'a>(names: impl Iterator<Item = &'a str>) -> Result<ShapefileParts> {
+    let mut stems: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    for (stem, ext) in names.filter_map(split_component) {
+        stems.entry(stem).or_default().insert(ext);
+    }
+
+    let with_shp: Vec<&str> = stems
+        .iter()
+        .filter(|(_, exts)| exts.contains("shp"))
+        .map(|(stem, _)| *stem)
+        .collect();
+    match with_shp.as_slice() {
+        [] => Err(anyhow!("No .shp file found")),
+        [stem] => {
+            if stems.len() > 1 {
+                let others: Vec<&str> = stems.keys().filter(|s| *s != stem).copied().collect();
+                return Err(anyhow!(
+                    "Shapefile components must share the name '{}', also found: {}",
+                    stem,
+                    others.join(", ")
+                ));
+            }
+            let parts = ShapefileParts {
+                stem: stem.to_string(),
+                extensions: stems.remove(stem).unwrap_or_default(),
+            };
+            parts.validate()?;
+            Ok(parts)
+        }
+        several => Err(anyhow!(
+            "Found {} shapefiles ({}), upload one at a time",
+            several.len(),
+            several.join(", ")
+        )),
+    }
+}
+
+// Name of a zip entry without its folders. Directories and macOS resource
+// forks are skipped.
+fn zip_entry_name(path: &str) -> Option<&str> {
+    if path.ends_with('/') || path.starts_with("__MACOSX/") {
+        return None;
+    }
+    let name = path.rsplit('/').next()?;
+    (!name.is_empty() && !name.starts_with("._")).then_some(name)
+}
+
+// Check a zip holds one complete shapefile. Only the central directory is read.
+pub fn inspect_shapefile_zip(path: &Path) -> Result<ShapefileParts> {
+    let archive = ZipArchive::new(File::open(path)?)
+        .map_err(|e| anyhow!("Failed to read zip file: {}", e))?;
+    group_components(archive.file_names().filter_map(zip_entry_name))
+}
+
+// Extract the shapefile in a zip into a directory, returning the .shp path.
+// Entries are written by their base name, so paths in the zip cannot escape it.
+pub fn extract_shapefile_zip(path: &Path, output_dir: &Path) -> Result<PathBuf> {
+    let parts = inspect_shapefile_zip(path)?;
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    std::fs::create_dir_all(output_dir)?;
+
+    // Sizes in the zip can be forged, so the limits are applied to the bytes
+    // actually written
+    let mut extracted = 0u64;
+    for index in 0..archive.len() {
+        let entry = archive.by_index(index)?;
+        let Some(name) = zip_entry_name(entry.name()) else {
+            continue;
+        };
+        let Some((stem, ext)) = split_component(name) else {
+            continue;
+        };
+        if stem != parts.stem {
+            continue;
+        }
+        let file_name = format!("{}.{}", stem, ext);
+        let limit = MAX_ENTRY_BYTES.min(MAX_EXTRACTED_BYTES - extracted);
+        let mut output = File::create(output_dir.join(&file_name))?;
+        let written = std::io::copy(&mut entry.take(limit + 1), &mut output)?;
+        if written > limit {
+            return Err(anyhow!(
+                "'{}' is too large to extract, the limit is {} MiB per file and {} MiB in total",
+                file_name,
+                MAX_ENTRY_BYTES / 1024 / 1024,
+                MAX_EXTRACTED_BYTES / 1024 / 1024
+            ));
+        }
+        extracted += written;
+    }
+
+    Ok(output_dir.join(format!("{}.shp", parts.stem)))
+}
+
+// Check the files of an upload make up one layer and return the file to load.
+// Several files must be the components of a single shapefile, and a zip must
+// hold one.
+pub fn primary_layer_file(files: &[UploadedFile]) -> Result<&UploadedFile> {
+    match files {
+        [] => Err(anyhow!("No file was uploaded")),
+        [file] if is_zip_file(&file.name) => {
+            inspect_shapefile_zip(&file.path)?;
+            Ok(file)
+        }
+        [file] if !is_shapefile_component(&file.name) => Ok(file),
+        files => {
+            if let Some(other) = files.iter().find(|f| !is_shapefile_component(&f.name)) {
+                return Err(anyhow!(
+                    "'{}' is not part of a shapefile, only one file can be uploaded otherwise",
+                    other.name
+                ));
+            }
+            let parts = group_components(files.iter().map(|f| f.name.as_str()))?;
+            files
+                .iter()
+                .find(|f| split_component(&f.name).is_some_and(|(_, ext)| ext == "shp"))
+                .ok_or_else(|| anyhow!("No .shp file found in '{}'", parts.stem))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_components_finds_one_complete_shapefile() {
+        let parts =
+            group_components(["Roads.shp", "Roads.SHX", "Roads.dbf", "Roads.prj"].into_iter())
+                .unwrap();
+        assert_eq!(parts.stem, "Roads");
+        assert!(parts.has_prj());
+        assert_eq!(parts.extensions.len(), 4);
+    }
+
+    #[test]
+    fn group_components_reports_missing_and_mixed_components() {
+        let missing = group_components(["roads.shp", "roads.prj"].into_iter()).unwrap_err();
+        assert_eq!(
+            missing.to_string(),
+            "Shapefile 'roads' is missing its .shx and .dbf files"
+        );
+
+        let mixed =
+            group_components(["roads.shp", "roads.shx", "rivers.dbf"].into_iter()).unwrap_err();
+        assert!(mixed.to_string().contains("also found: rivers"));
+
+        let several = group_components(["a.shp", "b.shp"].into_iter()).unwrap_err();
+        assert!(several.to_string().starts_with("Found 2 shapefiles"));
+
+        assert!(group_components(["roads.dbf"].into_iter()).is_err());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::connector::open_duckdb;
+use anyhow::{anyhow, Result};
+use calamine::{open_workbook_auto, Reader};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// Only the first failed rows are kept, the count covers all of them
+const MAX_REPORTED_ROWS: usize = 100;
+
+// Internal columns added while the rows are checked
+const ROW_COLUMN: &str = "__gw_row";
+const ERROR_COLUMN: &str = "__gw_error";
+
+// Which columns of a CSV or Excel file hold the geometry. Postcodes are not
+// supported, geocoding them needs a postcode lookup table the server does not
+// have, so files with only postcodes must be geocoded before upload.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum GeometrySpec {
+    LonLat {
+        lon: String,
+        lat: String,
+    },
+    EastingNorthing {
+        easting: String,
+        northing: String,
+        srid: i32,
+    },
+    Wkt {
+        column: String,
+        #[serde(default)]
+        srid: Option<i32>,
+    },
+}
+
+impl GeometrySpec {
+    // CRS of the coordinates, if the spec says
+    pub fn srid(&self) -> Option<i32> {
+        match self {
+            GeometrySpec::LonLat { .. } => Some(4326),
+            GeometrySpec::EastingNorthing { srid, .. } => Some(*srid),
+            GeometrySpec::Wkt { srid, .. } => *srid,
+        }
+    }
+
+    fn columns(&self) -> Vec<&str> {
+        match self {
+            GeometrySpec::LonLat { lon, lat } => vec![lon, lat],
+            GeometrySpec::EastingNorthing {
+                easting, northing, ..
+            } => vec![easting, northing],
+            GeometrySpec::Wkt { column, .. } => vec![column],
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.columns().iter().any(|c| c.trim().is_empty()) {
+            return Err(anyhow!("Geometry column names cannot be empty"));
+        }
+        if let Some(srid) = self.srid() {
+            if srid <= 0 {
+                return Err(anyhow!("Invalid geometry srid: {}", srid));
+            }
+        }
+        Ok(())
+    }
+
+    // SQL giving why a row has no geometry, or NULL when it has one
+    fn error_expression(&self) -> String {
+        let checks = match self {
+            GeometrySpec::LonLat { lon, lat } => vec![
+                number_check(lon, Some(180.0)),
+                number_check(lat, Some(90.0)),
+            ],
+            GeometrySpec::EastingNorthing {
+                easting, northing, ..
+            } => vec![number_check(easting, None), number_check(northing, None)],
+            GeometrySpec::Wkt { column, .. } => {
+                let c = quote_identifier(column);
+                vec![format!(
+                    "CASE WHEN {c} IS NULL OR trim({c}::VARCHAR) = '' THEN {empty}
+                    WHEN ST_GeomFromText({c}::VARCHAR, true) IS NULL THEN {invalid}
+                    WHEN ST_IsEmpty(ST_GeomFromText({c}::VARCHAR, true)) THEN {no_coords} END",
+                    empty = sql_literal(&format!("{} is empty", column)),
+                    invalid = sql_literal(&format!("{} is not valid WKT", column)),
+                    no_coords = sql_literal(&format!("{} is an empty geometry", column)),
+                )]
+            }
+        };
+        format!("coalesce({})", checks.join(", "))
+    }
+
+    fn geometry_expression(&self) -> String {
+        let number = |c: &str| format!("TRY_CAST({} AS DOUBLE)", quote_identifier(c));
+        match self {
+            GeometrySpec::LonLat { lon, lat } => {
+                format!("ST_Point({}, {})", number(lon), number(lat))
+            }
+            GeometrySpec::EastingNorthing {
+                easting, northing, ..
+            } => format!("ST_Point({}, {})", number(easting), number(northing)),
+            GeometrySpec::Wkt { column, .. } => format!(
+                "ST_GeomFromText({}::VARCHAR, true)",
+                quote_identifier(column)
+            ),
+        }
+    }
+}
+
+// A row that could not be turned into a geometry. Rows are numbered as in the
+// file, with the header as row 1.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FailedRow {
+    pub row: u64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FailedRows {
+    pub count: u64,
+    pub rows: Vec<FailedRow>,
+}
+
+pub fn is_tabular_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [".csv", ".xlsx", ".xls"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+}
+
+fn is_excel_file(name: &str) -> bool {
+    let name = name.to_lowercase();
+    name.ends_with(".xlsx") || name.ends_with(".xls")
+}
+
+// Build a GeoPackage of the rows of a CSV or Excel file that have a geometry,
+// reporting the rows that do not. The output is written in the spec's CRS so the
+// usual CRS detection picks it up. Blocking, so run it off the async workers.
+pub fn build_tabular_layer(
+    file_path: &Path,
+    spec: &GeometrySpec,
+    output_dir: &Path,
+) -> Result<(PathBuf, FailedRows)> {
+    std::fs::create_dir_all(output_dir)?;
+    let file_name = file_path.to_string_lossy();
+    let stem = file_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .ok_or_else(|| anyhow!("Invalid file name: {}", file_name))?;
+
+    // Excel sheets are read as CSV, numbered from the row holding the header
+    let (csv_path, header_row) = if is_excel_file(&file_name) {
+        let csv_path = output_dir.join(format!("{}.csv", stem));
+        let header_row = excel_to_csv(file_path, &csv_path)?;
+        (csv_path, header_row)
+    } else {
+        (file_path.to_path_buf(), 1)
+    };
+
+    let conn = open_duckdb(&[])?;
+
+    // Every row is sampled so a stray value late in the file cannot fail the read.
+    // Rows are numbered from the rowid of the loaded table, which follows the file
+    // order, so row numbers and feature ids are the same on every import.
+    conn.execute_batch(&format!(
+        "CREATE TABLE raw AS
+        SELECT * FROM read_csv({}, header = true, sample_size = -1);
+        CREATE TABLE source AS
+        SELECT rowid + 1 AS {ROW_COLUMN}, * FROM raw ORDER BY rowid;
+        DROP TABLE raw;",
+        sql_literal(&csv_path.to_string_lossy())
+    ))
+    .map_err(|e| anyhow!("Failed to read {}: {}", file_name, e))?;
+
+    let columns = source_columns(&conn)?;
+    let missing: Vec<&str> = spec
+        .columns()
+        .into_iter()
+        .filter(|c| !columns.iter().any(|col| col == c))
+        .collect();
+    if !missing.is_empty() {
+        return Err(anyhow!(
+            "Column{} {} not found, the file has: {}",
+            if missing.len() > 1 { "s" } else { "" },
+            missing.join(", "),
+            columns.join(", ")
+        ));
+    }
+
+    conn.execute_batch(&format!(
+        "CREATE TABLE checked AS SELECT *, {} AS {ERROR_COLUMN} FROM source;",
+        spec.error_expression()
+    ))
+    .map_err(|e| anyhow!("Failed to check geometry columns: {}", e))?;
+
+    let failed = failed_rows(&conn, header_row)?;
+    let loaded = count_rows(&conn, &format!("{ERROR_COLUMN} IS NULL"))?;
+    if loaded == 0 {
+        let example = failed
+            .rows
+            .first()
+            .map(|r| format!(", e.g. row {}: {}", r.row, r.reason))
+            .unwrap_or_default();
+        return Err(anyhow!("No rows have a valid geometry{}", example));
+    }
+
+    let geometry_column = ["geom", "geometry", "the_geom"]
+        .into_iter()
+        .find(|name| !columns.iter().any(|c| c.eq_ignore_ascii_case(name)))
+        .ok_or_else(|| anyhow!("No free column name for the geometry"))?;
+    let target = output_dir.join(format!("{}.gpkg", stem));
+    let srs = spec
+        .srid()
+        .map(|srid| format!(", SRS {}", sql_literal(&format!("EPSG:{}", srid))))
+        .unwrap_or_default();
+    conn.execute_batch(&format!(
+        "COPY (
+            SELECT * EXCLUDE ({ROW_COLUMN}, {ERROR_COLUMN}), {} AS {}
+            FROM checked WHERE {ERROR_COLUMN} IS NULL ORDER BY {ROW_COLUMN}
+        ) TO {} (FORMAT GDAL, DRIVER 'GPKG'{});",
+        spec.geometry_expression(),
+        quote_identifier(geometry_column),
+        sql_literal(&target.to_string_lossy()),
+        srs
+    ))
+    .map_err(|e| anyhow!("Failed to write point layer: {}", e))?;
+
+    Ok((target, failed))
+}
+
+// Write the first sheet of a workbook as CSV, returning the sheet row number of
+// the header. Sheets are trimmed to their used range, so leading empty rows are
+// skipped.
+fn excel_to_csv(file_path: &Path, csv_path: &Path) -> Result<u64> {
+    let mut workbook =
+        open_workbook_auto(file_path).map_err(|e| anyhow!("Failed to read Excel file: {}", e))?;
+    let range = workbook
+        .worksheet_range_at(0)
+        .ok_or_else(|| anyhow!("Excel file has no sheets"))?
+        .map_err(|e| anyhow!("Failed to read Excel sheet: {}", e))?;
+
+    let mut writer = csv::Writer::from_path(csv_path)?;
+    for row in range.rows() {
+        writer.write_record(row.iter().map(|cell| cell.to_string()))?;
+    }
+    writer.flush()?;
+    Ok(range.start().map(|(row, _)| row as u64 + 1).unwrap_or(1))
+}
+
+fn source_columns(conn: &duckdb::Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT column_name FROM (DESCRIBE source)")?;
+    let mut rows = stmt.query(duckdb::params![])?;
+    let mut columns = Vec::new();
+    while let Some(row) = rows.next()? {
+        let name: String = row.get(0)?;
+        if name != ROW_COLUMN {
+            columns.push(name);
+        }
+    }
+    Ok(columns)
+}
+
+fn count_rows(conn: &duckdb::Connection, condition: &str) -> Result<u64> {
+    let mut stmt = conn.prepare(&format!("SELECT count(*) FROM checked WHERE {}", condition))?;
+    let mut rows = stmt.query(duckdb::params![])?;
+    let count: i64 = match rows.next()? {
+        Some(row) => row.get(0)?,
+        None => 0,
+    };
+    Ok(count as u64)
+}
+
+fn failed_rows(conn: &duckdb::Connection, header_row: u64) -> Result<FailedRows> {
+    let count = count_rows(conn, &format!("{ERROR_COLUMN} IS NOT NULL"))?;
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ROW_COLUMN}, {ERROR_COLUMN} FROM checked
+        WHERE {ERROR_COLUMN} IS NOT NULL ORDER BY {ROW_COLUMN} LIMIT {MAX_REPORTED_ROWS}"
+    ))?;
+    let mut rows = stmt.query(duckdb::params![])?;
+    let mut failed = Vec::new();
+    while let Some(row) = rows.next()? {
+        let number: i64 = row.get(0)?;
+        failed.push(FailedRow {
+            row: header_row + number as u64,
+            reason: row.get(1)?,
+        });
+    }
+    Ok(FailedRows {
+        count,
+        rows: failed,
+    })
+}
+
+// Reason a coordinate column is unusable, optionally checking it is within +-limit
+fn number_check(column: &str, limit: Option<f64>) -> String {
+    let c = quote_identifier(column);
+    let range = limit
+        .map(|limit| {
+            format!(
+                "WHEN abs(TRY_CAST({c} AS DOUBLE)) > {limit} THEN {}",
+                sql_literal(&format!("{} is out of range", column))
+            )
+        })
+        .unwrap_or_default();
+    format!(
+        "CASE WHEN {c} IS NULL OR trim({c}::VARCHAR) = '' THEN {empty}
+        WHEN TRY_CAST({c} AS DOUBLE) IS NULL OR NOT isfinite(TRY_CAST({c} AS DOUBLE))
+            THEN {invalid} || {c}::VARCHAR
+        {range} END",
+        empty = sql_literal(&format!("{} is empty", column)),
+        invalid = sql_literal(&format!("{} is not a number: ", column)),
+    )
+}
+
+fn quote_identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn sql_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn geometry_spec_reads_tagged_json() {
+        let spec: GeometrySpec = serde_json::from_value(serde_json::json!({
+            "type": "easting_northing",
+            "easting": "X",
+            "northing": "Y",
+            "srid": 27700
+        }))
+        .unwrap();
+        assert_eq!(spec.srid(), Some(27700));
+        assert_eq!(spec.columns(), vec!["X", "Y"]);
+        assert!(spec.validate().is_ok());
+    }
+
+    #[test]
+    fn geometry_spec_rejects_empty_columns_and_bad_srids() {
+        let empty = GeometrySpec::LonLat {
+            lon: " ".to_string(),
+            lat: "lat".to_string(),
+        };
+        assert!(empty.validate().is_err());
+
+        let bad_srid = GeometrySpec::Wkt {
+            column: "wkt".to_string(),
+            srid: Some(0),
+        };
+        assert!(bad_srid.validate().is_err());
+    }
+
+    #[test]
+    fn quotes_identifiers_and_literals() {
+        assert_eq!(quote_identifier("a\"b"), "\"a\"\"b\"");
+        assert_eq!(sql_literal("it's"), "'it''s'");
+        assert!(is_tabular_file("Sites.XLSX"));
+        assert!(!is_tabular_file("sites.gpkg"));
+    }
+}
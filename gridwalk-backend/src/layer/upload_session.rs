@@ -0,0 +1,697 @@
+use super::endpoints_v2::validate_first_chunk;
+use crate::app_state::AppState;
+use crate::auth::AuthUser;
+use crate::utils::get_unix_timestamp;
+use crate::{
+    is_shapefile_component, is_shapefile_sidecar, primary_layer_file, queue_ingest, CreateLayer,
+    Layer, UploadedFile, User, Workspace, WorkspaceRole,
+};
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Extension, Path as UrlPath, State},
+    http::{HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+type UploadError = (StatusCode, Json<serde_json::Value>);
+
+fn upload_error(status: StatusCode, error: &str, details: impl ToString) -> UploadError {
+    let error = json!({
+        "error": error,
+        "details": details.to_string()
+    });
+    (status, Json(error))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateUpload {
+    pub file_name: String,
+    pub total_chunks: u32,
+    pub layer_info: CreateLayer,
+    // Other components of a shapefile named by file_name, e.g. roads.dbf, each
+    // sent whole with upload_sidecar
+    #[serde(default)]
+    pub sidecars: Vec<String>,
+}
+
+// An upload in progress. Each session has its own directory holding this record
+// as JSON and one file per received chunk, so uploads never share a file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UploadSession {
+    pub id: String,
+    pub created_by: String,
+    pub file_name: String,
+    pub total_chunks: u32,
+    pub layer_info: CreateLayer,
+    pub created_at: u64,
+    #[serde(default)]
+    pub sidecars: Vec<String>,
+}
+
+// Uploads not completed within this many seconds are removed, from GW_UPLOAD_TTL_HOURS
+fn upload_ttl() -> u64 {
+    std::env::var("GW_UPLOAD_TTL_HOURS")
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(24)
+        * 3600
+}
+
+impl UploadSession {
+    fn dir_for(id: &str) -> PathBuf {
+        Path::new("uploads").join("sessions").join(id)
+    }
+
+    fn is_expired(&self, ttl: u64) -> bool {
+        get_unix_timestamp().saturating_sub(self.created_at) > ttl
+    }
+
+    fn dir(&self) -> PathBuf {
+        Self::dir_for(&self.id)
+    }
+
+    fn chunk_path(&self, index: u32) -> PathBuf {
+        self.dir().join("chunks").join(format!("{}.part", index))
+    }
+
+    fn sidecar_path(&self, name: &str) -> PathBuf {
+        self.dir().join("sidecars").join(name)
+    }
+
+    async fn create(&self) -> Result<()> {
+        fs::create_dir_all(self.dir().join("chunks")).await?;
+        fs::create_dir_all(self.dir().join("sidecars")).await?;
+        fs::write(self.dir().join("upload.json"), serde_json::to_vec(self)?).await?;
+        Ok(())
+    }
+
+    // Sessions can only be seen by the user who created them, so an id that
+    // belongs to someone else is reported as not found
+    async fn load(id: &str, user: &User) -> Result<Self, UploadError> {
+        let not_found = || upload_error(StatusCode::NOT_FOUND, "Upload not found", id);
+        // Ids are uuids, anything else cannot name a session directory
+        Uuid::parse_str(id).map_err(|_| not_found())?;
+        let data = fs::read(Self::dir_for(id).join("upload.json"))
+            .await
+            .map_err(|_| not_found())?;
+        let session: UploadSession = serde_json::from_slice(&data).map_err(|e| {
+            upload_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to read upload",
+                e,
+            )
+        })?;
+        if session.created_by != user.id {
+            return Err(not_found());
+        }
+        if session.is_expired(upload_ttl()) {
+            if let Err(e) = fs::remove_dir_all(session.dir()).await {
+                tracing::error!("Failed to remove expired upload {}: {}", session.id, e);
+            }
+            return Err(not_found());
+        }
+        Ok(session)
+    }
+
+    async fn received_chunks(&self) -> Result<Vec<u32>> {
+        let mut received = Vec::new();
+        let mut entries = fs::read_dir(self.dir().join("chunks")).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            if let Some(index) = name
+                .to_str()
+                .and_then(|n| n.strip_suffix(".part"))
+                .and_then(|n| n.parse::<u32>().ok())
+            {
+                received.push(index);
+            }
+        }
+        received.sort_unstable();
+        Ok(received)
+    }
+
+    async fn missing_sidecars(&self) -> Result<Vec<String>> {
+        let mut missing = Vec::new();
+        for name in &self.sidecars {
+            if !fs::try_exists(self.sidecar_path(name)).await? {
+                missing.push(name.clone());
+            }
+        }
+        Ok(missing)
+    }
+
+    async fn status(&self) -> Result<serde_json::Value> {
+        let received = self.received_chunks().await?;
+        let missing: Vec<u32> = (0..self.total_chunks)
+            .filter(|index| received.binary_search(index).is_err())
+            .collect();
+        Ok(json!({
+            "upload_id": self.id,
+            "file_name": self.file_name,
+            "total_chunks": self.total_chunks,
+            "received": received,
+            "missing": missing,
+            "sidecars": self.sidecars,
+            "missing_sidecars": self.missing_sidecars().await?
+        }))
+    }
+
+    // Join the chunks in order into a single file, returning its path and SHA-256
+    async fn assemble(&self) -> Result<(PathBuf, String)> {
+        let path = self.dir().join(&self.file_name);
+        let mut file = fs::File::create(&path).await?;
+        let mut hasher = Sha256::new();
+        for index in 0..self.total_chunks {
+            let chunk = fs::read(self.chunk_path(index)).await?;
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.sync_all().await?;
+        Ok((path, format!("{:x}", hasher.finalize())))
+    }
+}
+
+fn require_user(auth_user: &AuthUser) -> Result<&User, UploadError> {
+    auth_user.user.as_ref().ok_or_else(|| {
+        let error = json!({
+            "error": "Unauthorized request",
+            "details": null
+        });
+        (StatusCode::UNAUTHORIZED, Json(error))
+    })
+}
+
+async fn check_write_access(
+    state: &AppState,
+    user: &User,
+    workspace_id: &str,
+) -> Result<Workspace, UploadError> {
+    let workspace = Workspace::from_id(&state.app_data, workspace_id)
+        .await
+        .map_err(|e| upload_error(StatusCode::NOT_FOUND, "Workspace not found", e))?;
+    let member = workspace
+        .get_member(&state.app_data, user)
+        .await
+        .map_err(|e| upload_error(StatusCode::FORBIDDEN, "Access forbidden", e))?;
+    if member.role == WorkspaceRole::Read {
+        return Err(upload_error(
+            StatusCode::FORBIDDEN,
+            "Read-only access",
+            "User does not have write permission",
+        ));
+    }
+    Ok(workspace)
+}
+
+// Base name of a file sent by the client, which cannot clash with the files
+// kept in the session directory
+fn upload_file_name(name: &str) -> Result<String, UploadError> {
+    Path::new(name)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .filter(|n| !["upload.json", "chunks", "sidecars"].contains(n))
+        .map(str::to_string)
+        .ok_or_else(|| upload_error(StatusCode::BAD_REQUEST, "Invalid file name", name))
+}
+
+fn checksum_header(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get("x-checksum")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.trim().to_lowercase())
+}
+
+pub async fn create_upload(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Json(req): Json<CreateUpload>,
+) -> Result<impl IntoResponse, UploadError> {
+    let user = require_user(&auth_user)?;
+    let workspace = check_write_access(&state, user, &req.layer_info.workspace_id).await?;
+    let layer = Layer::from_req(req.layer_info.clone(), user);
+    layer
+        .check_ingest_options(&req.file_name)
+        .map_err(|e| upload_error(StatusCode::BAD_REQUEST, "Invalid layer info", e))?;
+    layer
+        .check_connection_access(&state.app_data, &workspace)
+        .await
+        .map_err(|e| upload_error(StatusCode::FORBIDDEN, "Connection access denied", e))?;
+    // Checked again when the job runs, as the layer may change in the meantime
+    layer
+        .check_mode(&state.app_data, &workspace)
+        .await
+        .map_err(|e| upload_error(StatusCode::CONFLICT, "Layer mode conflict", e))?;
+
+    if req.total_chunks == 0 {
+        return Err(upload_error(
+            StatusCode::BAD_REQUEST,
+            "Invalid total chunks",
+            "An upload needs at least one chunk",
+        ));
+    }
+
+    // Only the final path component is kept, the extension tells the loader the format
+    let file_name = upload_file_name(&req.file_name)?;
+
+    // Sidecars only make sense next to a .shp, the set is checked as a whole
+    // once the upload completes
+    let mut sidecars = Vec::new();
+    for sidecar in &req.sidecars {
+        let name = upload_file_name(sidecar)?;
+        if !is_shapefile_component(&file_name) || !is_shapefile_sidecar(&name) {
+            return Err(upload_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid sidecar",
+                format!("'{}' is not a shapefile component of '{}'", name, file_name),
+            ));
+        }
+        if name == file_name || sidecars.contains(&name) {
+            return Err(upload_error(
+                StatusCode::BAD_REQUEST,
+                "Invalid sidecar",
+                format!("'{}' is given more than once", name),
+            ));
+        }
+        sidecars.push(name);
+    }
+
+    let session = UploadSession {
+        id: Uuid::new_v4().to_string(),
+        created_by: user.id.clone(),
+        file_name,
+        total_chunks: req.total_chunks,
+        layer_info: req.layer_info,
+        created_at: get_unix_timestamp(),
+        sidecars,
+    };
+    session.create().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to create upload",
+            e,
+        )
+    })?;
+
+    tracing::info!("Created upload {} for {}", session.id, session.file_name);
+    let status = session.status().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload",
+            e,
+        )
+    })?;
+    Ok((StatusCode::CREATED, Json(status)))
+}
+
+pub async fn get_upload(
+    Extension(auth_user): Extension<AuthUser>,
+    UrlPath(upload_id): UrlPath<String>,
+) -> Result<impl IntoResponse, UploadError> {
+    let user = require_user(&auth_user)?;
+    let session = UploadSession::load(&upload_id, user).await?;
+    let status = session.status().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload",
+            e,
+        )
+    })?;
+    Ok(Json(status))
+}
+
+// Store one chunk. Sending the same index again replaces the stored chunk, so
+// a retried request never duplicates data.
+pub async fn upload_chunk(
+    Extension(auth_user): Extension<AuthUser>,
+    UrlPath((upload_id, chunk_index)): UrlPath<(String, u32)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, UploadError> {
+    let user = require_user(&auth_user)?;
+    let session = UploadSession::load(&upload_id, user).await?;
+
+    if chunk_index >= session.total_chunks {
+        return Err(upload_error(
+            StatusCode::BAD_REQUEST,
+            "Invalid chunk number",
+            format!(
+                "Chunk {} is outside an upload of {} chunks",
+                chunk_index, session.total_chunks
+            ),
+        ));
+    }
+
+    let checksum = format!("{:x}", Sha256::digest(&body));
+    if let Some(expected) = checksum_header(&headers) {
+        if expected != checksum {
+            return Err(upload_error(
+                StatusCode::BAD_REQUEST,
+                "Chunk checksum mismatch",
+                format!("Expected {}, received {}", expected, checksum),
+            ));
+        }
+    }
+
+    if chunk_index == 0 {
+        validate_first_chunk(&body).await?;
+    }
+
+    // Write then rename so a chunk is either fully stored or not at all
+    let path = session.chunk_path(chunk_index);
+    let temp_path = path.with_extension("tmp");
+    let write = async {
+        fs::write(&temp_path, &body).await?;
+        fs::rename(&temp_path, &path).await
+    };
+    write.await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to write chunk",
+            e,
+        )
+    })?;
+
+    tracing::info!(
+        "Upload {}: chunk {}/{} stored, {} bytes",
+        session.id,
+        chunk_index + 1,
+        session.total_chunks,
+        body.len()
+    );
+
+    Ok(Json(json!({
+        "status": "chunk_received",
+        "upload_id": session.id,
+        "chunk": chunk_index,
+        "total": session.total_chunks,
+        "bytes_received": body.len(),
+        "checksum": checksum
+    })))
+}
+
+// Store a shapefile sidecar declared when the upload was created. Sending it
+// again replaces it.
+pub async fn upload_sidecar(
+    Extension(auth_user): Extension<AuthUser>,
+    UrlPath((upload_id, file_name)): UrlPath<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<impl IntoResponse, UploadError> {
+    let user = require_user(&auth_user)?;
+    let session = UploadSession::load(&upload_id, user).await?;
+
+    if !session.sidecars.contains(&file_name) {
+        return Err(upload_error(
+            StatusCode::NOT_FOUND,
+            "Sidecar not declared",
+            format!("'{}' is not a sidecar of this upload", file_name),
+        ));
+    }
+
+    let checksum = format!("{:x}", Sha256::digest(&body));
+    if let Some(expected) = checksum_header(&headers) {
+        if expected != checksum {
+            return Err(upload_error(
+                StatusCode::BAD_REQUEST,
+                "Sidecar checksum mismatch",
+                format!("Expected {}, received {}", expected, checksum),
+            ));
+        }
+    }
+
+    write_sidecar(&session.sidecar_path(&file_name), &body)
+        .await
+        .map_err(|e| {
+            upload_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to write sidecar",
+                e,
+            )
+        })?;
+
+    Ok(Json(json!({
+        "status": "sidecar_received",
+        "upload_id": session.id,
+        "file_name": file_name,
+        "bytes_received": body.len(),
+        "checksum": checksum
+    })))
+}
+
+// Write then rename so a sidecar is never read half written. Sidecars share the
+// stem of the main file, so each write gets a temp file of its own.
+async fn write_sidecar(path: &Path, body: &[u8]) -> std::io::Result<()> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let temp_path = path.with_file_name(format!("{}.{}.tmp", file_name, Uuid::new_v4()));
+    fs::write(&temp_path, body).await?;
+    fs::rename(&temp_path, path).await
+}
+
+// Join the chunks, check the whole file against the SHA-256 in x-checksum and
+// queue it for ingestion. On a mismatch the chunks are kept so they can be re-sent.
+pub async fn complete_upload(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    UrlPath(upload_id): UrlPath<String>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, UploadError> {
+    let user = require_user(&auth_user)?;
+    let session = UploadSession::load(&upload_id, user).await?;
+
+    let expected = checksum_header(&headers).ok_or_else(|| {
+        upload_error(
+            StatusCode::BAD_REQUEST,
+            "Missing checksum",
+            "x-checksum must hold the SHA-256 of the whole file",
+        )
+    })?;
+
+    let received = session.received_chunks().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload",
+            e,
+        )
+    })?;
+    let missing_sidecars = session.missing_sidecars().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to read upload",
+            e,
+        )
+    })?;
+    if received.len() != session.total_chunks as usize || !missing_sidecars.is_empty() {
+        let status = session.status().await.unwrap_or_default();
+        let error = json!({
+            "error": "Upload incomplete",
+            "details": status
+        });
+        return Err((StatusCode::CONFLICT, Json(error)));
+    }
+
+    // Membership may have changed since the upload was created
+    check_write_access(&state, user, &session.layer_info.workspace_id).await?;
+
+    let (file_path, checksum) = session.assemble().await.map_err(|e| {
+        upload_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to assemble upload",
+            e,
+        )
+    })?;
+    if checksum != expected {
+        if let Err(e) = fs::remove_file(&file_path).await {
+            tracing::error!("Failed to clean up assembled upload: {}", e);
+        }
+        return Err(upload_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "Checksum mismatch",
+            format!("Expected {}, assembled file has {}", expected, checksum),
+        ));
+    }
+
+    // The files are complete, so if they cannot be loaded re-sending will not help
+    let mut files = vec![UploadedFile {
+        name: session.file_name.clone(),
+        path: file_path.clone(),
+    }];
+    files.extend(session.sidecars.iter().map(|name| UploadedFile {
+        name: name.clone(),
+        path: session.sidecar_path(name),
+    }));
+    let sidecars: Vec<PathBuf> = files[1..].iter().map(|f| f.path.clone()).collect();
+    let checked = tokio::task::spawn_blocking(move || primary_layer_file(&files).map(|_| ()))
+        .await
+        .unwrap_or_else(|e| Err(e.into()));
+    if let Err(e) = checked {
+        if let Err(cleanup_err) = fs::remove_dir_all(session.dir()).await {
+            tracing::error!("Failed to clean up upload {}: {}", session.id, cleanup_err);
+        }
+        return Err(upload_error(
+            StatusCode::BAD_REQUEST,
+            "Invalid layer file",
+            e,
+        ));
+    }
+
+    let layer = Layer::from_req(session.layer_info.clone(), user);
+    let job = queue_ingest(&state, &layer, user, &file_path, &sidecars)
+        .await
+        .map_err(|e| {
+            upload_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to queue layer processing",
+                e,
+            )
+        })?;
+
+    // The assembled file now belongs to the job, the chunks are no longer needed
+    if let Err(e) = fs::remove_dir_all(session.dir()).await {
+        tracing::error!("Failed to clean up upload {}: {}", session.id, e);
+    }
+
+    tracing::info!("Upload {} queued as job {}", session.id, job.id);
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "status": job.status,
+            "job_id": job.id
+        })),
+    ))
+}
+
+// Remove expired uploads every hour, both sessions and the files of chunked
+// uploads to /upload_layer_v2 that were never finished
+pub fn start_upload_cleanup() {
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            match remove_expired_uploads(Path::new("uploads"), upload_ttl()).await {
+                Ok(0) => {}
+                Ok(removed) => tracing::info!("Removed {} expired uploads", removed),
+                Err(e) => tracing::error!("Failed to remove expired uploads: {}", e),
+            }
+        }
+    });
+}
+
+// Remove sessions created, and loose upload files last written, more than ttl
+// seconds ago. Queued jobs keep their files under jobs/ and are left alone.
+async fn remove_expired_uploads(root: &Path, ttl: u64) -> Result<usize> {
+    let mut removed = 0;
+
+    let sessions_dir = root.join("sessions");
+    if fs::try_exists(&sessions_dir).await? {
+        let mut entries = fs::read_dir(&sessions_dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let expired = match fs::read(entry.path().join("upload.json")).await {
+                Ok(data) => serde_json::from_slice::<UploadSession>(&data)
+                    .map(|session| session.is_expired(ttl))
+                    .unwrap_or(true),
+                // A session is written as soon as its directory is created
+                Err(_) => is_older_than(&entry.path(), ttl).await?,
+            };
+            if expired {
+                fs::remove_dir_all(entry.path()).await?;
+                removed += 1;
+            }
+        }
+    }
+
+    let mut entries = fs::read_dir(root).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.file_type().await?.is_file() && is_older_than(&entry.path(), ttl).await? {
+            fs::remove_file(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+async fn is_older_than(path: &Path, ttl: u64) -> Result<bool> {
+    let modified = fs::metadata(path).await?.modified()?;
+    Ok(modified.elapsed().unwrap_or_default().as_secs() > ttl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session(id: &str, created_at: u64) -> UploadSession {
+        UploadSession {
+            id: id.to_string(),
+            created_by: "user".to_string(),
+            file_name: "roads.gpkg".to_string(),
+            total_chunks: 1,
+            layer_info: serde_json::from_value(json!({
+                "workspace_id": "ws",
+                "name": "roads"
+            }))
+            .unwrap(),
+            created_at,
+            sidecars: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn expired_sessions_are_removed() {
+        let root = std::env::temp_dir().join(format!("gw-uploads-{}", Uuid::new_v4()));
+        let now = get_unix_timestamp();
+        for (id, created_at) in [("old", now - 7200), ("new", now)] {
+            let dir = root.join("sessions").join(id);
+            fs::create_dir_all(&dir).await.unwrap();
+            let record = serde_json::to_vec(&session(id, created_at)).unwrap();
+            fs::write(dir.join("upload.json"), record).await.unwrap();
+        }
+        fs::write(root.join("ws_roads.gpkg"), b"partial")
+            .await
+            .unwrap();
+
+        assert_eq!(remove_expired_uploads(&root, 3600).await.unwrap(), 1);
+        assert!(!root.join("sessions").join("old").exists());
+        assert!(root.join("sessions").join("new").exists());
+        assert!(root.join("ws_roads.gpkg").exists());
+
+        fs::remove_dir_all(&root).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sidecars_uploaded_together_keep_their_own_contents() {
+        let dir = std::env::temp_dir().join(format!("gw-sidecars-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let dbf = dir.join("roads.dbf");
+        let shx = dir.join("roads.shx");
+
+        let (a, b) = tokio::join!(
+            write_sidecar(&dbf, &[1u8; 100_000]),
+            write_sidecar(&shx, &[2u8; 100_000])
+        );
+        a.unwrap();
+        b.unwrap();
+
+        assert_eq!(fs::read(&dbf).await.unwrap(), vec![1u8; 100_000]);
+        assert_eq!(fs::read(&shx).await.unwrap(), vec![2u8; 100_000]);
+        let mut entries = fs::read_dir(&dir).await.unwrap();
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        names.sort();
+        assert_eq!(names, ["roads.dbf", "roads.shx"]);
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -2,7 +2,9 @@ mod app_state;
 mod auth;
 mod connector;
 mod data;
+mod job;
 mod layer;
+mod ogc;
 mod project;
 mod server;
 mod session;
@@ -12,8 +14,10 @@ mod workspace;
 
 use crate::app_state::AppState;
 use crate::connector::*;
-use crate::data::Dynamodb;
+use crate::data::{Dynamodb, InMemoryDatabase, PostgresAppStore};
+use crate::job::*;
 use crate::layer::*;
+use crate::ogc::*;
 use crate::project::*;
 use crate::session::*;
 use crate::user::*;
@@ -21,7 +25,7 @@ use crate::workspace::*;
 
 use anyhow::Result;
 use dotenvy::dotenv;
-use tracing::info;
+use tracing::{info, warn};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -30,7 +34,19 @@ async fn main() -> Result<()> {
     // Load environment variables from .env file
     dotenv().ok();
 
-    let app_db = Dynamodb::new().await.unwrap();
+    // Install DuckDB extensions up front so imports and exports only load them
+    if let Err(e) = tokio::task::spawn_blocking(install_duckdb_extensions).await? {
+        warn!("Failed to install DuckDB extensions: {}", e);
+    }
+
+    // Select the app database backend, defaulting to DynamoDB
+    let app_db_backend = std::env::var("GW_APP_DB").unwrap_or_else(|_| "dynamodb".to_string());
+    let app_db = match app_db_backend.to_lowercase().as_str() {
+        "dynamodb" => Dynamodb::new().await.unwrap(),
+        "postgres" => PostgresAppStore::new().await.unwrap(),
+        "memory" => InMemoryDatabase::new().await.unwrap(),
+        other => return Err(anyhow::anyhow!("Unsupported GW_APP_DB: {}", other)),
+    };
 
     // Create GeospatialConnections
     let geo_connections = GeoConnections::new();
@@ -39,6 +55,9 @@ async fn main() -> Result<()> {
     let app_state = AppState {
         app_data: app_db,
         geo_connections,
+        tile_auth_cache: TileAuthCache::from_env(),
+        tile_cache: TileCache::from_env(),
+        ingest_queue: IngestQueue::new(),
     };
 
     // Check for primary connection info in app_data and add to geo_connections if found
@@ -46,15 +65,21 @@ async fn main() -> Result<()> {
     match geoconnection_record_primary {
         Ok(geoconnection_primary) => {
             info!("Primary connection found");
-            let postgis_connector = PostgisConnector::new(geoconnection_primary.config).unwrap();
+            let connector = geoconnection_primary.config.create_connector()?;
             app_state
                 .geo_connections
-                .add_connection("primary".to_string(), postgis_connector)
+                .add_connection("primary".to_string(), connector)
                 .await;
         }
         Err(_) => return Err(anyhow::anyhow!("Primary connection not found")),
     }
 
+    // Start loading uploaded layers, resuming any jobs left from the last run
+    app_state.ingest_queue.start(app_state.clone()).await?;
+
+    // Remove uploads that were started but never finished
+    start_upload_cleanup();
+
     // Run app
     let app = server::create_app(app_state);
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3001").await?;
@@ -0,0 +1,264 @@
+use crate::app_state::AppState;
+use crate::auth::AuthUser;
+use crate::connector::{
+    authorize_source_access, source_fields, FeatureQuery, SourceNotFoundError, UnknownFieldsError,
+    UnsupportedCrsError,
+};
+use crate::utils::api_base_url;
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// OGC API - Features Part 1 over the sources of a workspace connection.
+// Geometries are served in CRS84, the only CRS required by Part 1.
+
+const CRS84: &str = "http://www.opengis.net/def/crs/OGC/1.3/CRS84";
+const OGC_DEFAULT_LIMIT: &str = "10";
+
+const CONFORMANCE_CLASSES: &[&str] = &[
+    "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/core",
+    "http://www.opengis.net/spec/ogcapi-features-1/1.0/conf/geojson",
+];
+
+// Query parameters defined by the standard that are not attribute filters
+const IGNORED_PARAMS: &[&str] = &["f", "datetime"];
+
+fn service_url(headers: &HeaderMap, workspace_id: &str, connection_id: &str) -> String {
+    format!(
+        "{}/ogc/workspaces/{}/connections/{}",
+        api_base_url(headers),
+        workspace_id,
+        connection_id
+    )
+}
+
+fn link(href: String, rel: &str, media_type: &str, title: &str) -> Value {
+    json!({
+        "href": href,
+        "rel": rel,
+        "type": media_type,
+        "title": title,
+    })
+}
+
+fn collection(base: &str, source_name: &str, bounds: Option<[f64; 4]>) -> Value {
+    let collection_url = format!("{}/collections/{}", base, source_name);
+    let mut collection = json!({
+        "id": source_name,
+        "title": source_name,
+        "itemType": "feature",
+        "crs": [CRS84],
+        "links": [
+            link(collection_url.clone(), "self", "application/json", "This collection"),
+            link(
+                format!("{}/items", collection_url),
+                "items",
+                "application/geo+json",
+                "Features in this collection",
+            ),
+        ],
+    });
+    if let Some(bounds) = bounds {
+        collection["extent"] = json!({
+            "spatial": { "bbox": [bounds], "crs": CRS84 },
+        });
+    }
+    collection
+}
+
+pub async fn ogc_landing_page(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+
+    let base = service_url(&headers, &workspace_id, &connection_id);
+    Ok::<_, (StatusCode, String)>(Json(json!({
+        "title": format!("Gridwalk {} / {}", workspace_id, connection_id),
+        "description": "Features from a gridwalk workspace connection",
+        "links": [
+            link(base.clone(), "self", "application/json", "This document"),
+            link(
+                format!("{}/conformance", base),
+                "conformance",
+                "application/json",
+                "Conformance classes",
+            ),
+            link(
+                format!("{}/collections", base),
+                "data",
+                "application/json",
+                "Collections",
+            ),
+        ],
+    })))
+}
+
+pub async fn ogc_conformance(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((workspace_id, connection_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+    Ok::<_, (StatusCode, String)>(Json(json!({ "conformsTo": CONFORMANCE_CLASSES })))
+}
+
+pub async fn ogc_collections(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let connection =
+        authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+
+    let sources = connection.list_sources(&workspace_id).await.map_err(|e| {
+        tracing::error!("Error listing sources: {:?}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Failed to list sources".to_string(),
+        )
+    })?;
+
+    // Extents are left to the single collection endpoint so listing stays cheap
+    let base = service_url(&headers, &workspace_id, &connection_id);
+    let collections: Vec<Value> = sources
+        .iter()
+        .map(|source_name| collection(&base, source_name, None))
+        .collect();
+
+    Ok::<_, (StatusCode, String)>(Json(json!({
+        "links": [link(
+            format!("{}/collections", base),
+            "self",
+            "application/json",
+            "Collections",
+        )],
+        "collections": collections,
+    })))
+}
+
+pub async fn ogc_collection(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id, collection_id)): Path<(String, String, String)>,
+) -> impl IntoResponse {
+    let connection =
+        authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+
+    let bounds = connection
+        .get_bounds(&workspace_id, &collection_id)
+        .await
+        .map_err(|e| {
+            if e.is::<SourceNotFoundError>() {
+                return (StatusCode::NOT_FOUND, "collection not found".to_string());
+            }
+            tracing::error!("Error getting bounds: {:?}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to get collection extent".to_string(),
+            )
+        })?;
+
+    let base = service_url(&headers, &workspace_id, &connection_id);
+    Ok::<_, (StatusCode, String)>(Json(collection(&base, &collection_id, bounds)))
+}
+
+pub async fn ogc_items(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id, collection_id)): Path<(String, String, String)>,
+    Query(mut params): Query<HashMap<String, String>>,
+) -> impl IntoResponse {
+    let connection =
+        authorize_source_access(&state, &auth_user, &workspace_id, &connection_id).await?;
+
+    for param in IGNORED_PARAMS {
+        params.remove(*param);
+    }
+    params
+        .entry("limit".to_string())
+        .or_insert_with(|| OGC_DEFAULT_LIMIT.to_string());
+    let fields = source_fields(&connection, &workspace_id, &collection_id).await?;
+    let query =
+        FeatureQuery::from_params(&params, &fields).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let page = match connection
+        .query_features(&workspace_id, &collection_id, &query)
+        .await
+    {
+        Ok(page) => page,
+        Err(e) if e.is::<UnknownFieldsError>() || e.is::<UnsupportedCrsError>() => {
+            return Err((StatusCode::BAD_REQUEST, e.to_string()));
+        }
+        Err(e) => {
+            tracing::error!("Error querying features: {:?}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Failed to query features".to_string(),
+            ));
+        }
+    };
+
+    let items_url = format!(
+        "{}/collections/{}/items",
+        service_url(&headers, &workspace_id, &connection_id),
+        collection_id
+    );
+    let page_url = |offset: i64| {
+        let mut page_params: Vec<(&String, String)> = params
+            .iter()
+            .filter(|(key, _)| key.as_str() != "offset")
+            .map(|(key, value)| (key, value.clone()))
+            .collect();
+        let offset_key = "offset".to_string();
+        page_params.push((&offset_key, offset.to_string()));
+        page_params.sort();
+        let query_string = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(page_params)
+            .finish();
+        format!("{}?{}", items_url, query_string)
+    };
+
+    let number_returned = page.features.len() as i64;
+    let mut links = vec![link(
+        page_url(query.offset),
+        "self",
+        "application/geo+json",
+        "This page",
+    )];
+    if query.offset + number_returned < page.number_matched {
+        links.push(link(
+            page_url(query.offset + number_returned),
+            "next",
+            "application/geo+json",
+            "Next page",
+        ));
+    }
+    if query.offset > 0 {
+        links.push(link(
+            page_url((query.offset - query.limit).max(0)),
+            "prev",
+            "application/geo+json",
+            "Previous page",
+        ));
+    }
+
+    let mut collection = page.into_feature_collection();
+    collection["links"] = json!(links);
+
+    Ok((
+        [(header::CONTENT_TYPE, "application/geo+json")],
+        Json(collection),
+    ))
+}
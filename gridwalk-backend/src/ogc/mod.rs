@@ -0,0 +1,3 @@
+mod features;
+
+pub use features::*;
@@ -1,8 +1,11 @@
 use crate::app_state::AppState;
 use crate::auth::AuthUser;
-use crate::{CreateProject, Project, User, Workspace, WorkspaceRole};
+use crate::{
+    get_workspace_membership, CreateProject, Project, ProjectDocument, ProjectNotFoundError,
+    ProjectRevisionConflict, User, Workspace, WorkspaceRole,
+};
 use axum::{
-    extract::{Extension, Query, State},
+    extract::{Extension, Path, Query, State},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
@@ -194,6 +197,8 @@ pub async fn delete_project(
         name: String::new(),        // These fields aren't needed for deletion
         uploaded_by: String::new(), // since we only use workspace_id and id
         created_at: 0,
+        revision: 0,
+        document: ProjectDocument::default(),
     };
 
     // Delete project record from database
@@ -219,3 +224,100 @@ pub async fn delete_project(
         })),
     ))
 }
+
+async fn load_project(
+    state: &Arc<AppState>,
+    workspace_id: &str,
+    project_id: &str,
+) -> Result<Project, (StatusCode, Json<serde_json::Value>)> {
+    Project::from_id(&state.app_data, workspace_id, project_id)
+        .await
+        .map_err(|e| {
+            if e.is::<ProjectNotFoundError>() {
+                let error = json!({
+                    "error": "Project not found",
+                    "details": e.to_string()
+                });
+                (StatusCode::NOT_FOUND, Json(error))
+            } else {
+                let error = json!({
+                    "error": "Failed to load project",
+                    "details": e.to_string()
+                });
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(error))
+            }
+        })
+}
+
+pub async fn get_project(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((workspace_id, project_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    get_workspace_membership(&state, &auth_user, &workspace_id).await?;
+    let project = load_project(&state, &workspace_id, &project_id).await?;
+
+    Ok((StatusCode::OK, Json(project)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateProjectRequest {
+    // The revision the client loaded, the save is rejected if it is stale
+    revision: u64,
+    document: ProjectDocument,
+}
+
+pub async fn update_project(
+    State(state): State<Arc<AppState>>,
+    Extension(auth_user): Extension<AuthUser>,
+    Path((workspace_id, project_id)): Path<(String, String)>,
+    Json(req): Json<UpdateProjectRequest>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    let (_workspace, member) = get_workspace_membership(&state, &auth_user, &workspace_id).await?;
+
+    // Verify write permissions
+    if member.role == WorkspaceRole::Read {
+        let error = json!({
+            "error": "Read-only access",
+            "details": "User does not have write permission"
+        });
+        return Err((StatusCode::FORBIDDEN, Json(error)));
+    }
+
+    let mut project = load_project(&state, &workspace_id, &project_id).await?;
+
+    if let Err(e) = req.document.validate() {
+        let error = json!({
+            "error": "Invalid project document",
+            "details": e.to_string()
+        });
+        return Err((StatusCode::BAD_REQUEST, Json(error)));
+    }
+
+    match project
+        .update_document(&state.app_data, req.document, req.revision)
+        .await
+    {
+        Ok(_) => Ok((StatusCode::OK, Json(project))),
+        Err(e) if e.downcast_ref::<ProjectRevisionConflict>().is_some() => {
+            // Report the stored revision so the client can reload and merge
+            let current_revision = Project::from_id(&state.app_data, &workspace_id, &project_id)
+                .await
+                .map(|p| p.revision)
+                .ok();
+            let error = json!({
+                "error": "Project has been modified by another user",
+                "details": e.to_string(),
+                "current_revision": current_revision
+            });
+            Err((StatusCode::CONFLICT, Json(error)))
+        }
+        Err(e) => {
+            let error = json!({
+                "error": "Failed to update project",
+                "details": e.to_string()
+            });
+            Err((StatusCode::INTERNAL_SERVER_ERROR, Json(error)))
+        }
+    }
+}
@@ -1,9 +1,11 @@
 mod endpoints;
 mod os_token;
 mod project;
+mod tile_auth;
 mod tiles;
 
 pub use endpoints::*;
 pub use os_token::*;
 pub use project::*;
+pub use tile_auth::*;
 pub use tiles::*;
@@ -3,9 +3,13 @@ use crate::utils::get_unix_timestamp;
 use crate::{User, Workspace, WorkspaceRole};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::sync::Arc;
 use uuid::Uuid;
 
+// Bump when the shape of ProjectDocument changes
+pub const PROJECT_DOCUMENT_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CreateProject {
     pub workspace_id: String,
@@ -19,8 +23,113 @@ pub struct Project {
     pub name: String,
     pub uploaded_by: String,
     pub created_at: u64,
+    // Incremented on every document save, used to reject stale writes
+    pub revision: u64,
+    pub document: ProjectDocument,
+}
+
+// The saved map state of a project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectDocument {
+    pub version: u32,
+    // Drawn bottom to top
+    pub layers: Vec<ProjectLayer>,
+    pub view: MapView,
+    pub basemap: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectLayer {
+    pub connection_id: String,
+    pub source_name: String,
+    pub style: LayerStyle,
+    pub visible: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayerStyle {
+    pub color: String,
+    pub opacity: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MapView {
+    // [longitude, latitude]
+    pub center: [f64; 2],
+    pub zoom: f64,
+}
+
+impl Default for ProjectDocument {
+    fn default() -> Self {
+        ProjectDocument {
+            version: PROJECT_DOCUMENT_VERSION,
+            layers: vec![],
+            view: MapView {
+                center: [-0.1278, 51.5074],
+                zoom: 11.0,
+            },
+            basemap: "light".to_string(),
+        }
+    }
+}
+
+impl ProjectDocument {
+    pub fn validate(&self) -> Result<()> {
+        if self.version != PROJECT_DOCUMENT_VERSION {
+            return Err(anyhow!(
+                "Unsupported project document version: {}",
+                self.version
+            ));
+        }
+        let [lng, lat] = self.view.center;
+        if !(-180.0..=180.0).contains(&lng) || !(-90.0..=90.0).contains(&lat) {
+            return Err(anyhow!("Map centre is out of range"));
+        }
+        if !(0.0..=24.0).contains(&self.view.zoom) {
+            return Err(anyhow!("Map zoom is out of range"));
+        }
+        if let Some(layer) = self
+            .layers
+            .iter()
+            .find(|l| !(0.0..=1.0).contains(&l.style.opacity))
+        {
+            return Err(anyhow!(
+                "Layer opacity is out of range: {}",
+                layer.source_name
+            ));
+        }
+        Ok(())
+    }
+}
+
+// Returned by the database when a save was based on an out of date revision
+#[derive(Debug)]
+pub struct ProjectRevisionConflict;
+
+impl fmt::Display for ProjectRevisionConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "project has been modified since it was loaded")
+    }
 }
 
+impl std::error::Error for ProjectRevisionConflict {}
+
+// Returned by the database when there is no project with the requested id
+#[derive(Debug)]
+pub struct ProjectNotFoundError;
+
+impl fmt::Display for ProjectNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "project not found")
+    }
+}
+
+impl std::error::Error for ProjectNotFoundError {}
+
 impl Project {
     pub fn from_req(req: CreateProject, user: &User) -> Self {
         Project {
@@ -29,6 +138,8 @@ impl Project {
             name: req.name,
             uploaded_by: user.id.clone(),
             created_at: get_unix_timestamp(),
+            revision: 0,
+            document: ProjectDocument::default(),
         }
     }
     pub async fn check_permissions(
@@ -57,6 +168,30 @@ impl Project {
         Ok(())
     }
 
+    pub async fn from_id(
+        database: &Arc<dyn Database>,
+        workspace_id: &str,
+        project_id: &str,
+    ) -> Result<Self> {
+        database.get_project(workspace_id, project_id).await
+    }
+
+    // Save a new document, failing with ProjectRevisionConflict if the stored
+    // revision no longer matches the one the caller loaded. The caller validates
+    // the document first.
+    pub async fn update_document(
+        &mut self,
+        database: &Arc<dyn Database>,
+        document: ProjectDocument,
+        expected_revision: u64,
+    ) -> Result<()> {
+        self.document = document;
+        self.revision = expected_revision + 1;
+        database
+            .update_project_document(self, expected_revision)
+            .await
+    }
+
     pub async fn get_workspace_projects(
         database: &Arc<dyn Database>,
         workspace: &Workspace,
@@ -65,3 +200,18 @@ impl Project {
         database.get_projects(&workspace.id).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InMemoryDatabase;
+
+    #[tokio::test]
+    async fn missing_projects_are_not_found_errors() {
+        let database = InMemoryDatabase::new().await.unwrap();
+        let error = Project::from_id(&database, "workspace", "missing")
+            .await
+            .unwrap_err();
+        assert!(error.is::<ProjectNotFoundError>());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::ConnectionAccessConfig;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::RwLock;
+
+// Entries beyond this trigger a sweep of expired entries on insert
+const SWEEP_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TileAuthKey {
+    pub session_id: String,
+    pub workspace_id: String,
+    pub connection_id: String,
+}
+
+// The resolved result of the session, user, workspace and connection lookups
+#[derive(Debug, Clone)]
+pub struct TileAccess {
+    pub user_id: String,
+    pub is_member: bool,
+    pub access_config: Option<ConnectionAccessConfig>,
+}
+
+#[derive(Clone)]
+pub struct TileAuthCache {
+    entries: Arc<RwLock<HashMap<TileAuthKey, (TileAccess, Instant)>>>,
+    ttl: Duration,
+}
+
+impl TileAuthCache {
+    pub fn new(ttl: Duration) -> Self {
+        TileAuthCache {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+            ttl,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        let ttl = std::env::var("GW_TILE_AUTH_CACHE_TTL")
+            .unwrap_or_else(|_| "60".to_string())
+            .parse::<u64>()
+            .expect("GW_TILE_AUTH_CACHE_TTL must be a number of seconds");
+        Self::new(Duration::from_secs(ttl))
+    }
+
+    pub async fn get(&self, key: &TileAuthKey) -> Option<TileAccess> {
+        let entries = self.entries.read().await;
+        entries
+            .get(key)
+            .filter(|(_, inserted_at)| inserted_at.elapsed() < self.ttl)
+            .map(|(access, _)| access.clone())
+    }
+
+    pub async fn insert(&self, key: TileAuthKey, access: TileAccess) {
+        let mut entries = self.entries.write().await;
+        if entries.len() >= SWEEP_THRESHOLD {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, inserted_at)| inserted_at.elapsed() < ttl);
+        }
+        entries.insert(key, (access, Instant::now()));
+    }
+
+    pub async fn invalidate_session(&self, session_id: &str) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|key, _| key.session_id != session_id);
+    }
+
+    pub async fn invalidate_member(&self, workspace_id: &str, user_id: &str) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|key, (access, _)| {
+            key.workspace_id != workspace_id || access.user_id != user_id
+        });
+    }
+
+    pub async fn invalidate_workspace(&self, workspace_id: &str) {
+        let mut entries = self.entries.write().await;
+        entries.retain(|key, _| key.workspace_id != workspace_id);
+    }
+}
@@ -1,101 +1,274 @@
 use crate::app_state::AppState;
-use crate::connector::ConnectionAccess;
-use crate::{Session, User, Workspace};
+use crate::connector::{
+    ConnectionAccess, GeoConnector, GeometryType, SourceNotFoundError, TileEncoding, TileKey,
+    UnknownFieldsError, VectorLayer,
+};
+use crate::utils::api_base_url;
+use crate::{Session, TileAccess, TileAuthKey, User, Workspace};
 use axum::{
-    extract::{Path, State},
-    http::{header, StatusCode},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
     response::{IntoResponse, Response},
+    Json,
 };
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use tower_cookies::Cookies;
 
-// TODO: Create cache for tile source/session to prevent repeated requests to DB
+// Characters that must be escaped in a URL path segment
+const PATH_SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
 
-pub async fn tiles(
-    State(state): State<Arc<AppState>>,
-    cookies: Cookies,
-    Path((workspace_id, connection_id, source_name, z, x, y)): Path<(
-        String,
-        String,
-        String,
-        u32,
-        u32,
-        u32,
-    )>,
-) -> impl IntoResponse {
-    let token = cookies.get("sid").unwrap().value().to_string();
+#[derive(Debug, Deserialize)]
+pub struct TileQuery {
+    // Comma separated attribute columns to include in the tile
+    pub fields: Option<String>,
+}
+
+impl TileQuery {
+    fn fields(&self) -> Option<Vec<String>> {
+        self.fields.as_ref().map(|fields| {
+            let mut fields: Vec<String> = fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(String::from)
+                .collect();
+            fields.sort();
+            fields.dedup();
+            fields
+        })
+    }
+}
+
+// Resolve the session's access to a workspace connection, using the tile auth
+// cache so map panning does not repeat the app database lookups for every tile
+async fn resolve_tile_access(
+    state: &Arc<AppState>,
+    session_id: &str,
+    workspace_id: &str,
+    connection_id: &str,
+) -> Result<TileAccess, Response> {
+    let key = TileAuthKey {
+        session_id: session_id.to_string(),
+        workspace_id: workspace_id.to_string(),
+        connection_id: connection_id.to_string(),
+    };
+    if let Some(access) = state.tile_auth_cache.get(&key).await {
+        return Ok(access);
+    }
 
-    let session = match Session::from_id(&state.app_data, &token).await {
+    let session = match Session::from_id(&state.app_data, session_id).await {
         Ok(session) => session,
-        Err(_) => return (StatusCode::UNAUTHORIZED, "").into_response(),
+        Err(_) => return Err((StatusCode::UNAUTHORIZED, "").into_response()),
     };
 
     // Do not allow unauthenticated users for now
-    if session.user_id.is_none() {
-        return (StatusCode::UNAUTHORIZED, "").into_response();
-    }
+    let user_id = match session.user_id {
+        Some(user_id) => user_id,
+        None => return Err((StatusCode::UNAUTHORIZED, "").into_response()),
+    };
 
     // TODO: Get user and workspace in a single transaction
     // Get the user
-    let user = match User::from_id(&state.app_data, &session.user_id.unwrap()).await {
+    let user = match User::from_id(&state.app_data, &user_id).await {
         Ok(user) => user,
-        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "").into_response(),
+        Err(_) => return Err((StatusCode::INTERNAL_SERVER_ERROR, "").into_response()),
     };
 
     // Get the workspace
-    let workspace = match Workspace::from_id(&state.app_data, &workspace_id).await {
+    let workspace = match Workspace::from_id(&state.app_data, workspace_id).await {
         Ok(ws) => ws,
-        Err(_) => return "workspace not found".into_response(),
+        Err(_) => return Err((StatusCode::NOT_FOUND, "workspace not found").into_response()),
     };
 
-    // TODO: Optimise this to remove need for workspace query
     // Check if user is a member of the workspace
-    let _workspace_member = workspace
-        .get_member(&state.app_data, &user)
-        .await
-        .map_err(|_| (StatusCode::FORBIDDEN, ""));
+    let is_member = workspace.get_member(&state.app_data, &user).await.is_ok();
 
-    // TODO: Add to same transaction as above
     // Check if workspace has access to the connection namespace
-    let _connection_access = ConnectionAccess::get(&state.app_data, &workspace, &connection_id)
+    let access_config = ConnectionAccess::get(&state.app_data, &workspace, connection_id)
         .await
-        .map_err(|_| (StatusCode::NOT_FOUND, ""));
+        .ok()
+        .map(|ca| ca.access_config);
+
+    // A failed lookup may be a passing database error rather than a missing
+    // grant, so only access that was found is cached
+    let found = is_member && access_config.is_some();
+    let access = TileAccess {
+        user_id: user.id,
+        is_member,
+        access_config,
+    };
+    if found {
+        state.tile_auth_cache.insert(key, access.clone()).await;
+    }
+    Ok(access)
+}
+
+// Check the session may read from the connection namespace and return its connector.
+// Tiles are requested from the workspace namespace, so a ReadOnly(ns) grant only
+// covers sources in ns.
+async fn authorize_source_request(
+    state: &Arc<AppState>,
+    cookies: &Cookies,
+    workspace_id: &str,
+    connection_id: &str,
+) -> Result<Arc<dyn GeoConnector>, Response> {
+    let token = match cookies.get("sid") {
+        Some(cookie) => cookie.value().to_string(),
+        None => return Err((StatusCode::UNAUTHORIZED, "").into_response()),
+    };
 
-    let geoconnector = state
+    let access = resolve_tile_access(state, &token, workspace_id, connection_id).await?;
+    if !access.is_member {
+        return Err((StatusCode::FORBIDDEN, "unauthorized").into_response());
+    }
+
+    let access_config = match access.access_config {
+        Some(access_config) => access_config,
+        None => return Err((StatusCode::NOT_FOUND, "connection not found").into_response()),
+    };
+    if !access_config.allows_namespace(workspace_id) {
+        return Err((StatusCode::FORBIDDEN, "unauthorized").into_response());
+    }
+
+    state
         .geo_connections
-        .get_connection(&connection_id)
+        .get_connection(&state.app_data, connection_id)
         .await
-        .unwrap();
+        .map_err(|_| (StatusCode::NOT_FOUND, "connection not found").into_response())
+}
 
-    let tile = geoconnector
-        .get_tile(&workspace_id, &source_name, z, x, y)
-        .await
-        .unwrap();
+// Map a connector error to 404 if the source does not exist, otherwise 500
+fn source_error_response(error: &anyhow::Error, message: &'static str) -> Response {
+    if error.is::<SourceNotFoundError>() {
+        (StatusCode::NOT_FOUND, "source not found").into_response()
+    } else {
+        (StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+    }
+}
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, "application/x-protobuf")
+pub async fn tiles(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id, source_name, z, x, y)): Path<(
+        String,
+        String,
+        String,
+        u32,
+        u32,
+        u32,
+    )>,
+    Query(query): Query<TileQuery>,
+) -> impl IntoResponse {
+    let geoconnector =
+        match authorize_source_request(&state, &cookies, &workspace_id, &connection_id).await {
+            Ok(geoconnector) => geoconnector,
+            Err(response) => return response,
+        };
+
+    let key = TileKey {
+        connection_id,
+        namespace: workspace_id,
+        source_name,
+        z,
+        x,
+        y,
+        fields: query.fields(),
+    };
+    let tile = match state.tile_cache.get_tile(&geoconnector, &key).await {
+        Ok(tile) => tile,
+        Err(e) if e.is::<UnknownFieldsError>() => {
+            return (StatusCode::BAD_REQUEST, e.to_string()).into_response();
+        }
+        Err(e) => {
+            tracing::error!("Failed to get tile: {}", e);
+            return source_error_response(&e, "Failed to get tile");
+        }
+    };
+
+    let encoding = TileEncoding::from_accept_encoding(
+        headers
+            .get(header::ACCEPT_ENCODING)
+            .and_then(|v| v.to_str().ok()),
+    );
+    let etag = tile.etag_for(encoding);
+
+    // Tiles sit behind a session, so only the browser may cache them and it must
+    // revalidate so a re-uploaded layer is picked up
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| tile.matches_etag(v, encoding));
+
+    let response = Response::builder()
+        .header(header::ETAG, etag)
+        .header(header::CACHE_CONTROL, "private, no-cache")
+        .header(header::VARY, "Accept-Encoding")
         .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "http://localhost:3000")
         .header(
             header::ACCESS_CONTROL_ALLOW_METHODS,
             "GET, POST, PUT, DELETE, OPTIONS",
         )
-        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*")
-        //.header(header::CONTENT_ENCODING, "gzip")
-        .body(axum::body::Body::from(tile))
+        .header(header::ACCESS_CONTROL_ALLOW_HEADERS, "*");
+
+    if not_modified {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(axum::body::Body::empty())
+            .unwrap()
+            .into_response();
+    }
+
+    // Compression is CPU bound, so keep it off the async workers. The result is
+    // stored on the cached tile and reused by later requests.
+    let data = match tokio::task::spawn_blocking(move || tile.encoded(encoding)).await {
+        Ok(Ok(data)) => data,
+        Ok(Err(e)) => {
+            tracing::error!("Failed to compress tile: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get tile").into_response();
+        }
+        Err(e) => {
+            tracing::error!("Tile compression task failed: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get tile").into_response();
+        }
+    };
+
+    let mut response = response
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-protobuf");
+    if let Some(content_encoding) = encoding.content_encoding() {
+        response = response.header(header::CONTENT_ENCODING, content_encoding);
+    }
+
+    response
+        .body(axum::body::Body::from(data))
         .unwrap()
         .into_response()
 }
 
 pub async fn get_geometry_type(
     State(state): State<Arc<AppState>>,
+    cookies: Cookies,
     Path((workspace_id, connection_id, source_name)): Path<(String, String, String)>,
 ) -> impl IntoResponse {
-    let geoconnector = state
-        .geo_connections
-        .get_connection(&connection_id)
-        .await
-        .unwrap();
+    let geoconnector =
+        match authorize_source_request(&state, &cookies, &workspace_id, &connection_id).await {
+            Ok(geoconnector) => geoconnector,
+            Err(response) => return response,
+        };
 
     // Get the geometry type and convert it to a string
     match geoconnector
@@ -117,10 +290,136 @@ pub async fn get_geometry_type(
                 .unwrap()
                 .into_response()
         }
-        Err(_) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            "Failed to get geometry type",
-        )
-            .into_response(),
+        Err(e) => source_error_response(&e, "Failed to get geometry type"),
     }
 }
+
+const TILEJSON_MIN_ZOOM: u32 = 0;
+const TILEJSON_MAX_ZOOM: u32 = 22;
+
+// TileJSON 3.0.0 document, with the source geometry type as an extension so
+// clients can pick a style without calling /geometry
+#[derive(Debug, Serialize)]
+pub struct TileJson {
+    pub tilejson: &'static str,
+    pub name: String,
+    pub scheme: &'static str,
+    pub tiles: Vec<String>,
+    pub minzoom: u32,
+    pub maxzoom: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<[f64; 4]>,
+    pub vector_layers: Vec<VectorLayer>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub geometry_type: Option<GeometryType>,
+}
+
+pub async fn tilejson(
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+    headers: HeaderMap,
+    Path((workspace_id, connection_id, source_name)): Path<(String, String, String)>,
+    Query(query): Query<TileQuery>,
+) -> impl IntoResponse {
+    let geoconnector =
+        match authorize_source_request(&state, &cookies, &workspace_id, &connection_id).await {
+            Ok(geoconnector) => geoconnector,
+            Err(response) => return response,
+        };
+
+    let fields = match geoconnector.get_fields(&workspace_id, &source_name).await {
+        Ok(fields) => fields,
+        Err(e) => return source_error_response(&e, "Failed to get fields"),
+    };
+
+    // Restrict the layer fields to the same selection the tile URL will request
+    let selected = query.fields();
+    if let Some(selected) = &selected {
+        let unknown: Vec<String> = selected
+            .iter()
+            .filter(|name| !fields.iter().any(|f| &f.name == *name))
+            .cloned()
+            .collect();
+        if !unknown.is_empty() {
+            return (
+                StatusCode::BAD_REQUEST,
+                UnknownFieldsError { fields: unknown }.to_string(),
+            )
+                .into_response();
+        }
+    }
+    let is_selected = |name: &String| selected.as_ref().is_none_or(|s| s.contains(name));
+    let layer_fields = fields
+        .into_iter()
+        .filter(|f| is_selected(&f.name))
+        .map(|f| (f.name, f.field_type))
+        .collect();
+
+    // Pre-rendered tiles carry the layers and zoom range they were rendered with
+    let tile_set = match geoconnector.get_tile_set(&workspace_id, &source_name).await {
+        Ok(tile_set) => tile_set.unwrap_or_default(),
+        Err(e) => return source_error_response(&e, "Failed to get tile set"),
+    };
+    let minzoom = tile_set.minzoom.unwrap_or(TILEJSON_MIN_ZOOM);
+    let maxzoom = tile_set.maxzoom.unwrap_or(TILEJSON_MAX_ZOOM);
+    let vector_layers = if tile_set.vector_layers.is_empty() {
+        vec![VectorLayer {
+            id: source_name.clone(),
+            fields: layer_fields,
+            minzoom,
+            maxzoom,
+        }]
+    } else {
+        tile_set
+            .vector_layers
+            .into_iter()
+            .map(|mut layer| {
+                layer.fields.retain(|name, _| is_selected(name));
+                layer
+            })
+            .collect()
+    };
+
+    let bounds = match geoconnector.get_bounds(&workspace_id, &source_name).await {
+        Ok(bounds) => bounds,
+        Err(e) => {
+            tracing::error!("Failed to get bounds: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Failed to get bounds").into_response();
+        }
+    };
+
+    // An empty table has no geometry type, which is not an error for TileJSON
+    let geometry_type = geoconnector
+        .get_geometry_type(&workspace_id, &source_name)
+        .await
+        .ok();
+
+    // Source names can hold characters that are not valid in a URL path segment
+    let mut tile_url = format!(
+        "{}/workspaces/{}/connections/{}/sources/{}/tiles/{{z}}/{{x}}/{{y}}",
+        api_base_url(&headers),
+        utf8_percent_encode(&workspace_id, PATH_SEGMENT),
+        utf8_percent_encode(&connection_id, PATH_SEGMENT),
+        utf8_percent_encode(&source_name, PATH_SEGMENT)
+    );
+    if let Some(selected) = &selected {
+        let query = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("fields", &selected.join(","))
+            .finish();
+        tile_url.push('?');
+        tile_url.push_str(&query);
+    }
+
+    Json(TileJson {
+        tilejson: "3.0.0",
+        name: source_name.clone(),
+        scheme: "xyz",
+        tiles: vec![tile_url],
+        minzoom,
+        maxzoom,
+        bounds,
+        vector_layers,
+        geometry_type,
+    })
+    .into_response()
+}
@@ -1,16 +1,21 @@
 use crate::app_state::AppState;
 use crate::auth::auth_middleware;
 use crate::{
-    add_workspace_member, create_project, create_workspace, delete_project, delete_workspace,
-    generate_os_token, get_geometry_type, get_projects, get_workspace, get_workspace_members,
-    get_workspaces, health_check, login, logout, profile, register, remove_workspace_member,
-    reset_password, tiles, upload_layer, upload_layer_v2,
+    add_workspace_member, complete_upload, create_project, create_upload, create_workspace,
+    delete_layer, delete_project, delete_workspace, generate_os_token, get_geometry_type,
+    get_layer, get_project, get_projects, get_upload, get_workspace, get_workspace_members,
+    get_workspaces, health_check, list_layers, login, logout, profile, register,
+    remove_workspace_member, reset_password, tilejson, tiles, update_project, upload_chunk,
+    upload_layer_v2, upload_sidecar,
 };
-use crate::{create_connection, list_connections, list_sources};
+use crate::{
+    create_connection, export_source, get_features, get_job, list_connections, list_sources,
+};
+use crate::{ogc_collection, ogc_collections, ogc_conformance, ogc_items, ogc_landing_page};
 use axum::{
     extract::DefaultBodyLimit,
     middleware,
-    routing::{delete, get, post},
+    routing::{delete, get, post, put},
     Router,
 };
 use http::Method;
@@ -41,7 +46,7 @@ fn create_dynamic_cors() -> CorsLayer {
 
     CorsLayer::new()
         .allow_credentials(true)
-        .allow_methods([Method::GET, Method::OPTIONS, Method::POST])
+        .allow_methods([Method::GET, Method::OPTIONS, Method::POST, Method::PUT])
         .allow_headers([
             AUTHORIZATION,
             ACCEPT,
@@ -52,6 +57,7 @@ fn create_dynamic_cors() -> CorsLayer {
             HeaderName::from_static("x-total-chunks"),
             HeaderName::from_static("x-file-size"),
             HeaderName::from_static("x-checksum"),
+            HeaderName::from_static("x-upload-id"),
         ])
         .expose_headers([
             HeaderName::from_static("x-file-type"),
@@ -60,6 +66,7 @@ fn create_dynamic_cors() -> CorsLayer {
             HeaderName::from_static("x-total-chunks"),
             HeaderName::from_static("x-file-size"),
             HeaderName::from_static("x-checksum"),
+            HeaderName::from_static("x-upload-id"),
         ])
         .allow_origin(origins)
 }
@@ -72,8 +79,8 @@ pub fn create_app(app_state: AppState) -> Router {
 
     let shared_state = Arc::new(app_state);
 
-    let upload_router = Router::new()
-        .route("/upload_layer", post(upload_layer))
+    let upload_router_new = Router::new()
+        .route("/upload_layer_v2", post(upload_layer_v2))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024))
         .layer(create_dynamic_cors())
@@ -83,8 +90,17 @@ pub fn create_app(app_state: AppState) -> Router {
         ))
         .with_state(shared_state.clone());
 
-    let upload_router_new = Router::new()
-        .route("/upload_layer_v2", post(upload_layer_v2))
+    // Resumable uploads: create a session, PUT chunks by index and any shapefile
+    // sidecars, then complete it
+    let upload_session_router = Router::new()
+        .route("/uploads", post(create_upload))
+        .route("/uploads/:upload_id", get(get_upload))
+        .route("/uploads/:upload_id/chunks/:chunk_index", put(upload_chunk))
+        .route(
+            "/uploads/:upload_id/sidecars/:file_name",
+            put(upload_sidecar),
+        )
+        .route("/uploads/:upload_id/complete", post(complete_upload))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024))
         .layer(create_dynamic_cors())
@@ -97,6 +113,10 @@ pub fn create_app(app_state: AppState) -> Router {
     let main_router = Router::new()
         .route("/projects", get(get_projects))
         .route("/projects", delete(delete_project))
+        .route(
+            "/workspaces/:workspace_id/projects/:project_id",
+            get(get_project).put(update_project),
+        )
         .route("/workspaces", get(get_workspaces))
         .route("/logout", post(logout))
         .route("/profile", get(profile))
@@ -122,7 +142,21 @@ pub fn create_app(app_state: AppState) -> Router {
             "/workspaces/:workspace_id/connections/:connection_id/sources",
             get(list_sources),
         )
+        .route(
+            "/workspaces/:workspace_id/connections/:connection_id/sources/:source_name/features",
+            get(get_features),
+        )
+        .route(
+            "/workspaces/:workspace_id/connections/:connection_id/sources/:source_name/export",
+            get(export_source),
+        )
+        .route("/workspaces/:workspace_id/layers", get(list_layers))
+        .route(
+            "/workspaces/:workspace_id/layers/:layer_name",
+            get(get_layer).delete(delete_layer),
+        )
         .route("/create_project", post(create_project))
+        .route("/jobs/:job_id", get(get_job))
         .layer(DefaultBodyLimit::disable())
         .layer(RequestBodyLimitLayer::new(100 * 1024 * 1024))
         .layer(middleware::from_fn_with_state(
@@ -131,6 +165,20 @@ pub fn create_app(app_state: AppState) -> Router {
         ))
         .with_state(shared_state.clone());
 
+    // OGC API - Features for desktop GIS clients, authenticated with the bearer session
+    let ogc_router = Router::new()
+        .route("/", get(ogc_landing_page))
+        .route("/conformance", get(ogc_conformance))
+        .route("/collections", get(ogc_collections))
+        .route("/collections/:collection_id", get(ogc_collection))
+        .route("/collections/:collection_id/items", get(ogc_items))
+        .layer(create_dynamic_cors())
+        .layer(middleware::from_fn_with_state(
+            shared_state.clone(),
+            auth_middleware,
+        ))
+        .with_state(shared_state.clone());
+
     // Create a separate router for public endpoints
     let public_router = Router::new()
         .route("/register", post(register))
@@ -142,8 +190,9 @@ pub fn create_app(app_state: AppState) -> Router {
 
     // Create the tiles router with its specific CORS configuration
     let tiles_router = Router::new()
-        .route("/:z/:x/:y", get(tiles))
-        .route("/geometry", get(get_geometry_type))
+        .route("/tiles/:z/:x/:y", get(tiles))
+        .route("/tiles/geometry", get(get_geometry_type))
+        .route("/tilejson", get(tilejson))
         .layer(create_dynamic_cors())
         .layer(CookieManagerLayer::new())
         .with_state(shared_state.clone());
@@ -151,12 +200,16 @@ pub fn create_app(app_state: AppState) -> Router {
     // Merge all routers and apply global middleware
     Router::new()
         .merge(main_router)
-        .merge(upload_router)
         .merge(upload_router_new)
+        .merge(upload_session_router)
         .nest(
-            "/workspaces/:workspace_id/connections/:connection_id/sources/:source_name/tiles",
+            "/workspaces/:workspace_id/connections/:connection_id/sources/:source_name",
             tiles_router,
         )
+        .nest(
+            "/ogc/workspaces/:workspace_id/connections/:connection_id",
+            ogc_router,
+        )
         .merge(public_router)
         .layer(
             TraceLayer::new_for_http()
@@ -164,3 +217,128 @@ pub fn create_app(app_state: AppState) -> Router {
                 .on_response(trace::DefaultOnResponse::new().level(Level::INFO)),
         )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::InMemoryDatabase;
+    use crate::{GeoConnections, IngestQueue, TileAuthCache, TileCache};
+    use serde_json::{json, Value};
+
+    // Serve the whole app on the in-memory backend and return its base URL
+    async fn serve_app() -> String {
+        let app_data = InMemoryDatabase::new().await.unwrap();
+        let geo_connections = GeoConnections::new();
+        let primary = app_data.get_connection("primary").await.unwrap();
+        geo_connections
+            .add_connection(
+                "primary".to_string(),
+                primary.config.create_connector().unwrap(),
+            )
+            .await;
+        let app_state = AppState {
+            app_data,
+            geo_connections,
+            tile_auth_cache: TileAuthCache::from_env(),
+            tile_cache: TileCache::new(100, None),
+            ingest_queue: IngestQueue::new(),
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, create_app(app_state)).await });
+        format!("http://{}", address)
+    }
+
+    #[tokio::test]
+    async fn register_login_and_fetch_profile_in_memory() {
+        let base = serve_app().await;
+        let client = reqwest::Client::new();
+
+        let health: Value = client
+            .get(format!("{base}/health"))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(health["status"], "healthy");
+
+        let registration = json!({
+            "email": "test@example.com",
+            "password": "correct horse",
+            "first_name": "Test",
+            "last_name": "User",
+        });
+        let register = |body: Value| {
+            let request = client.post(format!("{base}/register")).json(&body);
+            async move { request.send().await.unwrap().text().await.unwrap() }
+        };
+        assert_eq!(
+            register(registration.clone()).await,
+            "registration succeeded"
+        );
+        // Emails are unique
+        assert_eq!(register(registration).await, "registration failed");
+
+        let wrong_password = client
+            .post(format!("{base}/login"))
+            .json(&json!({ "email": "test@example.com", "password": "wrong" }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(wrong_password.status(), 401);
+
+        let login: Value = client
+            .post(format!("{base}/login"))
+            .json(&json!({ "email": "test@example.com", "password": "correct horse" }))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let token = login["apiKey"].as_str().unwrap();
+
+        let anonymous = client.get(format!("{base}/profile")).send().await.unwrap();
+        assert_eq!(anonymous.status(), 401);
+
+        let profile: Value = client
+            .get(format!("{base}/profile"))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(profile["email"], "test@example.com");
+
+        let workspaces: Value = client
+            .get(format!("{base}/workspaces"))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        assert_eq!(workspaces, json!([]));
+
+        let logout = client
+            .post(format!("{base}/logout"))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert!(logout.status().is_success());
+        let after_logout = client
+            .get(format!("{base}/profile"))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(after_logout.status(), 401);
+    }
+}
@@ -110,6 +110,7 @@ pub async fn logout(
     match Session::from_id(&state.app_data, token).await {
         Ok(session) => {
             let _ = session.delete(&state.app_data).await;
+            state.tile_auth_cache.invalidate_session(&session.id).await;
             "logged out".into_response()
         }
         Err(_) => "logged out".into_response(),
@@ -3,6 +3,7 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
+use axum::http::{header, HeaderMap};
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -38,3 +39,20 @@ pub fn verify_password(stored_hash: &str, password_attempt: &str) -> Result<bool
         .verify_password(password_attempt.as_bytes(), &parsed_hash)
         .is_ok())
 }
+
+// Base URL for links back to the API. GW_API_URL is used when set, as the Host
+// header is not the public address when running behind a proxy.
+pub fn api_base_url(headers: &HeaderMap) -> String {
+    if let Ok(url) = std::env::var("GW_API_URL") {
+        return url.trim_end_matches('/').to_string();
+    }
+    let host = headers
+        .get(header::HOST)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("localhost:3001");
+    let scheme = headers
+        .get("x-forwarded-proto")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("http");
+    format!("{}://{}", scheme, host)
+}
@@ -1,8 +1,9 @@
 use crate::auth::AuthUser;
 use crate::{app_state::AppState, utils::get_unix_timestamp};
-use crate::{User, Workspace, WorkspaceRole};
+use crate::{User, Workspace, WorkspaceMember, WorkspaceRole};
 use axum::{
     extract::{Extension, Path, State},
+    http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
@@ -65,7 +66,7 @@ pub async fn create_workspace(
         let wsp = Workspace::from_req(req, owner.clone().id);
         let primary_connection = state
             .geo_connections
-            .get_connection("primary")
+            .get_connection(&state.app_data, "primary")
             .await
             .unwrap();
         match Workspace::create(&state.app_data, &primary_connection, &wsp).await {
@@ -96,7 +97,13 @@ pub async fn delete_workspace(
             if workspace.owner == req_user.id {
                 // Ensure the user is the owner
                 match Workspace::delete(&state.app_data, &workspace_id).await {
-                    Ok(_) => "workspace deleted successfully".into_response(),
+                    Ok(_) => {
+                        state
+                            .tile_auth_cache
+                            .invalidate_workspace(&workspace_id)
+                            .await;
+                        "workspace deleted successfully".into_response()
+                    }
                     Err(_) => "failed to delete workspace".into_response(),
                 }
             } else {
@@ -129,11 +136,18 @@ pub async fn add_workspace_member(
         };
 
         // Add memeber workspace
+        let workspace_id = workspace.id.clone();
         match workspace
             .add_member(&state.app_data, &req_user, &user_to_add, req.role)
             .await
         {
-            Ok(_) => "member added to workspace successfully".into_response(),
+            Ok(_) => {
+                state
+                    .tile_auth_cache
+                    .invalidate_member(&workspace_id, &user_to_add.id)
+                    .await;
+                "member added to workspace successfully".into_response()
+            }
             Err(_) => "failed to add member to workspace".into_response(),
         }
     } else {
@@ -160,8 +174,15 @@ pub async fn remove_workspace_member(
         };
 
         // Remove workspace member
+        let workspace_id = wsp.id.clone();
         match wsp.remove_member(&state.app_data, &req_user, &user).await {
-            Ok(_) => "removed workspace member".into_response(),
+            Ok(_) => {
+                state
+                    .tile_auth_cache
+                    .invalidate_member(&workspace_id, &user.id)
+                    .await;
+                "removed workspace member".into_response()
+            }
             Err(_) => "failed to remove member".into_response(),
         }
     } else {
@@ -301,3 +322,41 @@ pub async fn get_workspace(
         Json(error).into_response()
     }
 }
+
+// Resolve the workspace and the requesting user's membership of it
+pub(crate) async fn get_workspace_membership(
+    state: &Arc<AppState>,
+    auth_user: &AuthUser,
+    workspace_id: &str,
+) -> Result<(Workspace, WorkspaceMember), (StatusCode, Json<serde_json::Value>)> {
+    let user = auth_user.user.as_ref().ok_or_else(|| {
+        let error = json!({
+            "error": "Unauthorized request",
+            "details": null
+        });
+        (StatusCode::UNAUTHORIZED, Json(error))
+    })?;
+
+    let workspace = Workspace::from_id(&state.app_data, workspace_id)
+        .await
+        .map_err(|e| {
+            let error = json!({
+                "error": "Workspace not found",
+                "details": e.to_string()
+            });
+            (StatusCode::NOT_FOUND, Json(error))
+        })?;
+
+    let member = workspace
+        .get_member(&state.app_data, user)
+        .await
+        .map_err(|e| {
+            let error = json!({
+                "error": "Access forbidden",
+                "details": e.to_string()
+            });
+            (StatusCode::FORBIDDEN, Json(error))
+        })?;
+
+    Ok((workspace, member))
+}
@@ -130,8 +130,7 @@ impl Workspace {
         database: &Arc<dyn Database>,
         user: &User,
     ) -> Result<WorkspaceMember> {
-        // TODO: Fix unwrap
-        Ok(database.get_workspace_member(&self, user).await.unwrap())
+        database.get_workspace_member(self, user).await
     }
 
     pub async fn get_members(&self, database: &Arc<dyn Database>) -> Result<Vec<WorkspaceMember>> {
@@ -28,6 +28,8 @@ export const useSingleFileUploader = () => {
         const baseHeaders = await getUploadHeaders();
         const totalChunks = Math.ceil(file.size / CHUNK_SIZE);
         let finalResponse = null;
+        // Returned for the first chunk and sent with the rest
+        let uploadId: string | null = null;
 
         for (let currentChunk = 0; currentChunk < totalChunks; currentChunk++) {
           const start = currentChunk * CHUNK_SIZE;
@@ -50,7 +52,7 @@ export const useSingleFileUploader = () => {
             formData.append("layer_info", JSON.stringify(layerInfo));
           }
 
-          const headers = {
+          const headers: Record<string, string> = {
             Authorization: baseHeaders.Authorization,
             "X-File-Type": "." + file.name.split(".").pop()?.toLowerCase(),
             "X-Workspace-Id": currentWorkspaceId,
@@ -58,6 +60,9 @@ export const useSingleFileUploader = () => {
             "X-Total-Chunks": totalChunks.toString(),
             "X-File-Size": file.size.toString(),
           };
+          if (uploadId) {
+            headers["X-Upload-Id"] = uploadId;
+          }
 
           const response = await fetch(
             `${process.env.NEXT_PUBLIC_GRIDWALK_API}/upload_layer_v2`,
@@ -74,6 +79,7 @@ export const useSingleFileUploader = () => {
           }
 
           const data = await response.json();
+          uploadId = uploadId ?? data.upload_id ?? null;
           onProgress?.(Math.round(((currentChunk + 1) / totalChunks) * 100));
 
           if (currentChunk === totalChunks - 1) {
@@ -141,6 +147,8 @@ export const useShapefileUploader = () => {
         const baseHeaders = await getUploadHeaders();
         const totalChunks = Math.ceil(file.size / CHUNK_SIZE);
         let finalResponse = null;
+        // Returned for the first chunk and sent with the rest
+        let uploadId: string | null = null;
 
         for (let currentChunk = 0; currentChunk < totalChunks; currentChunk++) {
           const start = currentChunk * CHUNK_SIZE;
@@ -163,7 +171,7 @@ export const useShapefileUploader = () => {
             formData.append("layer_info", JSON.stringify(layerInfo));
           }
 
-          const headers = {
+          const headers: Record<string, string> = {
             Authorization: baseHeaders.Authorization,
             "X-File-Type": "shapefile",
             "X-Workspace-Id": currentWorkspaceId,
@@ -171,6 +179,9 @@ export const useShapefileUploader = () => {
             "X-Total-Chunks": totalChunks.toString(),
             "X-File-Size": file.size.toString(),
           };
+          if (uploadId) {
+            headers["X-Upload-Id"] = uploadId;
+          }
 
           const response = await fetch(
             `${process.env.NEXT_PUBLIC_GRIDWALK_API}/upload_layer_v2`,
@@ -187,6 +198,7 @@ export const useShapefileUploader = () => {
           }
 
           const data = await response.json();
+          uploadId = uploadId ?? data.upload_id ?? null;
           onProgress?.(Math.round(((currentChunk + 1) / totalChunks) * 100));
 
           if (currentChunk === totalChunks - 1) {